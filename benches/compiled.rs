@@ -0,0 +1,52 @@
+//! Times evaluating one formula for many values of `y`, from its text each
+//! time and compiled once. Run with `cargo bench`.
+#![allow(clippy::needless_return)]
+
+use std::collections::BTreeMap;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use expresso::Evaluator;
+
+const INPUT: &str = "3 * y ^ 2 + max(y, 2) / (1 + y) - sqrt(16) * (y - 0.5)";
+const RUNS: usize = 100_000;
+
+fn time(mut run: impl FnMut(f64) -> f64) -> Duration {
+    let start = Instant::now();
+    let mut total = 0.0;
+    for i in 0..RUNS {
+        total += run(i as f64);
+    }
+    black_box(total);
+    return start.elapsed();
+}
+
+fn main() {
+    let evaluator = Evaluator::default();
+    let compiled = evaluator.compile(INPUT).unwrap();
+    let mut env = BTreeMap::from([("y".to_string(), 0.0)]);
+
+    let text = time(|y| {
+        env.insert("y".to_string(), y);
+        return evaluator.evaluate_with_env(black_box(INPUT), &env).unwrap();
+    });
+    let once = time(|y| {
+        env.insert("y".to_string(), y);
+        return compiled.eval(&env).unwrap();
+    });
+    let inputs: Vec<String> = (0..RUNS).map(|i| INPUT.replace('y', &i.to_string())).collect();
+    let start = Instant::now();
+    black_box(evaluator.evaluate_batch(inputs.iter().map(String::as_str)));
+    let batch = start.elapsed();
+    let start = Instant::now();
+    for input in &inputs {
+        black_box(evaluator.evaluate(input).unwrap());
+    }
+    let one_at_a_time = start.elapsed();
+
+    println!("{} evaluations of {}", RUNS, INPUT);
+    println!("from text:      {:?}", text);
+    println!("compiled:       {:?} ({:.1}x faster)", once, text.as_secs_f64() / once.as_secs_f64());
+    println!("one at a time:  {:?}", one_at_a_time);
+    println!("evaluate_batch: {:?}", batch);
+}
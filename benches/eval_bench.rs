@@ -0,0 +1,82 @@
+//! Throughput of each stage on a 100-term polynomial in `x`: tokenizing,
+//! converting to RPN, evaluating the RPN and evaluating the infix directly.
+//! Run with `cargo bench --bench eval_bench`.
+#![allow(clippy::needless_return)]
+
+use std::collections::HashMap;
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use expresso::{evaluate_rpn_value, evaluate_rpn_with_vars, infix_to_rpn, sy_evaluate_with_vars, sy_evaulate, Tokenizer};
+
+const TERMS: usize = 100;
+
+/// `"1 + 2 * x + 3 * x ^ 2 + ..."`, with `terms` terms.
+fn polynomial(terms: usize, x: &str) -> String {
+    let mut input = String::from("1");
+    for power in 1..terms {
+        let coefficient = power + 1;
+        if power == 1 {
+            input.push_str(&format!(" + {} * {}", coefficient, x));
+        } else {
+            input.push_str(&format!(" + {} * {} ^ {}", coefficient, x, power));
+        }
+    }
+    return input;
+}
+
+fn tokenize(c: &mut Criterion) {
+    let input = polynomial(TERMS, "x");
+    let mut group = c.benchmark_group("tokenize");
+    group.throughput(Throughput::Bytes(input.len() as u64));
+    // `Tokenizer::new` takes its input by value; the copy isn't timed.
+    group.bench_function("Tokenizer::new", |b| {
+        b.iter_batched(|| input.clone(), |input| Tokenizer::new(black_box(input)).unwrap(), BatchSize::SmallInput)
+    });
+    group.finish();
+}
+
+fn convert(c: &mut Criterion) {
+    let input = polynomial(TERMS, "x");
+    let mut group = c.benchmark_group("convert");
+    group.throughput(Throughput::Bytes(input.len() as u64));
+    group.bench_function("infix_to_rpn", |b| {
+        b.iter_batched(|| input.clone(), |input| infix_to_rpn(black_box(input)).unwrap(), BatchSize::SmallInput)
+    });
+    group.finish();
+}
+
+fn evaluate(c: &mut Criterion) {
+    let variables = HashMap::from([("x".to_string(), 0.5)]);
+    let input = polynomial(TERMS, "x");
+    let rpn = infix_to_rpn(input.clone()).unwrap();
+    // The integer functions have no variables; with `x` as 1 nothing
+    // overflows.
+    let integer_input = polynomial(TERMS, "1");
+    let integer_rpn = infix_to_rpn(integer_input.clone()).unwrap();
+
+    let mut group = c.benchmark_group("evaluate");
+    group.throughput(Throughput::Elements(TERMS as u64));
+    group.bench_function("evaluate_rpn_value", |b| b.iter(|| evaluate_rpn_value(black_box(&integer_rpn)).unwrap()));
+    group.bench_function("evaluate_rpn_with_vars", |b| {
+        b.iter_batched(
+            || rpn.clone(),
+            |rpn| evaluate_rpn_with_vars(black_box(rpn), black_box(&variables)).unwrap(),
+            BatchSize::SmallInput,
+        )
+    });
+    group.bench_function("sy_evaulate", |b| {
+        b.iter_batched(|| integer_input.clone(), |input| sy_evaulate(black_box(input)).unwrap(), BatchSize::SmallInput)
+    });
+    group.bench_function("sy_evaluate_with_vars", |b| {
+        b.iter_batched(
+            || input.clone(),
+            |input| sy_evaluate_with_vars(black_box(input), black_box(&variables)).unwrap(),
+            BatchSize::SmallInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, tokenize, convert, evaluate);
+criterion_main!(benches);
@@ -0,0 +1,3 @@
+//! The `cdylib` `wasm-pack` builds for `index.html`. The bindings
+//! themselves are in expresso, behind its `wasm` feature.
+pub use expresso::{js_evaluate, js_infix_to_rpn};
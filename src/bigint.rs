@@ -0,0 +1,136 @@
+//! Integer mode without a limit on size, for the `bigint` feature.
+
+use alloc::vec::Vec;
+
+use num_bigint::{BigInt, Sign};
+use num_traits::{FromPrimitive, ToPrimitive};
+
+#[cfg(not(any(feature = "std", test)))]
+#[allow(unused_imports)]
+use crate::float::Float;
+
+use crate::operators::default_operators;
+use crate::{
+    check_float, compare, evaluate_rpn_tokens_generic, integer_exponent, ok_tokens, shunting_yard_evaluate,
+    tokenize_infix, tokenize_rpn, Context, EvalError, ExpressionError, Function, Numeric, OperatorFn,
+};
+
+/// The most bits a result may have, about 300,000 decimal digits. Past it
+/// `^` and `<<` are an `Overflow` rather than running out of memory.
+const MAX_BITS: u64 = 1 << 20;
+
+/// Same as `sy_evaulate`, but with integers of any size, so `"2 ^ 200"` is
+/// exact. Division still truncates. Literals have to fit in an `i64`, as
+/// the tokenizer is shared with the other modes.
+pub fn evaluate_big(input: &str) -> Result<BigInt, EvalError> {
+    return shunting_yard_evaluate::<BigInt>(ok_tokens(tokenize_infix(input, default_operators())?), &Context::builtin(), None);
+}
+
+/// Same as `evaluate_rpn`, but with integers of any size, like
+/// `evaluate_big`.
+pub fn evaluate_rpn_big(input: &str) -> Result<BigInt, EvalError> {
+    return evaluate_rpn_tokens_generic::<BigInt>(&tokenize_rpn(input, default_operators())?, &Context::builtin());
+}
+
+fn is_zero(n: &BigInt) -> bool {
+    return n.sign() == Sign::NoSign;
+}
+
+/// `amount` as a shift or exponent, if it is small enough to use at all.
+fn small(amount: &BigInt) -> Result<i64, ExpressionError> {
+    return amount.to_i64().ok_or(ExpressionError::Overflow);
+}
+
+/// Checks that `bits` more bits on top of `n` stay within `MAX_BITS`.
+fn check_growth(n: &BigInt, bits: u64) -> Result<(), ExpressionError> {
+    if n.bits().saturating_add(bits) > MAX_BITS {
+        return Err(ExpressionError::Overflow);
+    }
+    return Ok(());
+}
+
+/// Like `integer_result`, for results of any size.
+fn big_result(result: f64) -> Result<BigInt, ExpressionError> {
+    let result = check_float(result)?;
+    if result.fract() != 0.0 {
+        return Err(ExpressionError::NonIntegerResult);
+    }
+    return BigInt::from_f64(result).ok_or(ExpressionError::Overflow);
+}
+
+fn to_f64(n: &BigInt) -> Result<f64, ExpressionError> {
+    return n.to_f64().filter(|f| f.is_finite()).ok_or(ExpressionError::Overflow);
+}
+
+impl Numeric for BigInt {
+    fn from_integer(n: i64) -> Result<Self, ExpressionError> {
+        Ok(BigInt::from(n))
+    }
+
+    fn from_float(f: f64) -> Result<Self, ExpressionError> {
+        Err(ExpressionError::FloatInIntegerMode(f))
+    }
+
+    fn negate(self: Self) -> Result<Self, ExpressionError> {
+        Ok(-self)
+    }
+
+    fn not(self: Self) -> Result<Self, ExpressionError> {
+        Ok(BigInt::from(is_zero(&self) as i64))
+    }
+
+    /// The same operators as in integer mode, and with the same rounding.
+    fn apply(op: char, left: Self, right: Self) -> Result<Self, ExpressionError> {
+        match op {
+            '+' => return Ok(left + right),
+            '-' => return Ok(left - right),
+            '*' => return Ok(left * right),
+            '/' | '%' if is_zero(&right) => return Err(ExpressionError::DivisionByZero),
+            '/' => return Ok(left / right),
+            '%' => return Ok(left % right),
+            '^' => {
+                let exponent = integer_exponent(small(&right)?)?;
+                // `0`, `1` and `-1` stay that size whatever the power.
+                if left.bits() > 1 {
+                    check_growth(&left, (left.bits() - 1).saturating_mul(exponent as u64))?;
+                }
+                return Ok(left.pow(exponent));
+            },
+            '&' => return Ok(left & right),
+            '|' => return Ok(left | right),
+            '⊕' => return Ok(left ^ right),
+            '«' | '»' => {
+                let amount = small(&right)?;
+                if amount < 0 {
+                    return Err(ExpressionError::InvalidShift(amount));
+                }
+                if op == '»' {
+                    return Ok(left >> amount as u64);
+                }
+                check_growth(&left, amount as u64)?;
+                return Ok(left << amount as u64);
+            },
+            '∧' => return Ok(BigInt::from((!is_zero(&left) && !is_zero(&right)) as i64)),
+            '∨' => return Ok(BigInt::from((!is_zero(&left) || !is_zero(&right)) as i64)),
+            _ => match compare(op, left, right) {
+                Some(result) => return Ok(BigInt::from(result as i64)),
+                None => return Err(ExpressionError::UnexpectedToken(op)),
+            },
+        }
+    }
+
+    /// Functions work in floating point, so their arguments and results
+    /// are only exact up to 2^53.
+    fn call(_name: &str, function: Function, args: &[Self]) -> Result<Self, ExpressionError> {
+        let args = args.iter().map(to_f64).collect::<Result<Vec<f64>, ExpressionError>>()?;
+        return big_result(function.call(&args));
+    }
+
+    fn apply_custom(_op: char, apply: OperatorFn, left: Self, right: Self) -> Result<Self, ExpressionError> {
+        return big_result(apply(to_f64(&left)?, to_f64(&right)?)?);
+    }
+
+    fn as_number(self: &Self) -> Option<f64> {
+        return self.to_f64();
+    }
+}
@@ -0,0 +1,249 @@
+use alloc::string::ToString;
+use core::fmt;
+
+use crate::{ExpressionError, Notation, Numeric, Token, Value};
+
+/// Settings for `evaluate_with_config`: how trigonometry and division
+/// work, and limits for input from people who can't be trusted.
+///
+/// Change the fields to change the defaults:
+///
+/// ```
+/// use expresso::{evaluate_with_config, AngleUnit, EvaluatorConfig, Value};
+///
+/// let config = EvaluatorConfig { angle: AngleUnit::Degrees, ..EvaluatorConfig::default() };
+/// assert_eq!(evaluate_with_config("sin(90)", &config), Ok(Value::Float(1.0)));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EvaluatorConfig {
+    /// What `sin`, `cos` and `tan` take their argument in.
+    pub angle: AngleUnit,
+    /// What `/` gives for two integers.
+    pub division: Division,
+    /// What integer `+`, `-`, `*` and `^` give when the result doesn't fit
+    /// an `i64`.
+    pub overflow: OverflowMode,
+    /// How far apart two numbers may be and still be `==`. Zero, the
+    /// default, means they have to be exactly equal.
+    pub epsilon: f64,
+    /// The largest magnitude a `^` may give.
+    pub max_power: f64,
+    /// The longest input, in bytes.
+    pub max_length: usize,
+    /// How deeply parentheses may nest. A call's parentheses count too.
+    pub max_depth: usize,
+    /// The point in a number such as `3.5`. Make it `,` to read `3,5`, and
+    /// give the arguments another separator, as they can't share one.
+    pub decimal_separator: char,
+    /// What goes between the arguments of a call such as `max(1, 2)`.
+    pub argument_separator: char,
+    /// Whether full-width digits, as in `１２３`, are read like ASCII ones.
+    pub fullwidth_digits: bool,
+    /// What a variable the environment doesn't have is.
+    pub missing_variables: MissingVarPolicy,
+}
+
+/// Radians, integer division promoted to float, overflow as an error, no
+/// `==` tolerance, `^` up to `1e308`, and inputs of up to 4096 bytes
+/// nested up to 64 deep, written with `.`, `,` and ASCII digits, in which
+/// a missing variable is an error.
+impl Default for EvaluatorConfig {
+    fn default() -> Self {
+        EvaluatorConfig {
+            angle: AngleUnit::Radians,
+            division: Division::Promote,
+            overflow: OverflowMode::Checked,
+            epsilon: 0.0,
+            max_power: 1e308,
+            max_length: 4096,
+            max_depth: 64,
+            decimal_separator: '.',
+            argument_separator: ',',
+            fullwidth_digits: false,
+            missing_variables: MissingVarPolicy::Error,
+        }
+    }
+}
+
+/// The unit of the argument of the trigonometric functions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AngleUnit {
+    Radians,
+    Degrees,
+}
+
+/// What dividing one integer by another gives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Division {
+    /// A float, so `1 / 2` is `0.5`.
+    Promote,
+    /// An integer rounded towards zero, so `1 / 2` is `0`, as `evaluate`
+    /// does.
+    Truncate,
+}
+
+/// What a variable that can't be looked up is, for data with fields that
+/// may be missing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MissingVarPolicy {
+    /// An `UndefinedVariable` error.
+    Error,
+    /// The value given, as if the environment had it, so with
+    /// `Default(Value::Int(0))` a missing `x` in `"x + 1"` gives `1`.
+    Default(Value),
+    /// `Value::Null`, which every operator and function passes on, so the
+    /// whole expression is null unless `coalesce` or `is_null` catches it.
+    Propagate,
+}
+
+/// What integer arithmetic does past the range of an `i64`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowMode {
+    /// An `IntegerOverflow` error saying which operation overflowed.
+    Checked,
+    /// Wraps around as two's complement does, so `i64::MAX + 1` is
+    /// `i64::MIN`.
+    Wrapping,
+    /// Stops at `i64::MAX` or `i64::MIN`, whichever is nearer.
+    Saturating,
+}
+
+impl OverflowMode {
+    /// Applies `+`, `-`, `*` or `^` to two integers. A negative power is
+    /// a `NegativeExponent` error whatever the mode, as it isn't an integer
+    /// at all; a huge one is fine, and only overflows like any other.
+    fn apply(self: Self, op: char, left: i64, right: i64) -> Result<i64, ExpressionError> {
+        let result = match op {
+            '+' => self.pick(left.checked_add(right), left.wrapping_add(right), left.saturating_add(right)),
+            '-' => self.pick(left.checked_sub(right), left.wrapping_sub(right), left.saturating_sub(right)),
+            '*' => self.multiply(left, right),
+            '^' if right < 0 => return Err(ExpressionError::NegativeExponent),
+            '^' => self.pow(left, right as u64),
+            _ => return Err(ExpressionError::UnexpectedToken(op)),
+        };
+        return result.ok_or_else(|| ExpressionError::IntegerOverflow {
+            op: Token::from_operator_symbol(op).to_string(),
+            left,
+            right,
+        });
+    }
+
+    fn pick(self: Self, checked: Option<i64>, wrapping: i64, saturating: i64) -> Option<i64> {
+        match self {
+            OverflowMode::Checked => return checked,
+            OverflowMode::Wrapping => return Some(wrapping),
+            OverflowMode::Saturating => return Some(saturating),
+        }
+    }
+
+    fn multiply(self: Self, left: i64, right: i64) -> Option<i64> {
+        return self.pick(left.checked_mul(right), left.wrapping_mul(right), left.saturating_mul(right));
+    }
+
+    /// Squares and multiplies, so any `u64` exponent takes at most 64
+    /// steps. Saturated steps stay saturated with the right sign, as every
+    /// factor after one is at least 2 in size.
+    fn pow(self: Self, mut base: i64, mut exponent: u64) -> Option<i64> {
+        let mut result: i64 = 1;
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = self.multiply(result, base)?;
+            }
+            exponent >>= 1;
+            if exponent > 0 {
+                base = self.multiply(base, base)?;
+            }
+        }
+        return Some(result);
+    }
+}
+
+/// Which of the limits in an `EvaluatorConfig` an expression went past.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Limit {
+    /// `max_length`
+    Length,
+    /// `max_depth`
+    Depth,
+    /// `max_power`
+    Power,
+}
+
+impl fmt::Display for Limit {
+    fn fmt(self: &Self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Limit::Length => write!(f, "expression length"),
+            Limit::Depth => write!(f, "nesting depth"),
+            Limit::Power => write!(f, "power"),
+        }
+    }
+}
+
+impl EvaluatorConfig {
+    /// Checks `input` against `max_length` before it is tokenized.
+    pub(crate) fn check_length(self: &Self, input: &str) -> Result<(), ExpressionError> {
+        if input.len() > self.max_length {
+            return Err(ExpressionError::LimitExceeded { which: Limit::Length });
+        }
+        return Ok(());
+    }
+
+    /// How the tokenizer should read numbers and argument lists, or a
+    /// `SeparatorConflict` if the two separators are the same.
+    pub(crate) fn notation(self: &Self) -> Result<Notation, ExpressionError> {
+        if self.decimal_separator == self.argument_separator {
+            return Err(ExpressionError::SeparatorConflict(self.decimal_separator));
+        }
+        return Ok(Notation {
+            decimal_separator: self.decimal_separator,
+            argument_separator: self.argument_separator,
+            fullwidth_digits: self.fullwidth_digits,
+            math_notation: false,
+            superscripts: false,
+        });
+    }
+
+    /// Checks that the parentheses in infix `tokens` nest no deeper than
+    /// `max_depth`.
+    pub(crate) fn check_depth(self: &Self, tokens: &[Token]) -> Result<(), ExpressionError> {
+        let mut depth: usize = 0;
+        for token in tokens {
+            match token {
+                Token::ParenLeft => depth += 1,
+                Token::ParenRight => depth = depth.saturating_sub(1),
+                _ => continue,
+            }
+            if depth > self.max_depth {
+                return Err(ExpressionError::LimitExceeded { which: Limit::Depth });
+            }
+        }
+        return Ok(());
+    }
+
+    /// Applies the built-in binary operator `op` with these settings.
+    pub(crate) fn apply<N: Numeric>(self: &Self, op: char, left: N, right: N) -> Result<N, ExpressionError> {
+        let result = match (op, left.as_integer(), right.as_integer()) {
+            ('+' | '-' | '*' | '^', Some(l), Some(r)) => N::from_integer(self.overflow.apply(op, l, r)?)?,
+            _ => self.apply_number(op, left, right)?,
+        };
+        if op == '^' && result.as_number().is_some_and(|n| n.abs() > self.max_power) {
+            return Err(ExpressionError::LimitExceeded { which: Limit::Power });
+        }
+        return Ok(result);
+    }
+
+    fn apply_number<N: Numeric>(self: &Self, op: char, left: N, right: N) -> Result<N, ExpressionError> {
+        match (op, left.as_number(), right.as_number()) {
+            ('/', Some(_), Some(_)) if self.division == Division::Promote => {
+                return N::apply_custom(op, |l, r| f64::apply('/', l, r), left, right);
+            },
+            ('=' | '≠', Some(l), Some(r)) if self.epsilon > 0.0 => {
+                return N::from_bool(((l - r).abs() <= self.epsilon) == (op == '='));
+            },
+            _ => {},
+        }
+
+        return N::apply(op, left, right);
+    }
+}
@@ -0,0 +1,73 @@
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use core::f64::consts;
+#[cfg(any(feature = "std", test))]
+use std::collections::HashMap;
+
+/// The constants every evaluator knows. They take priority over variables
+/// of the same name.
+pub static BUILTIN_CONSTANTS: &[(&str, f64)] = &[
+    ("pi", consts::PI),
+    ("PI", consts::PI),
+    ("e", consts::E),
+    ("tau", consts::TAU),
+    // The golden ratio, (1 + sqrt(5)) / 2.
+    ("phi", 1.618033988749895),
+];
+
+/// Whether `name` is a built-in constant.
+pub(crate) fn is_constant(name: &str) -> bool {
+    return BUILTIN_CONSTANTS.iter().any(|(constant, _)| *constant == name);
+}
+
+/// The value of `name` if it is a built-in constant, else of the variable
+/// `name` in `variables`.
+pub(crate) fn lookup(variables: &dyn Environment, name: &str) -> Option<f64> {
+    match BUILTIN_CONSTANTS.iter().find(|(constant, _)| *constant == name) {
+        Some((_, value)) => Some(*value),
+        None => variables.lookup(name),
+    }
+}
+
+/// Where evaluators look up the values of variables. Implemented for
+/// `HashMap`s and `BTreeMap`s from names to numbers; implement it to plug
+/// in any other lookup.
+pub trait Environment {
+    /// The value of `name`, or `None` if it isn't defined.
+    fn lookup(self: &Self, name: &str) -> Option<f64>;
+}
+
+#[cfg(any(feature = "std", test))]
+impl Environment for HashMap<String, f64> {
+    fn lookup(self: &Self, name: &str) -> Option<f64> {
+        self.get(name).copied()
+    }
+}
+
+#[cfg(any(feature = "std", test))]
+impl Environment for HashMap<String, i64> {
+    fn lookup(self: &Self, name: &str) -> Option<f64> {
+        self.get(name).map(|value| *value as f64)
+    }
+}
+
+impl Environment for BTreeMap<String, f64> {
+    fn lookup(self: &Self, name: &str) -> Option<f64> {
+        self.get(name).copied()
+    }
+}
+
+impl Environment for BTreeMap<String, i64> {
+    fn lookup(self: &Self, name: &str) -> Option<f64> {
+        self.get(name).map(|value| *value as f64)
+    }
+}
+
+/// An environment with no variables at all.
+pub(crate) struct NoVariables;
+
+impl Environment for NoVariables {
+    fn lookup(self: &Self, _name: &str) -> Option<f64> {
+        None
+    }
+}
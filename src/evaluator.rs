@@ -0,0 +1,364 @@
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use crate::{
+    evaluate_postfix, evaluate_rpn_tokens_generic, ok_tokens, shunting_yard, shunting_yard_evaluate,
+    shunting_yard_evaluate_in, shunting_yard_to_rpn, tokenize_infix_lossy, tokenize_infix_with, tokenize_rpn,
+    Associativity, Context, Environment, EvalBuffers, ExpressionError, Function, Functions, NoVariables, Notation,
+    OperatorFn, OperatorInfo, OperatorTable, ParseError, ParseErrorKind, SkippedCharacter, Stack, Token,
+};
+
+/// Evaluates expressions with a configurable set of operators and
+/// functions. `Evaluator::default()` knows the same ones as `sy_evaluate_f64`:
+///
+/// ```
+/// use expresso::{Associativity, Evaluator};
+///
+/// let evaluator = Evaluator::default()
+///     .with_operator('@', 9, Associativity::Left, |a, b| Ok((a + b) / 2.0))
+///     .unwrap();
+/// assert_eq!(evaluator.evaluate("1 + 2 @ 4").unwrap(), 4.0);
+/// ```
+///
+/// Everything evaluates in floating point.
+///
+/// `with_implicit_multiplication` lets infix input leave out `*` the way
+/// handwritten math does, as in `2(3 + 4)`, and `with_math_notation` lets
+/// it use `√x` and `|x|`. For scraped input with stray characters in it,
+/// `evaluate_lossy` skips them and says which it did.
+///
+/// To evaluate one formula many times, `compile` it once. For many
+/// formulas, `evaluate_batch` saves setting up for each one.
+///
+/// For a REPL, `feed` takes input a line at a time, so an expression can
+/// continue over several lines, and remembers the last result as `ans`.
+#[derive(Debug, Clone, Default)]
+pub struct Evaluator {
+    operators: OperatorTable,
+    functions: Functions,
+    /// The lines of an expression `feed` hasn't seen the end of yet.
+    pending: String,
+    answer: Option<f64>,
+    implicit_multiplication: bool,
+    math_notation: bool,
+    superscripts: bool,
+    capacity_hint: usize,
+}
+
+impl Evaluator {
+    /// Adds a binary operator, replacing any operator with the same symbol.
+    /// Digits, letters, `_`, whitespace, parentheses, `.`, `,`, the prefix
+    /// operators `~` and `!`, and `×`, `÷` and `−`, which always mean `*`,
+    /// `/` and `-`, can't be operators; they are an `InvalidOperator`. Nor
+    /// can `|` with `with_math_notation`.
+    pub fn with_operator(
+        mut self: Self,
+        symbol: char,
+        precedence: u8,
+        associativity: Associativity,
+        apply: OperatorFn,
+    ) -> Result<Self, ExpressionError> {
+        OperatorTable::check_symbol(symbol)?;
+        if symbol == '|' && self.math_notation {
+            return Err(ExpressionError::InvalidOperator(symbol));
+        }
+        self.operators.insert(OperatorInfo { symbol, precedence, associativity, apply: Some(apply) });
+        return Ok(self);
+    }
+
+    /// Adds a function, replacing any function with the same name.
+    pub fn with_function(mut self: Self, name: &str, function: Function) -> Self {
+        self.functions.insert(name, function);
+        return self;
+    }
+
+    /// Whether an operand followed by another one with no operator between
+    /// them is multiplied by it, so `"2(3 + 4)"` is 14 and `"1/2(3)"` is the
+    /// same as `"1/2*3"`. Off by default, when those are `MissingOperator`
+    /// errors.
+    pub fn with_implicit_multiplication(mut self: Self, enabled: bool) -> Self {
+        self.implicit_multiplication = enabled;
+        return self;
+    }
+
+    /// Whether infix input can write `sqrt(x)` as `√x` and `abs(x)` as
+    /// `|x|`, the way documents do, so `"√(x^2 + y^2)"` and `"|x - y|"`
+    /// work. Off by default, when `√` is an unexpected character.
+    ///
+    /// A `|` where an operand is expected opens a bar, and one where an
+    /// operator is expected closes it, so `"||x| - 1|"` is
+    /// `abs(abs(x) - 1)` and `"||x||"` is `abs(abs(x))`. A `||` inside bars
+    /// closes them, so an or there needs parentheses: `"|(a || b)|"`. Even
+    /// with implicit multiplication, `"3|x|"` has to be `"3 * |x|"`. `√`
+    /// takes the operand right after it, so `"√4^2"` is `sqrt(4) ^ 2`.
+    ///
+    /// A `|` operator added with `with_operator` would be read as a bar, so
+    /// with one this is an `InvalidOperator`.
+    pub fn with_math_notation(mut self: Self, enabled: bool) -> Result<Self, ExpressionError> {
+        if enabled && self.operators.get('|').is_some_and(|info| info.apply.is_some()) {
+            return Err(ExpressionError::InvalidOperator('|'));
+        }
+        self.math_notation = enabled;
+        return Ok(self);
+    }
+
+    /// Whether a run of superscript digits after an operand raises it to
+    /// that power, as in `"x² + y²"` and `"10³"`, with a `⁻` before them
+    /// for a negative one, as in `"2⁻¹"`. It is the same as writing `^`
+    /// and the exponent, so `"2^3²"` is `2 ^ 3 ^ 2`. Off by default, when
+    /// superscripts are unexpected characters.
+    pub fn with_superscripts(mut self: Self, enabled: bool) -> Self {
+        self.superscripts = enabled;
+        return self;
+    }
+
+    /// Sizes the buffers the evaluator keeps from one expression to the
+    /// next, in `evaluate_batch` and `feed`, for expressions of up to
+    /// `bytes` bytes, so they never have to grow for them. Each
+    /// expression's own buffers are sized from its length either way, so
+    /// evaluating one allocates about as often however long it is.
+    pub fn with_capacity_hint(mut self: Self, bytes: usize) -> Self {
+        self.capacity_hint = bytes;
+        self.pending.reserve(bytes);
+        return self;
+    }
+
+    pub fn operators(self: &Self) -> &OperatorTable {
+        return &self.operators;
+    }
+
+    pub fn functions(self: &Self) -> &Functions {
+        return &self.functions;
+    }
+
+    /// Evaluates an infix expression.
+    pub fn evaluate(self: &Self, input: &str) -> Result<f64, ExpressionError> {
+        return self.evaluate_with_env(input, &NoVariables);
+    }
+
+    /// Evaluates an infix expression, looking its variables up in `env`.
+    pub fn evaluate_with_env(self: &Self, input: &str, env: &dyn Environment) -> Result<f64, ExpressionError> {
+        let context = self.context(env);
+        return shunting_yard_evaluate::<f64>(ok_tokens(self.tokenize(input)?), &context, None);
+    }
+
+    /// Same as `evaluate_with_env`, but characters that can't be read at
+    /// all, such as the zero-width space in `"1 + 2 \u{200B} * 3"`, are
+    /// skipped rather than an error. Returns the value with what was
+    /// skipped, for checking. See `Tokenizer::tokenize_lossy`.
+    pub fn evaluate_lossy(
+        self: &Self,
+        input: &str,
+        env: &dyn Environment,
+    ) -> Result<(f64, Vec<SkippedCharacter>), ExpressionError> {
+        let context = self.context(env);
+        let implicit = self.implicit_multiplication;
+        let (tokens, skipped) = tokenize_infix_lossy(input, &self.operators, implicit, self.notation())?;
+        return Ok((shunting_yard_evaluate::<f64>(ok_tokens(tokens), &context, None)?, skipped));
+    }
+
+    /// Evaluates each of `inputs`, like `evaluate` but sharing the stacks
+    /// the evaluation works in. The results are in the same order.
+    pub fn evaluate_batch<'a>(self: &Self, inputs: impl IntoIterator<Item = &'a str>) -> Vec<Result<f64, ExpressionError>> {
+        let context = self.context(&NoVariables);
+        let mut buffers = EvalBuffers::with_capacity(self.capacity_hint);
+        return inputs
+            .into_iter()
+            .map(|input| shunting_yard_evaluate_in::<f64>(ok_tokens(self.tokenize(input)?), &context, None, &mut buffers))
+            .collect();
+    }
+
+    /// Parses an infix expression once, for evaluating many times with
+    /// `CompiledExpr::eval`. Besides syntax errors, an unknown function or
+    /// a call with the wrong number of arguments is an error here rather
+    /// than when evaluating.
+    pub fn compile(self: &Self, input: &str) -> Result<CompiledExpr<'_>, ExpressionError> {
+        let tokens = self.tokenize(input)?;
+        check_calls(&tokens, &self.functions)?;
+        let postfix = shunting_yard::<f64>(&tokens, &self.operators)?;
+        return Ok(CompiledExpr { postfix, evaluator: self });
+    }
+
+    /// Converts an infix expression to RPN.
+    pub fn infix_to_rpn(self: &Self, input: &str) -> Result<String, ExpressionError> {
+        return shunting_yard_to_rpn::<f64>(&self.tokenize(input)?, &self.operators, input.len());
+    }
+
+    /// Evaluates an RPN expression.
+    pub fn evaluate_rpn(self: &Self, input: &str) -> Result<f64, ExpressionError> {
+        let context = self.context(&NoVariables);
+        return evaluate_rpn_tokens_generic::<f64>(&tokenize_rpn(input, &self.operators)?, &context);
+    }
+
+    /// Adds `line` to the expression being entered. Returns its value once
+    /// it is complete, or `None` while it still needs more input, such as
+    /// after `"(1 + 2 *"`. The variables `ans` and `_` are the last value
+    /// returned.
+    ///
+    /// A line ending with `\` continues onto the next whatever comes
+    /// before it, so `"1 \"` and `"+ 2"` are `1 + 2` where `"1"` alone would
+    /// be done. `#` starts a comment that runs to the end of the line,
+    /// unless it is one of the evaluator's operators, and the `\` can come
+    /// before one.
+    ///
+    /// Errors abandon the expression, so the next line starts a new one.
+    /// Positions in them count from the start of the expression's first
+    /// line, with lines joined by `\n`. Comments and `\`s are blanked out
+    /// rather than removed, so positions count them too.
+    pub fn feed(self: &mut Self, line: &str) -> Result<Option<f64>, ExpressionError> {
+        let mut line = line.to_string();
+        if self.operators.get('#').is_none() {
+            if let Some(start) = line.find('#') {
+                let blank = " ".repeat(line.len() - start);
+                line.replace_range(start.., &blank);
+            }
+        }
+        let backslash = line.trim_end().strip_suffix('\\').map(str::len);
+        if let Some(at) = backslash {
+            line.replace_range(at..at + 1, " ");
+        }
+
+        if !self.pending.is_empty() {
+            self.pending.push('\n');
+        }
+        self.pending.push_str(&line);
+        if backslash.is_some() {
+            return Ok(None);
+        }
+        if self.pending.trim().is_empty() {
+            self.pending.clear();
+            return Ok(None);
+        }
+
+        let result = self.evaluate_with_env(&self.pending, &Answer(self.answer));
+        if let Err(ExpressionError::Parse(error)) = result {
+            if self.continues(error) {
+                return Ok(None);
+            }
+        }
+        return self.answered(result);
+    }
+
+    /// Evaluates the expression `feed` is in the middle of as it stands,
+    /// for when the input ends, so `"1 +"` is an error here. `None` if there
+    /// is no such expression.
+    pub fn finish(self: &mut Self) -> Result<Option<f64>, ExpressionError> {
+        if self.pending.is_empty() {
+            return Ok(None);
+        }
+        let result = self.evaluate_with_env(&self.pending, &Answer(self.answer));
+        return self.answered(result);
+    }
+
+    /// Ends the pending expression with `result`, its value or error.
+    fn answered(self: &mut Self, result: Result<f64, ExpressionError>) -> Result<Option<f64>, ExpressionError> {
+        self.pending.clear();
+        let value = result?;
+        self.answer = Some(value);
+        return Ok(Some(value));
+    }
+
+    /// Abandons the expression `feed` is in the middle of, if any.
+    pub fn reset(self: &mut Self) {
+        self.pending.clear();
+    }
+
+    /// Whether `feed` is in the middle of an expression.
+    pub fn is_pending(self: &Self) -> bool {
+        return !self.pending.is_empty();
+    }
+
+    /// The last value `feed` returned.
+    pub fn answer(self: &Self) -> Option<f64> {
+        return self.answer;
+    }
+
+    /// Whether `error` in the pending input is only that it stops early:
+    /// it ends expecting an operand, or a `(` is still open.
+    fn continues(self: &Self, error: ParseError) -> bool {
+        match error.kind {
+            ParseErrorKind::TrailingOperator { .. } => return true,
+            ParseErrorKind::MissingOperand => return error.position == self.pending.len(),
+            ParseErrorKind::UnbalancedParenthesis => return self.pending[error.position..].starts_with('('),
+            _ => return false,
+        }
+    }
+
+    fn tokenize(self: &Self, input: &str) -> Result<Vec<Token>, ExpressionError> {
+        return tokenize_infix_with(input, &self.operators, self.implicit_multiplication, self.notation());
+    }
+
+    fn notation(self: &Self) -> Notation {
+        return Notation { math_notation: self.math_notation, superscripts: self.superscripts, ..Notation::ASCII };
+    }
+
+    fn context<'a>(self: &'a Self, variables: &'a dyn Environment) -> Context<'a> {
+        return Context { variables, functions: &self.functions, operators: &self.operators, config: None };
+    }
+}
+
+/// An expression `Evaluator::compile` has turned into postfix, so it can
+/// be evaluated without tokenizing it again.
+#[derive(Debug, Clone)]
+pub struct CompiledExpr<'a> {
+    postfix: Vec<Token>,
+    evaluator: &'a Evaluator,
+}
+
+impl CompiledExpr<'_> {
+    /// Evaluates the expression, looking its variables up in `env`. The
+    /// result is the same as `Evaluator::evaluate_with_env` gives.
+    pub fn eval(self: &Self, env: &dyn Environment) -> Result<f64, ExpressionError> {
+        let context = self.evaluator.context(env);
+        return evaluate_postfix::<f64>(&self.postfix, &context, false, &mut Stack::new());
+    }
+}
+
+/// Checks that every function in infix `tokens` exists and is given as
+/// many arguments as it takes, which postfix no longer says.
+pub(crate) fn check_calls(tokens: &[Token], functions: &Functions) -> Result<(), ExpressionError> {
+    // For each open `(`, the call it belongs to and its arguments so far.
+    let mut open: Vec<Option<(&str, usize)>> = Vec::new();
+    let mut name: Option<&str> = None;
+    let mut previous: Option<&Token> = None;
+
+    for token in tokens {
+        match token {
+            Token::Function(called) => name = Some(called),
+            Token::ParenLeft => open.push(name.take().map(|name| (name, 1))),
+            Token::Comma => match open.last_mut() {
+                Some(Some((_, args))) => *args += 1,
+                _ => return Err(ExpressionError::UnexpectedToken(',')),
+            },
+            Token::ParenRight => {
+                let Some(Some((name, args))) = open.pop() else { continue; };
+                let args = if previous == Some(&Token::ParenLeft) { 0 } else { args };
+                let Some(function) = functions.get(name) else {
+                    return Err(ExpressionError::UnknownFunction(name.to_string()));
+                };
+                if args != function.arity() {
+                    return Err(ExpressionError::WrongArity {
+                        name: name.to_string(),
+                        expected: function.arity(),
+                        got: args,
+                    });
+                }
+            },
+            _ => {},
+        }
+        previous = Some(token);
+    }
+    return Ok(());
+}
+
+/// The variables `feed` evaluates with.
+struct Answer(Option<f64>);
+
+impl Environment for Answer {
+    fn lookup(self: &Self, name: &str) -> Option<f64> {
+        match name {
+            "ans" | "_" => return self.0,
+            _ => return None,
+        }
+    }
+}
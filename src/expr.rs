@@ -0,0 +1,1587 @@
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use core::mem;
+use core::str::FromStr;
+#[cfg(any(feature = "std", test))]
+use std::collections::{HashMap, HashSet};
+
+#[cfg(not(any(feature = "std", test)))]
+#[allow(unused_imports)]
+use crate::float::Float;
+
+use crate::environment::{self, NoVariables};
+use crate::functions::default_functions;
+use crate::operators::default_operators;
+use crate::{
+    call_function, missing_operator_hint, tokenize_infix_spanned, tokenize_parse_error, tokenize_rpn_spanned, Associativity,
+    Environment, ExpressionError, Functions, Numeric, ParseError, ParseErrorKind, Span, SpannedToken, Token, NOT,
+    UNARY_MINUS,
+};
+
+/// A binary operator in an `Expr` tree.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BinOpKind {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Pow,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    Eq,
+    Ne,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
+    And,
+    Or,
+}
+
+impl BinOpKind {
+    /// The operator's symbol on the operator stack. It is also how the
+    /// operator is written, apart from `<=`, `>=`, `==`, `!=`, `xor`, `<<`,
+    /// `>>`, `&&` and `||`, which are `≤`, `≥`, `=`, `≠`, `⊕`, `«`, `»`, `∧`
+    /// and `∨`; `Display` prints those as written.
+    pub fn symbol(self: &Self) -> char {
+        match self {
+            BinOpKind::Add => '+',
+            BinOpKind::Sub => '-',
+            BinOpKind::Mul => '*',
+            BinOpKind::Div => '/',
+            BinOpKind::Rem => '%',
+            BinOpKind::Pow => '^',
+            BinOpKind::Lt => '<',
+            BinOpKind::Gt => '>',
+            BinOpKind::Le => '≤',
+            BinOpKind::Ge => '≥',
+            BinOpKind::Eq => '=',
+            BinOpKind::Ne => '≠',
+            BinOpKind::BitAnd => '&',
+            BinOpKind::BitOr => '|',
+            BinOpKind::BitXor => '⊕',
+            BinOpKind::Shl => '«',
+            BinOpKind::Shr => '»',
+            BinOpKind::And => '∧',
+            BinOpKind::Or => '∨',
+        }
+    }
+
+    fn from_symbol(symbol: char) -> Option<Self> {
+        match symbol {
+            '+' => Some(BinOpKind::Add),
+            '-' => Some(BinOpKind::Sub),
+            '*' => Some(BinOpKind::Mul),
+            '/' => Some(BinOpKind::Div),
+            '%' => Some(BinOpKind::Rem),
+            '^' => Some(BinOpKind::Pow),
+            '<' => Some(BinOpKind::Lt),
+            '>' => Some(BinOpKind::Gt),
+            '≤' => Some(BinOpKind::Le),
+            '≥' => Some(BinOpKind::Ge),
+            '=' => Some(BinOpKind::Eq),
+            '≠' => Some(BinOpKind::Ne),
+            '&' => Some(BinOpKind::BitAnd),
+            '|' => Some(BinOpKind::BitOr),
+            '⊕' => Some(BinOpKind::BitXor),
+            '«' => Some(BinOpKind::Shl),
+            '»' => Some(BinOpKind::Shr),
+            '∧' => Some(BinOpKind::And),
+            '∨' => Some(BinOpKind::Or),
+            _ => None,
+        }
+    }
+
+    fn precedence(self: &Self) -> u8 {
+        return default_operators().get_precedence(self.symbol()).expect("binary operators have a precedence");
+    }
+
+    fn is_left_associative(self: &Self) -> bool {
+        let associativity = default_operators().get_associativity(self.symbol()).expect("binary operators have an associativity");
+        return associativity == Associativity::Left;
+    }
+
+    fn is_comparison(self: &Self) -> bool {
+        use BinOpKind::*;
+
+        return matches!(self, Lt | Gt | Le | Ge | Eq | Ne);
+    }
+
+    /// Whether swapping the operands gives the same value.
+    fn is_commutative(self: &Self) -> bool {
+        return matches!(
+            self,
+            BinOpKind::Add
+                | BinOpKind::Mul
+                | BinOpKind::Eq
+                | BinOpKind::Ne
+                | BinOpKind::BitAnd
+                | BinOpKind::BitOr
+                | BinOpKind::BitXor
+                | BinOpKind::And
+                | BinOpKind::Or
+        );
+    }
+}
+
+impl fmt::Display for BinOpKind {
+    fn fmt(self: &Self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", Token::from_operator_symbol(self.symbol()))
+    }
+}
+
+/// A parsed expression tree. Build one with `parse`, then evaluate it as
+/// many times as needed with `Expr::eval`.
+///
+/// `Clone`, `PartialEq`, `Debug` and `Drop` go a node at a time rather than
+/// recursing, so they work on trees of any depth, such as ones built with
+/// `ExprBuilder` that the parser's nesting limit never saw.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Expr {
+    /// An integer literal, kept exactly as written.
+    Int(i64),
+    Num(f64),
+    /// `true` or `false`, which evaluate to 1 and 0.
+    Bool(bool),
+    Var(String),
+    BinOp {
+        op: BinOpKind,
+        left: Box<Expr>,
+        right: Box<Expr>,
+    },
+    UnaryMinus(Box<Expr>),
+    /// Logical not, `!x`: 1 if `x` is zero and 0 otherwise.
+    Not(Box<Expr>),
+    /// A function call, e.g. `max(x, 2)`.
+    Call {
+        name: String,
+        args: Vec<Expr>,
+    },
+}
+
+impl Expr {
+    /// Evaluates the tree in floating point, with the same rules as
+    /// `sy_evaluate_f64`. Any variable other than a built-in constant is
+    /// an `UndefinedVariable` error.
+    pub fn eval(self: &Self) -> Result<f64, ExpressionError> {
+        return self.eval_with(&NoVariables, default_functions());
+    }
+
+    /// Evaluates the tree, looking variables up in `variables`.
+    #[cfg(any(feature = "std", test))]
+    pub fn eval_with_vars(self: &Self, variables: &HashMap<String, f64>) -> Result<f64, ExpressionError> {
+        return self.eval_with(variables, default_functions());
+    }
+
+    /// Evaluates the tree, looking variables up in `variables` and calling
+    /// functions from `functions` instead of the built-ins.
+    pub fn eval_with(self: &Self, variables: &dyn Environment, functions: &Functions) -> Result<f64, ExpressionError> {
+        match self {
+            Expr::Int(n) => Ok(*n as f64),
+            Expr::Num(n) => Ok(*n),
+            Expr::Bool(b) => f64::from_bool(*b),
+            Expr::Var(name) => environment::lookup(variables, name)
+                .ok_or_else(|| ExpressionError::UndefinedVariable(name.clone())),
+            Expr::BinOp { op, left, right } => {
+                f64::apply(op.symbol(), left.eval_with(variables, functions)?, right.eval_with(variables, functions)?)
+            },
+            Expr::UnaryMinus(operand) => operand.eval_with(variables, functions)?.negate(),
+            Expr::Not(operand) => operand.eval_with(variables, functions)?.not(),
+            Expr::Call { name, args } => {
+                let mut values: Vec<f64> = Vec::new();
+                for arg in args {
+                    values.push(arg.eval_with(variables, functions)?);
+                }
+                call_function(functions, name, &values)
+            },
+        }
+    }
+
+    /// Same as `==`, but the operands of commutative operators such as `+`
+    /// and `*` may be either way round, at any depth, so `x + 1` equals
+    /// `1 + x`. Operators like `-`, `/` and `^` still need them in order.
+    /// Only direct swaps count: `(a + b) + c` doesn't equal `a + (b + c)`.
+    pub fn semantically_eq(self: &Self, other: &Expr) -> bool {
+        match (self, other) {
+            (
+                Expr::BinOp { op, left, right },
+                Expr::BinOp { op: other_op, left: other_left, right: other_right },
+            ) => {
+                if op != other_op {
+                    return false;
+                }
+                if left.semantically_eq(other_left) && right.semantically_eq(other_right) {
+                    return true;
+                }
+                return op.is_commutative() && left.semantically_eq(other_right) && right.semantically_eq(other_left);
+            },
+            (Expr::UnaryMinus(operand), Expr::UnaryMinus(other_operand)) => return operand.semantically_eq(other_operand),
+            (Expr::Not(operand), Expr::Not(other_operand)) => return operand.semantically_eq(other_operand),
+            (Expr::Call { name, args }, Expr::Call { name: other_name, args: other_args }) => {
+                return name == other_name
+                    && args.len() == other_args.len()
+                    && args.iter().zip(other_args).all(|(arg, other_arg)| arg.semantically_eq(other_arg));
+            },
+            _ => return self == other,
+        }
+    }
+
+    /// The names of the variables the expression needs values for, e.g.
+    /// `x` and `y` in `x * 2 + y`. Built-in constants such as `pi` aren't
+    /// included, as they always have a value.
+    #[cfg(any(feature = "std", test))]
+    pub fn variables(self: &Self) -> HashSet<String> {
+        let mut names: HashSet<String> = HashSet::new();
+        self.for_each_variable(&mut |name| {
+            names.insert(name.to_string());
+        });
+        return names;
+    }
+
+    /// Whether the expression needs no variables, so `eval` can give its
+    /// value. The same as `variables().is_empty()`.
+    pub fn is_constant(self: &Self) -> bool {
+        let mut constant = true;
+        self.for_each_variable(&mut |_| constant = false);
+        return constant;
+    }
+
+    fn for_each_variable(self: &Self, f: &mut dyn FnMut(&str)) {
+        match self {
+            Expr::Int(_) | Expr::Num(_) | Expr::Bool(_) => {},
+            Expr::Var(name) if environment::is_constant(name) => {},
+            Expr::Var(name) => f(name),
+            Expr::UnaryMinus(operand) | Expr::Not(operand) => operand.for_each_variable(f),
+            Expr::BinOp { left, right, .. } => {
+                left.for_each_variable(f);
+                right.for_each_variable(f);
+            },
+            Expr::Call { args, .. } => {
+                for arg in args {
+                    arg.for_each_variable(f);
+                }
+            },
+        }
+    }
+
+    /// How tightly the expression binds when printed, so `Display` knows
+    /// which children need parentheses. Operands never need them.
+    fn precedence(self: &Self) -> u8 {
+        match self {
+            Expr::BinOp { op, .. } => return op.precedence(),
+            // A negative literal prints with a leading `-`, like a negation.
+            Expr::UnaryMinus(_) => return unary_precedence(),
+            Expr::Not(_) => return not_precedence(),
+            Expr::Num(n) if n.is_sign_negative() => return unary_precedence(),
+            Expr::Int(n) if *n < 0 => return unary_precedence(),
+            Expr::Int(_) | Expr::Num(_) | Expr::Bool(_) | Expr::Var(_) | Expr::Call { .. } => return u8::MAX,
+        }
+    }
+
+    fn fmt_child(self: &Self, f: &mut fmt::Formatter, parenthesize: bool) -> fmt::Result {
+        if parenthesize {
+            return write!(f, "({})", self);
+        }
+        return fmt::Display::fmt(self, f);
+    }
+}
+
+fn unary_precedence() -> u8 {
+    return default_operators().get_precedence(UNARY_MINUS).expect("unary minus has a precedence");
+}
+
+fn not_precedence() -> u8 {
+    return default_operators().get_precedence(NOT).expect("not has a precedence");
+}
+
+/// Prints the expression as infix with only the parentheses it needs, e.g.
+/// `(1 + 2) * 3`. The alternate form `{:#}` parenthesizes every operation
+/// instead, e.g. `((1 + 2) * 3)`.
+impl fmt::Display for Expr {
+    fn fmt(self: &Self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expr::Int(n) => return write!(f, "{}", n),
+            Expr::Num(n) => {
+                // There's no literal for these, so they print as the division
+                // that makes them, which parses back.
+                if n.is_nan() {
+                    return write!(f, "(0.0 / 0.0)");
+                }
+                if n.is_infinite() {
+                    return write!(f, "({}1.0 / 0.0)", if *n < 0.0 { "-" } else { "" });
+                }
+                // Very large and very small values would otherwise be written
+                // out in full, and integral ones past 2^53 would read back
+                // in as a different integer.
+                if *n != 0.0 && (n.abs() >= 1e16 || n.abs() < 1e-6) {
+                    return write!(f, "{:e}", n);
+                }
+                // `7.0` printed as `7` would read back as an integer.
+                if n.fract() == 0.0 {
+                    return write!(f, "{:.1}", n);
+                }
+                return write!(f, "{}", n);
+            },
+            Expr::Bool(b) => return write!(f, "{}", b),
+            Expr::Var(name) => return write!(f, "{}", name),
+            Expr::Call { name, args } => {
+                write!(f, "{}(", name)?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    fmt::Display::fmt(arg, f)?;
+                }
+                return write!(f, ")");
+            },
+            Expr::BinOp { op, left, right } if f.alternate() => {
+                return write!(f, "({:#} {} {:#})", left, op, right);
+            },
+            Expr::UnaryMinus(operand) if f.alternate() => return write!(f, "(-{:#})", operand),
+            Expr::Not(operand) if f.alternate() => return write!(f, "(!{:#})", operand),
+            Expr::BinOp { op, left, right } => {
+                let prec = op.precedence();
+                let left_assoc = op.is_left_associative();
+                // `(1 < x) < 5` without its parentheses would be a chain.
+                let compared = op.is_comparison() && matches!(**left, Expr::BinOp { op, .. } if op.is_comparison());
+                left.fmt_child(f, left.precedence() < prec || (left.precedence() == prec && !left_assoc) || compared)?;
+                write!(f, " {} ", op)?;
+                return right.fmt_child(f, right.precedence() < prec || (right.precedence() == prec && left_assoc));
+            },
+            Expr::UnaryMinus(operand) => {
+                write!(f, "-")?;
+                return operand.fmt_child(f, operand.precedence() < unary_precedence());
+            },
+            Expr::Not(operand) => {
+                write!(f, "!")?;
+                return operand.fmt_child(f, operand.precedence() < not_precedence());
+            },
+        }
+    }
+}
+
+impl Expr {
+    /// The tree for reading while debugging, a node to a line with each
+    /// node's operands under it indented two spaces more:
+    ///
+    /// ```
+    /// use expresso::parse;
+    ///
+    /// assert_eq!(parse("-x * max(2, 1.5)").unwrap().to_debug_tree(), "\
+    /// BinaryOp Mul
+    ///   UnaryMinus
+    ///     Var x
+    ///   Call max
+    ///     Int 2
+    ///     Num 1.5
+    /// ");
+    /// ```
+    pub fn to_debug_tree(self: &Self) -> String {
+        let mut tree = String::new();
+        let mut nodes = vec![(self, 0)];
+
+        while let Some((node, depth)) = nodes.pop() {
+            tree.push_str(&"  ".repeat(depth));
+            match node {
+                Expr::Int(n) => tree.push_str(&format!("Int {}", n)),
+                Expr::Num(n) => tree.push_str(&format!("Num {:?}", n)),
+                Expr::Bool(b) => tree.push_str(&format!("Bool {}", b)),
+                Expr::Var(name) => tree.push_str(&format!("Var {}", name)),
+                Expr::BinOp { op, .. } => tree.push_str(&format!("BinaryOp {:?}", op)),
+                Expr::UnaryMinus(_) => tree.push_str("UnaryMinus"),
+                Expr::Not(_) => tree.push_str("Not"),
+                Expr::Call { name, .. } => tree.push_str(&format!("Call {}", name)),
+            }
+            tree.push('\n');
+            nodes.extend(node.operands().into_iter().rev().map(|operand| (operand, depth + 1)));
+        }
+        return tree;
+    }
+
+    /// Moves the node out, leaving a leaf in its place.
+    pub(crate) fn take(self: &mut Self) -> Expr {
+        return mem::replace(self, Expr::Int(0));
+    }
+
+    /// The node's operands, in order.
+    fn operands(self: &Self) -> Vec<&Expr> {
+        match self {
+            Expr::BinOp { left, right, .. } => return vec![left.as_ref(), right.as_ref()],
+            Expr::UnaryMinus(operand) | Expr::Not(operand) => return vec![operand.as_ref()],
+            Expr::Call { args, .. } => return args.iter().collect(),
+            Expr::Int(_) | Expr::Num(_) | Expr::Bool(_) | Expr::Var(_) => return Vec::new(),
+        }
+    }
+
+    /// Moves the node's operands onto `into`, leaving leaves in their place.
+    fn take_operands(self: &mut Self, into: &mut Vec<Expr>) {
+        match self {
+            Expr::BinOp { left, right, .. } => {
+                into.push(left.take());
+                into.push(right.take());
+            },
+            Expr::UnaryMinus(operand) | Expr::Not(operand) => into.push(operand.take()),
+            Expr::Call { args, .. } => into.append(args),
+            Expr::Int(_) | Expr::Num(_) | Expr::Bool(_) | Expr::Var(_) => {},
+        }
+    }
+}
+
+impl Clone for Expr {
+    fn clone(self: &Self) -> Self {
+        // Nodes still to copy, each with whether its operands have been, and
+        // the copies of operands not yet given to their parent.
+        let mut pending: Vec<(&Expr, bool)> = vec![(self, false)];
+        let mut copies: Vec<Expr> = Vec::new();
+
+        while let Some((node, operands_copied)) = pending.pop() {
+            let operands = node.operands();
+            if !operands_copied && !operands.is_empty() {
+                pending.push((node, true));
+                pending.extend(operands.into_iter().rev().map(|operand| (operand, false)));
+                continue;
+            }
+
+            let mut args = copies.split_off(copies.len() - operands.len());
+            let copy = match node {
+                Expr::Int(n) => Expr::Int(*n),
+                Expr::Num(n) => Expr::Num(*n),
+                Expr::Bool(b) => Expr::Bool(*b),
+                Expr::Var(name) => Expr::Var(name.clone()),
+                Expr::BinOp { op, .. } => {
+                    let (Some(right), Some(left)) = (args.pop(), args.pop()) else { unreachable!() };
+                    Expr::BinOp { op: *op, left: Box::new(left), right: Box::new(right) }
+                },
+                Expr::UnaryMinus(_) | Expr::Not(_) => {
+                    let Some(operand) = args.pop() else { unreachable!() };
+                    match node {
+                        Expr::Not(_) => Expr::Not(Box::new(operand)),
+                        _ => Expr::UnaryMinus(Box::new(operand)),
+                    }
+                },
+                Expr::Call { name, .. } => Expr::Call { name: name.clone(), args },
+            };
+            copies.push(copy);
+        }
+
+        let Some(copy) = copies.pop() else { unreachable!() };
+        return copy;
+    }
+}
+
+impl PartialEq for Expr {
+    fn eq(self: &Self, other: &Self) -> bool {
+        let mut pairs: Vec<(&Expr, &Expr)> = vec![(self, other)];
+
+        while let Some((a, b)) = pairs.pop() {
+            let same = match (a, b) {
+                (Expr::Int(x), Expr::Int(y)) => x == y,
+                (Expr::Num(x), Expr::Num(y)) => x == y,
+                (Expr::Bool(x), Expr::Bool(y)) => x == y,
+                (Expr::Var(x), Expr::Var(y)) => x == y,
+                (Expr::BinOp { op: x, .. }, Expr::BinOp { op: y, .. }) => x == y,
+                (Expr::UnaryMinus(_), Expr::UnaryMinus(_)) | (Expr::Not(_), Expr::Not(_)) => true,
+                (Expr::Call { name: x, args: xs }, Expr::Call { name: y, args: ys }) => x == y && xs.len() == ys.len(),
+                _ => false,
+            };
+            if !same {
+                return false;
+            }
+            pairs.extend(a.operands().into_iter().zip(b.operands()));
+        }
+        return true;
+    }
+}
+
+/// Prints what `#[derive(Debug)]` would, on one line even with `{:#?}`.
+/// With a precision only that many levels print, and the nodes below them
+/// are `..`: `{:.1?}` of `1 + 2` is `BinOp { op: Add, left: .., right: .. }`.
+impl fmt::Debug for Expr {
+    fn fmt(self: &Self, f: &mut fmt::Formatter) -> fmt::Result {
+        enum Piece<'a> {
+            Node(&'a Expr, usize),
+            Text(&'static str),
+        }
+
+        let max_depth = f.precision();
+        let mut pieces = vec![Piece::Node(self, 0)];
+
+        while let Some(piece) = pieces.pop() {
+            let (node, depth) = match piece {
+                Piece::Text(text) => {
+                    f.write_str(text)?;
+                    continue;
+                },
+                Piece::Node(node, depth) => (node, depth),
+            };
+            if max_depth.is_some_and(|max| depth >= max) {
+                f.write_str("..")?;
+                continue;
+            }
+
+            let below = depth + 1;
+            match node {
+                Expr::Int(n) => write!(f, "Int({:?})", n)?,
+                Expr::Num(n) => write!(f, "Num({:?})", n)?,
+                Expr::Bool(b) => write!(f, "Bool({:?})", b)?,
+                Expr::Var(name) => write!(f, "Var({:?})", name)?,
+                Expr::BinOp { op, left, right } => {
+                    write!(f, "BinOp {{ op: {:?}, left: ", op)?;
+                    let rest = [Piece::Text(" }"), Piece::Node(right, below), Piece::Text(", right: "), Piece::Node(left, below)];
+                    pieces.extend(rest);
+                },
+                Expr::UnaryMinus(operand) => {
+                    f.write_str("UnaryMinus(")?;
+                    pieces.extend([Piece::Text(")"), Piece::Node(operand, below)]);
+                },
+                Expr::Not(operand) => {
+                    f.write_str("Not(")?;
+                    pieces.extend([Piece::Text(")"), Piece::Node(operand, below)]);
+                },
+                Expr::Call { name, args } => {
+                    write!(f, "Call {{ name: {:?}, args: [", name)?;
+                    pieces.push(Piece::Text("] }"));
+                    for (i, arg) in args.iter().enumerate().rev() {
+                        pieces.push(Piece::Node(arg, below));
+                        if i > 0 {
+                            pieces.push(Piece::Text(", "));
+                        }
+                    }
+                },
+            }
+        }
+        return Ok(());
+    }
+}
+
+impl Drop for Expr {
+    fn drop(self: &mut Self) {
+        // Each node taken off leaves its own operands here, so by the time
+        // it drops it has only leaves left.
+        let mut nodes = Vec::new();
+        self.take_operands(&mut nodes);
+        while let Some(mut node) = nodes.pop() {
+            node.take_operands(&mut nodes);
+        }
+    }
+}
+
+/// The same as `parse`, so `"x + 1".parse::<Expr>()` works.
+impl FromStr for Expr {
+    type Err = ExpressionError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        return parse(input);
+    }
+}
+
+impl From<f64> for Expr {
+    fn from(n: f64) -> Self {
+        return Expr::Num(n);
+    }
+}
+
+impl From<i64> for Expr {
+    fn from(n: i64) -> Self {
+        return Expr::Int(n);
+    }
+}
+
+/// Builds an `Expr` in code rather than parsing one, such as a polynomial
+/// with as many terms as a loop runs for:
+///
+/// ```
+/// use expresso::{parse, ExprBuilder as E};
+///
+/// let mut poly = E::int(1);
+/// for n in 1..=3 {
+///     poly = E::add(poly, E::pow(E::var("x"), E::int(n)));
+/// }
+/// assert_eq!(poly, parse("1 + x ^ 1 + x ^ 2 + x ^ 3").unwrap());
+/// ```
+///
+/// The tree is built as given, so there is no precedence to get wrong:
+/// `E::mul(E::add(a, b), c)` is `(a + b) * c`.
+pub struct ExprBuilder;
+
+impl ExprBuilder {
+    /// An integer, like `parse` makes of `2`.
+    pub fn int(n: i64) -> Expr {
+        return Expr::Int(n);
+    }
+
+    /// A float, like `parse` makes of `2.5`.
+    pub fn num(n: f64) -> Expr {
+        return Expr::Num(n);
+    }
+
+    pub fn var(name: &str) -> Expr {
+        return Expr::Var(name.to_string());
+    }
+
+    /// A call of the function `name`, which is only looked up when the
+    /// tree is evaluated.
+    pub fn func(name: &str, args: Vec<Expr>) -> Expr {
+        return Expr::Call { name: name.to_string(), args };
+    }
+
+    pub fn neg(operand: Expr) -> Expr {
+        return Expr::UnaryMinus(Box::new(operand));
+    }
+
+    /// `left op right`, for the operators without a function of their own.
+    pub fn binary(op: BinOpKind, left: Expr, right: Expr) -> Expr {
+        return Expr::BinOp { op, left: Box::new(left), right: Box::new(right) };
+    }
+
+    pub fn add(left: Expr, right: Expr) -> Expr {
+        return ExprBuilder::binary(BinOpKind::Add, left, right);
+    }
+
+    pub fn sub(left: Expr, right: Expr) -> Expr {
+        return ExprBuilder::binary(BinOpKind::Sub, left, right);
+    }
+
+    pub fn mul(left: Expr, right: Expr) -> Expr {
+        return ExprBuilder::binary(BinOpKind::Mul, left, right);
+    }
+
+    pub fn div(left: Expr, right: Expr) -> Expr {
+        return ExprBuilder::binary(BinOpKind::Div, left, right);
+    }
+
+    pub fn rem(left: Expr, right: Expr) -> Expr {
+        return ExprBuilder::binary(BinOpKind::Rem, left, right);
+    }
+
+    pub fn pow(left: Expr, right: Expr) -> Expr {
+        return ExprBuilder::binary(BinOpKind::Pow, left, right);
+    }
+}
+
+/// Formats an `Expr` for reading rather than parsing back, either on one
+/// line with every operation parenthesized, like `{:#}`, or as a tree:
+///
+/// ```
+/// use expresso::{parse, PrettyPrinter};
+///
+/// let expr = parse("1 + 2 * 3 - 4").unwrap();
+/// assert_eq!(PrettyPrinter::inline(&expr).to_string(), "((1 + (2 * 3)) - 4)");
+/// assert_eq!(PrettyPrinter::tree(&expr).to_string(), "\
+/// -
+/// ├─ +
+/// │  ├─ 1
+/// │  └─ *
+/// │     ├─ 2
+/// │     └─ 3
+/// └─ 4");
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct PrettyPrinter<'a> {
+    expr: &'a Expr,
+    tree: bool,
+}
+
+impl<'a> PrettyPrinter<'a> {
+    /// Prints `expr` on one line, e.g. `((1 + (2 * 3)) - 4)`.
+    pub fn inline(expr: &'a Expr) -> Self {
+        return PrettyPrinter { expr, tree: false };
+    }
+
+    /// Prints `expr` as a tree with a node per line, like `cargo tree`.
+    /// Operators label their operands, and calls, printed as `max()`, their
+    /// arguments. There is no newline after the last line.
+    pub fn tree(expr: &'a Expr) -> Self {
+        return PrettyPrinter { expr, tree: true };
+    }
+}
+
+impl fmt::Display for PrettyPrinter<'_> {
+    fn fmt(self: &Self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.tree {
+            return write_tree(f, self.expr, &mut String::new());
+        }
+        return write!(f, "{:#}", self.expr);
+    }
+}
+
+/// Writes the node for `expr` and, on the lines after it, its children.
+/// `prefix` continues the branches of the nodes above, one column of
+/// three characters for each level of depth.
+fn write_tree(f: &mut dyn fmt::Write, expr: &Expr, prefix: &mut String) -> fmt::Result {
+    let children: Vec<&Expr> = match expr {
+        Expr::Int(_) | Expr::Num(_) | Expr::Bool(_) | Expr::Var(_) => {
+            write!(f, "{}", expr)?;
+            Vec::new()
+        },
+        Expr::BinOp { op, left, right } => {
+            write!(f, "{}", op)?;
+            vec![left.as_ref(), right.as_ref()]
+        },
+        Expr::UnaryMinus(operand) => {
+            write!(f, "-")?;
+            vec![operand.as_ref()]
+        },
+        Expr::Not(operand) => {
+            write!(f, "!")?;
+            vec![operand.as_ref()]
+        },
+        Expr::Call { name, args } => {
+            write!(f, "{}()", name)?;
+            args.iter().collect()
+        },
+    };
+
+    for (i, child) in children.iter().enumerate() {
+        let last = i + 1 == children.len();
+        write!(f, "\n{}{}", prefix, if last { "└─ " } else { "├─ " })?;
+        let depth = prefix.len();
+        prefix.push_str(if last { "   " } else { "│  " });
+        write_tree(f, child, prefix)?;
+        prefix.truncate(depth);
+    }
+    return Ok(());
+}
+
+/// How deeply operations and parentheses may nest in an `Expr` tree. The
+/// parser and printers recurse once per level, so well past this they would
+/// run out of stack.
+const MAX_NESTING: usize = 256;
+
+/// Parses an infix expression into an `Expr` tree. Malformed input is
+/// reported as an `ExpressionError::Parse` with the offending position,
+/// and so is nesting deeper than `MAX_NESTING`, as `NestingTooDeep`.
+pub fn parse(input: &str) -> Result<Expr, ExpressionError> {
+    let tokens = tokenize_infix_spanned(input, default_operators())?;
+
+    let mut parser = Parser { tokens: &tokens, pos: 0, depth: 0 };
+    let (expr, _) = parser.parse_expr(0)?;
+
+    match parser.peek() {
+        None => return Ok(expr),
+        Some(Token::ParenRight) => return Err(ExpressionError::MismatchedParentheses),
+        Some(_) => return Err(ExpressionError::TooManyOperands),
+    }
+}
+
+/// Reformats an infix expression: one space either side of each binary
+/// operator, none inside parentheses or before a comma, and only the
+/// parentheses the precedence and associativity of the operators need, so
+/// `"((1+2))*3"` becomes `"(1 + 2) * 3"` and `"2^(3^2)"` becomes
+/// `"2 ^ 3 ^ 2"`. Numbers print as their values, so `0x10` becomes `16`;
+/// integers stay exact, and very large or small floats take an exponent,
+/// as in `1e300`. Formatting the result again gives it back unchanged.
+pub fn format_expression(input: &str) -> Result<String, ExpressionError> {
+    return Ok(parse(input)?.to_string());
+}
+
+/// Converts a postfix expression such as `"1 2 + 3 *"` to infix with only
+/// the parentheses it needs, `"(1 + 2) * 3"`. Names of built-in functions
+/// are calls, taking as many operands as the function has arguments; other
+/// names are variables.
+pub fn rpn_to_infix(input: &str) -> Result<String, ParseError> {
+    return Ok(parse_rpn(input)?.to_string());
+}
+
+/// Same as `rpn_to_infix`, but with every operation in parentheses, so
+/// `"1 2 3 * + 4 -"` becomes `"((1 + (2 * 3)) - 4)"` whatever the
+/// precedence. An operator short of operands is `EmptyStack`, and values
+/// left over are `UnconsumedInput`, pointing at the first extra one.
+pub fn format_rpn_to_infix(rpn: &str) -> Result<String, ExpressionError> {
+    let expr = parse_rpn(rpn).map_err(|err| match err.kind {
+        ParseErrorKind::MissingOperand => ExpressionError::EmptyStack,
+        ParseErrorKind::MissingOperator { .. } => ExpressionError::UnconsumedInput(err.position),
+        _ => ExpressionError::Parse(err),
+    })?;
+    return Ok(format!("{:#}", expr));
+}
+
+fn parse_rpn(input: &str) -> Result<Expr, ParseError> {
+    let error = |kind, position| Err(ParseError { kind, position });
+
+    let tokens = tokenize_rpn_spanned(input, default_operators()).map_err(tokenize_parse_error)?;
+
+    // Each operand with its span, for pointing at leftovers, and its height.
+    let mut st: Vec<(Expr, Span, usize)> = Vec::new();
+
+    for SpannedToken { token, span } in tokens {
+        let arity = match &token {
+            Token::Number(n) => {
+                st.push((Expr::Int(*n), span, 1));
+                continue;
+            },
+            Token::Float(f) => {
+                st.push((Expr::Num(*f), span, 1));
+                continue;
+            },
+            Token::Bool(b) => {
+                st.push((Expr::Bool(*b), span, 1));
+                continue;
+            },
+            Token::Ident(name) | Token::Function(name) => match default_functions().get(name) {
+                Some(function) => function.arity(),
+                None => {
+                    st.push((Expr::Var(name.clone()), span, 1));
+                    continue;
+                },
+            },
+            Token::UnaryMinus | Token::Not => 1,
+            Token::Chain(ops) => ops.len() + 1,
+            _ => 2,
+        };
+
+        if st.len() < arity {
+            return error(ParseErrorKind::MissingOperand, span.start);
+        }
+        let operands = st.split_off(st.len() - arity);
+        let start = operands.first().map_or(span.start, |(_, operand, _)| operand.start);
+        let mut height = 1 + operands.iter().map(|(_, _, height)| *height).max().unwrap_or(0);
+        if let Token::Chain(ops) = &token {
+            height += ops.len() - 1;
+        }
+        if height > MAX_NESTING {
+            return error(ParseErrorKind::NestingTooDeep, span.start);
+        }
+        let mut args: Vec<Expr> = operands.into_iter().map(|(expr, _, _)| expr).collect();
+
+        let expr = match token {
+            Token::Ident(name) | Token::Function(name) => Expr::Call { name, args },
+            Token::UnaryMinus => Expr::UnaryMinus(Box::new(args.remove(0))),
+            Token::Not => Expr::Not(Box::new(args.remove(0))),
+            Token::Chain(ops) => {
+                let link = |i: usize| {
+                    let Some(op) = BinOpKind::from_symbol(ops[i]) else { unreachable!() };
+                    return Expr::BinOp { op, left: Box::new(args[i].clone()), right: Box::new(args[i + 1].clone()) };
+                };
+                // A tree can't share the middle operands, so they repeat.
+                let mut chain = link(0);
+                for i in 1..ops.len() {
+                    chain = Expr::BinOp { op: BinOpKind::And, left: Box::new(chain), right: Box::new(link(i)) };
+                }
+                chain
+            },
+            _ => {
+                let Some(symbol) = token.operator_symbol() else { unreachable!() };
+                let Some(op) = BinOpKind::from_symbol(symbol) else {
+                    return error(ParseErrorKind::UnexpectedCharacter(symbol), span.start);
+                };
+                let right = args.remove(1);
+                let left = args.remove(0);
+                Expr::BinOp { op, left: Box::new(left), right: Box::new(right) }
+            },
+        };
+        st.push((expr, Span { start, end: span.end }, height));
+    }
+
+    match st.len() {
+        0 => return error(ParseErrorKind::MissingOperand, input.len()),
+        1 => return Ok(st.remove(0).0),
+        _ => {
+            let (left_span, right_span) = (st[0].1, st[1].1);
+            let kind = ParseErrorKind::MissingOperator { left_span, right_span, hint: missing_operator_hint() };
+            return error(kind, right_span.start);
+        },
+    }
+}
+
+/// Precedence climbing parser over a token stream, using the same
+/// precedence and associativity as the Shunting Yard functions. `depth`
+/// counts the `parse_expr` calls under way.
+struct Parser<'a> {
+    tokens: &'a [SpannedToken],
+    pos: usize,
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn next(self: &mut Self) -> Option<&'a Token> {
+        let token = self.peek();
+        self.pos += 1;
+        return token;
+    }
+
+    fn peek(self: &Self) -> Option<&'a Token> {
+        return self.tokens.get(self.pos).map(|t| &t.token);
+    }
+
+    /// A `NestingTooDeep` error at the token at `pos`, or the end.
+    fn too_deep(self: &Self, pos: usize) -> ExpressionError {
+        let position = self.tokens.get(pos).or(self.tokens.last()).map_or(0, |t| t.span.start);
+        return ExpressionError::Parse(ParseError { kind: ParseErrorKind::NestingTooDeep, position });
+    }
+
+    /// Parses an expression whose binary operators all bind at least as
+    /// tightly as `min_prec`, and gives it with the height of its tree.
+    fn parse_expr(self: &mut Self, min_prec: u8) -> Result<(Expr, usize), ExpressionError> {
+        if self.depth == MAX_NESTING {
+            return Err(self.too_deep(self.pos));
+        }
+        self.depth += 1;
+        let parsed = self.parse_binops(min_prec);
+        self.depth -= 1;
+        return parsed;
+    }
+
+    fn parse_binops(self: &mut Self, min_prec: u8) -> Result<(Expr, usize), ExpressionError> {
+        let (mut left, mut height) = self.parse_operand()?;
+        // The right operand of the last operator, if it was a comparison.
+        let mut compared: Option<Expr> = None;
+
+        loop {
+            let Some(token) = self.peek() else { break; };
+            let Some(symbol) = token.operator_symbol() else { break; };
+            let Some(op) = BinOpKind::from_symbol(symbol) else { break; };
+
+            let prec = default_operators().get_precedence(symbol)?;
+            if prec < min_prec {
+                break;
+            }
+            let op_pos = self.pos;
+            self.pos += 1;
+
+            // Left associative operators only take tighter operators on
+            // their right; right associative ones also take themselves.
+            let right_min_prec = match default_operators().get_associativity(symbol)? {
+                Associativity::Left => prec + 1,
+                Associativity::Right => prec,
+            };
+            let (right, right_height) = self.parse_expr(right_min_prec)?;
+
+            // A chain such as `1 + 1 + …` grows the tree without recursing.
+            height = 1 + height.max(right_height);
+            if height > MAX_NESTING {
+                return Err(self.too_deep(op_pos));
+            }
+            let middle = compared.take().filter(|_| op.is_comparison());
+            if op.is_comparison() {
+                compared = Some(right.clone());
+            }
+            left = match middle {
+                // Comparisons chain, so `1 < x < 5` is `1 < x && x < 5`. A
+                // tree can't share `x`, so it repeats.
+                Some(middle) => {
+                    height += 1;
+                    let link = Expr::BinOp { op, left: Box::new(middle), right: Box::new(right) };
+                    Expr::BinOp { op: BinOpKind::And, left: Box::new(left), right: Box::new(link) }
+                },
+                None => Expr::BinOp {
+                    op,
+                    left: Box::new(left),
+                    right: Box::new(right),
+                },
+            };
+        }
+
+        return Ok((left, height));
+    }
+
+    fn parse_operand(self: &mut Self) -> Result<(Expr, usize), ExpressionError> {
+        let Some(token) = self.next() else {
+            return Err(ExpressionError::EmptyStack);
+        };
+
+        match token {
+            Token::Number(n) => return Ok((Expr::Int(*n), 1)),
+            Token::Float(f) => return Ok((Expr::Num(*f), 1)),
+            Token::Bool(b) => return Ok((Expr::Bool(*b), 1)),
+            Token::Ident(name) => return Ok((Expr::Var(name.clone()), 1)),
+            Token::Function(name) => {
+                if self.next() != Some(&Token::ParenLeft) {
+                    return Err(ExpressionError::MismatchedParentheses);
+                }
+                // `eval` reports it if the function takes arguments.
+                if self.peek() == Some(&Token::ParenRight) {
+                    self.pos += 1;
+                    return Ok((Expr::Call { name: name.clone(), args: Vec::new() }, 1));
+                }
+                let (first, mut height) = self.parse_expr(0)?;
+                let mut args = vec![first];
+                loop {
+                    match self.next() {
+                        Some(Token::Comma) => {
+                            let (arg, arg_height) = self.parse_expr(0)?;
+                            args.push(arg);
+                            height = height.max(arg_height);
+                        },
+                        Some(Token::ParenRight) => break,
+                        _ => return Err(ExpressionError::MismatchedParentheses),
+                    }
+                }
+                return Ok((Expr::Call { name: name.clone(), args }, height + 1));
+            },
+            Token::UnaryMinus => {
+                let (operand, height) = self.parse_expr(default_operators().get_precedence(UNARY_MINUS)?)?;
+                return Ok((Expr::UnaryMinus(Box::new(operand)), height + 1));
+            },
+            Token::Not => {
+                let (operand, height) = self.parse_expr(not_precedence())?;
+                return Ok((Expr::Not(Box::new(operand)), height + 1));
+            },
+            Token::ParenLeft => {
+                let inner = self.parse_expr(0)?;
+                if self.next() != Some(&Token::ParenRight) {
+                    return Err(ExpressionError::MismatchedParentheses);
+                }
+                return Ok(inner);
+            },
+            Token::ParenRight => return Err(ExpressionError::MismatchedParentheses),
+            _ => {
+                // An operator where an operand should be, as in `* 3`.
+                let Some(symbol) = token.operator_symbol() else { unreachable!() };
+                return Err(ExpressionError::UnexpectedToken(symbol));
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::format;
+
+    use super::*;
+
+    fn num(n: f64) -> Box<Expr> {
+        Box::new(Expr::Num(n))
+    }
+
+    fn int(n: i64) -> Box<Expr> {
+        Box::new(Expr::Int(n))
+    }
+
+    fn missing_operator(left: (usize, usize), right: (usize, usize)) -> ParseErrorKind {
+        let span = |(start, end)| Span { start, end };
+        let hint = missing_operator_hint();
+        ParseErrorKind::MissingOperator { left_span: span(left), right_span: span(right), hint }
+    }
+
+    #[test]
+    fn test_parse_tree_shape() {
+        assert_eq!(parse("1 + 2 * 3").unwrap(), Expr::BinOp {
+            op: BinOpKind::Add,
+            left: int(1),
+            right: Box::new(Expr::BinOp { op: BinOpKind::Mul, left: int(2), right: int(3) }),
+        });
+        assert_eq!(parse("-2 ^ 2").unwrap(), Expr::UnaryMinus(Box::new(Expr::BinOp {
+            op: BinOpKind::Pow,
+            left: int(2),
+            right: int(2),
+        })));
+    }
+
+    #[test]
+    fn test_display_fully_parenthesized() {
+        assert_eq!(format!("{:#}", parse("1 + 2 * 3 - 4").unwrap()), "((1 + (2 * 3)) - 4)");
+        assert_eq!(format!("{:#}", parse("2 ^ 3 ^ 2").unwrap()), "(2 ^ (3 ^ 2))");
+        assert_eq!(format!("{:#}", parse("10 - 4 - 3").unwrap()), "((10 - 4) - 3)");
+        assert_eq!(format!("{:#}", parse("-(1.5 + 2)").unwrap()), "(-(1.5 + 2))");
+    }
+
+    #[test]
+    fn test_display_minimal_parentheses() {
+        for (input, printed) in [
+            ("((1 + (2 * 3)) - 4)", "1 + 2 * 3 - 4"),
+            ("(1 + 2) * 3", "(1 + 2) * 3"),
+            ("10 - (4 - 3)", "10 - (4 - 3)"),
+            ("(10 - 4) - 3", "10 - 4 - 3"),
+            ("(2 ^ 3) ^ 2", "(2 ^ 3) ^ 2"),
+            ("2 ^ (3 ^ 2)", "2 ^ 3 ^ 2"),
+            ("(-2) ^ 2", "(-2) ^ 2"),
+            ("-(2 ^ 2)", "-2 ^ 2"),
+            ("-(1 + x)", "-(1 + x)"),
+            ("(-3) * 2", "-3 * 2"),
+            ("(7 % 4) * 2", "7 % 4 * 2"),
+            ("7 % (4 * 2)", "7 % (4 * 2)"),
+        ] {
+            assert_eq!(parse(input).unwrap().to_string(), printed);
+        }
+
+        let negative = Expr::BinOp { op: BinOpKind::Pow, left: num(-2.0), right: num(2.0) };
+        assert_eq!(negative.to_string(), "(-2.0) ^ 2.0");
+        assert_eq!(Expr::Num(1e20).to_string(), "1e20");
+        assert_eq!(Expr::Num(7.0).to_string(), "7.0");
+        for (value, printed) in [(f64::INFINITY, "(1.0 / 0.0)"), (f64::NEG_INFINITY, "(-1.0 / 0.0)"), (f64::NAN, "(0.0 / 0.0)")] {
+            assert_eq!(Expr::Num(value).to_string(), printed);
+            assert!(parse(printed).is_ok(), "{}", printed);
+        }
+        assert_eq!(Expr::Num(-2.5e-9).to_string(), "-2.5e-9");
+        assert_eq!(Expr::Num(0.5).to_string(), "0.5");
+        assert_eq!(Expr::Int(i64::MAX).to_string(), "9223372036854775807");
+    }
+
+    #[test]
+    fn test_round_trip_matches_sy_evaulate() {
+        for input in [
+            "1 + 2 * 3 - 4",
+            "(12+34)*2",
+            "((1+2)*(3+4))-5",
+            "2 ^ 3 ^ 2",
+            "(2 ^ 3) ^ 2",
+            "-2^2",
+            "2 * (-1)",
+            "1-(-2)",
+            "--3",
+            "100 - 7 - 3 * (4 - 9)",
+        ] {
+            let expr = parse(input).unwrap();
+            assert_eq!(parse(&expr.to_string()).unwrap(), expr, "{}", input);
+            assert_eq!(parse(&format!("{:#}", expr)).unwrap(), expr, "{}", input);
+            assert_eq!(expr.eval().unwrap(), crate::sy_evaulate(input.to_string()).unwrap() as f64, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_eval_matches_sy_evaluate_f64() {
+        for input in ["1 + 2 * 3 - 4", "(12+34)*2", "-2^2", "2 ^ 3 ^ 2", "7 / 2", "(-3 + 4) * -2", "3.5 * 2 + 0.25"] {
+            let expr = parse(input).unwrap();
+            assert_eq!(expr.eval().unwrap(), crate::sy_evaluate_f64(input.to_string()).unwrap(), "{}", input);
+            // Display output parses back to the same tree.
+            assert_eq!(parse(&expr.to_string()).unwrap(), expr);
+        }
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(parse(""), Err(ExpressionError::EmptyInput));
+        for (input, kind, position) in [
+            ("(1 + 2", ParseErrorKind::UnbalancedParenthesis, 0),
+            ("1 + 2)", ParseErrorKind::UnbalancedParenthesis, 5),
+            ("1 +", ParseErrorKind::TrailingOperator { span: Span { start: 2, end: 3 } }, 2),
+            ("* 3", ParseErrorKind::LeadingOperator { span: Span { start: 0, end: 1 } }, 0),
+            ("2 3", missing_operator((0, 1), (2, 3)), 2),
+            ("1 $ 2", ParseErrorKind::UnexpectedCharacter('$'), 2),
+        ] {
+            assert_eq!(parse(input), Err(ExpressionError::Parse(ParseError { kind, position })), "{}", input);
+        }
+        assert_eq!(parse("1 / 0").unwrap().eval(), Err(ExpressionError::DivisionByZero));
+    }
+
+    #[test]
+    fn test_function_calls() {
+        let expr = parse("sqrt(x + 7) * 2").unwrap();
+        assert_eq!(expr, Expr::BinOp {
+            op: BinOpKind::Mul,
+            left: Box::new(Expr::Call {
+                name: "sqrt".to_string(),
+                args: vec![Expr::BinOp { op: BinOpKind::Add, left: Box::new(Expr::Var("x".to_string())), right: int(7) }],
+            }),
+            right: int(2),
+        });
+        assert_eq!(expr.to_string(), "sqrt(x + 7) * 2");
+        assert_eq!(format!("{:#}", expr), "(sqrt((x + 7)) * 2)");
+        assert_eq!(parse(&format!("{:#}", expr)).unwrap(), expr);
+
+        let variables = HashMap::from([("x".to_string(), 9.0)]);
+        assert_eq!(expr.eval_with_vars(&variables).unwrap(), 8.0);
+        assert_eq!(expr.eval_with(&variables, &Functions::new()), Err(ExpressionError::UnknownFunction("sqrt".to_string())));
+    }
+
+    #[test]
+    fn test_multi_argument_calls() {
+        let expr = parse("max(x, -2) + log(2, 8)").unwrap();
+        assert_eq!(expr.to_string(), "max(x, -2) + log(2, 8)");
+        assert_eq!(parse(&format!("{:#}", expr)).unwrap(), expr);
+
+        let variables = HashMap::from([("x".to_string(), 1.0)]);
+        assert_eq!(expr.eval_with_vars(&variables).unwrap(), 4.0);
+        assert_eq!(parse("hypot(3)").unwrap().eval(), Err(ExpressionError::WrongArity {
+            name: "hypot".to_string(),
+            expected: 2,
+            got: 1,
+        }));
+    }
+
+    #[test]
+    fn test_rpn_to_infix() {
+        assert_eq!(rpn_to_infix("1 2 3 * +").unwrap(), "1 + 2 * 3");
+        assert_eq!(rpn_to_infix("1 2 + 3 *").unwrap(), "(1 + 2) * 3");
+        assert_eq!(rpn_to_infix("2 3 2 ^ ^").unwrap(), "2 ^ 3 ^ 2");
+        assert_eq!(rpn_to_infix("2 3 ^ 2 ^").unwrap(), "(2 ^ 3) ^ 2");
+        assert_eq!(rpn_to_infix("1 2 3 - -").unwrap(), "1 - (2 - 3)");
+        assert_eq!(rpn_to_infix("x 1 + ~ 16 sqrt *").unwrap(), "-(x + 1) * sqrt(16)");
+        assert_eq!(rpn_to_infix("2 8 log 1.5 %").unwrap(), "log(2, 8) % 1.5");
+    }
+
+    #[test]
+    fn test_rpn_to_infix_round_trip() {
+        let expressions = [
+            "1 + 2 * 3",
+            "(1 + 2) * 3",
+            "2 ^ 3 ^ 2",
+            "(2 ^ 3) ^ 2",
+            "1 - (2 - 3) - 4",
+            "-2 ^ 2 + (-2) ^ 2",
+            "10 % (4 * 2) / 3",
+            "max(1, abs(-7)) * (3 - 1)",
+        ];
+        for input in expressions {
+            let rpn = crate::infix_to_rpn(input.to_string()).unwrap();
+            assert_eq!(rpn_to_infix(&rpn).unwrap(), parse(input).unwrap().to_string(), "{} -> {}", input, rpn);
+        }
+    }
+
+    #[test]
+    fn test_rpn_to_infix_errors() {
+        let error = |kind, position| Err(ParseError { kind, position });
+        assert_eq!(rpn_to_infix("1 2 3 +"), error(missing_operator((0, 1), (2, 7)), 2));
+        assert_eq!(rpn_to_infix("1 2 + 3"), error(missing_operator((0, 5), (6, 7)), 6));
+        assert_eq!(rpn_to_infix("1 +"), error(ParseErrorKind::MissingOperand, 2));
+        assert_eq!(rpn_to_infix("sqrt"), error(ParseErrorKind::MissingOperand, 0));
+        assert_eq!(rpn_to_infix(""), error(ParseErrorKind::MissingOperand, 0));
+        assert_eq!(rpn_to_infix("1 2 $"), error(ParseErrorKind::UnexpectedCharacter('$'), 4));
+        assert_eq!(rpn_to_infix("99999999999999999999 1 +"), error(ParseErrorKind::NumberTooLarge, 0));
+        assert_eq!(rpn_to_infix("1 +").unwrap_err().to_string(), "missing operand at position 2");
+    }
+
+    #[test]
+    fn test_comparisons() {
+        let expr = parse("x + 1 >= 2 * y").unwrap();
+        assert_eq!(expr, Expr::BinOp {
+            op: BinOpKind::Ge,
+            left: Box::new(Expr::BinOp {
+                op: BinOpKind::Add,
+                left: Box::new(Expr::Var("x".to_string())),
+                right: int(1),
+            }),
+            right: Box::new(Expr::BinOp {
+                op: BinOpKind::Mul,
+                left: int(2),
+                right: Box::new(Expr::Var("y".to_string())),
+            }),
+        });
+        assert_eq!(expr.to_string(), "x + 1 >= 2 * y");
+        assert_eq!(format!("{:#}", expr), "((x + 1) >= (2 * y))");
+
+        let variables = HashMap::from([("x".to_string(), 3.0), ("y".to_string(), 2.0)]);
+        assert_eq!(expr.eval_with_vars(&variables).unwrap(), 1.0);
+        assert_eq!(parse("1 == (2 != 2)").unwrap().to_string(), "1 == (2 != 2)");
+        assert_eq!(rpn_to_infix("1 2 < 3 ==").unwrap(), "(1 < 2) == 3");
+        assert_eq!(rpn_to_infix("1 x 5 [< <=]").unwrap(), "1 < x && x <= 5");
+    }
+
+    #[test]
+    fn test_pretty_printer() {
+        let expr = parse("-max(x, 2 ^ y) / 3").unwrap();
+        assert_eq!(PrettyPrinter::inline(&expr).to_string(), format!("{:#}", expr));
+        assert_eq!(PrettyPrinter::tree(&expr).to_string(), [
+            "/",
+            "├─ -",
+            "│  └─ max()",
+            "│     ├─ x",
+            "│     └─ ^",
+            "│        ├─ 2",
+            "│        └─ y",
+            "└─ 3",
+        ].join("\n"));
+
+        let leaf = Expr::Num(-1.5);
+        assert_eq!(PrettyPrinter::tree(&leaf).to_string(), "-1.5");
+        assert_eq!(PrettyPrinter::inline(&leaf).to_string(), "-1.5");
+    }
+
+    #[test]
+    fn test_semantically_eq() {
+        let eq = |a: &str, b: &str| parse(a).unwrap().semantically_eq(&parse(b).unwrap());
+
+        assert!(parse("x + 1").unwrap().semantically_eq(&parse("1 + x").unwrap()));
+        assert!(eq("2 * y", "y * 2"));
+        assert!(eq("(x + 1) * 2", "2 * (1 + x)"));
+        assert!(eq("max(x * 3, 1)", "max(3 * x, 1)"));
+        assert!(eq("-(a == b)", "-(b == a)"));
+        assert!(eq("x - 1", "x - 1"));
+
+        assert!(!eq("x - 1", "1 - x"));
+        assert!(!eq("x / 2", "2 / x"));
+        assert!(!eq("2 ^ x", "x ^ 2"));
+        assert!(!eq("x < 1", "1 < x"));
+        assert!(!eq("max(x, 1)", "max(1, x)"));
+        assert!(!eq("x + 1", "x * 1"));
+        assert!(!eq("(a + b) + c", "a + (b + c)"));
+
+        // `==` stays structural.
+        assert_ne!(parse("x + 1").unwrap(), parse("1 + x").unwrap());
+    }
+
+    #[test]
+    fn test_format_expression() {
+        for (input, formatted) in [
+            ("((1+2))*3", "(1 + 2) * 3"),
+            ("1 + (2 * 3)", "1 + 2 * 3"),
+            ("(1 + 2) * 3", "(1 + 2) * 3"),
+            ("2 ^ (3 ^ 2)", "2 ^ 3 ^ 2"),
+            ("(2 ^ 3) ^ 2", "(2 ^ 3) ^ 2"),
+            ("10-(4-3)", "10 - (4 - 3)"),
+            ("( max( x ,2 ) )", "max(x, 2)"),
+            ("-(x)", "-x"),
+            ("(1<2)==(3>=4)", "(1 < 2) == (3 >= 4)"),
+            ("1<x<=5", "1 < x && x <= 5"),
+        ] {
+            assert_eq!(format_expression(input).unwrap(), formatted, "{}", input);
+        }
+        assert_eq!(
+            format_expression("(1 + 2"),
+            Err(ExpressionError::Parse(ParseError { kind: ParseErrorKind::UnbalancedParenthesis, position: 0 }))
+        );
+    }
+
+    #[test]
+    fn test_format_expression_is_idempotent_and_keeps_values() {
+        let variables = HashMap::from([("x".to_string(), 2.5), ("y".to_string(), -3.0)]);
+        for input in [
+            "((((x))))",
+            "((1+2))*3-((4))/(5)",
+            "-(-(x)) ^ 2",
+            "(-x) ^ 2",
+            "(-2) ^ (-(1 + 1))",
+            "2 ^ -x ^ 2",
+            "x - (y - (x - y))",
+            "(x / y) / (x * y)",
+            "x % (y % 3) * 2",
+            "max((x), min(y, (1 + 2) * x)) ^ (1 / 2)",
+            "sqrt(((x * x) + (y * y)))",
+            "(x > y) + (x <= 1) * 10",
+            "(x && y) || (y == 1)",
+            "1 / (0 - 0) + x",
+            "pi * ((x))^2",
+        ] {
+            let formatted = format_expression(input).unwrap();
+            assert_eq!(format_expression(&formatted).unwrap(), formatted, "{}", input);
+            assert_eq!(
+                parse(&formatted).unwrap().eval_with_vars(&variables),
+                parse(input).unwrap().eval_with_vars(&variables),
+                "{} formatted as {}",
+                input,
+                formatted,
+            );
+        }
+
+        // Literals are written so they read back as the same values.
+        for (input, formatted) in [
+            ("123456789012345678", "123456789012345678"),
+            ("1e300", "1e300"),
+            ("1e20 + 1", "1e20 + 1"),
+            ("0.0000000025*x", "2.5e-9 * x"),
+            ("9007199254740993 - 1.5", "9007199254740993 - 1.5"),
+            ("7.0 / 2", "7.0 / 2"),
+            ("2.0 ^ 70", "2.0 ^ 70"),
+        ] {
+            assert_eq!(format_expression(input).as_deref(), Ok(formatted), "{}", input);
+            assert_eq!(format_expression(formatted).as_deref(), Ok(formatted), "{}", input);
+            assert_eq!(crate::evaluate(formatted), crate::evaluate(input), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_format_rpn_to_infix() {
+        assert_eq!(format_rpn_to_infix("1 2 3 * + 4 -").unwrap(), "((1 + (2 * 3)) - 4)");
+        assert_eq!(format_rpn_to_infix("1 2 3 - -").unwrap(), "(1 - (2 - 3))");
+        assert_eq!(format_rpn_to_infix("x 2.5 ^ y <=").unwrap(), "((x ^ 2.5) <= y)");
+        assert_eq!(format_rpn_to_infix("42").unwrap(), "42");
+        assert_eq!(format_rpn_to_infix("1 +"), Err(ExpressionError::EmptyStack));
+        assert_eq!(format_rpn_to_infix(""), Err(ExpressionError::EmptyStack));
+        assert_eq!(format_rpn_to_infix("1 2 3 +"), Err(ExpressionError::UnconsumedInput(2)));
+        let unexpected = ParseError { kind: ParseErrorKind::UnexpectedCharacter('$'), position: 4 };
+        assert_eq!(format_rpn_to_infix("1 2 $"), Err(ExpressionError::Parse(unexpected)));
+
+        // Postfix as the crate writes it reads back, negation, signed
+        // literals and calls included.
+        assert_eq!(format_rpn_to_infix("1 2 + ~").unwrap(), "(-(1 + 2))");
+        assert_eq!(format_rpn_to_infix("5 -3 +").unwrap(), "(5 + -3)");
+        assert_eq!(format_rpn_to_infix("16 sqrt").unwrap(), "sqrt(16)");
+        for infix in ["-(1+2)", "sqrt(16) * 2", "max(1, -x) ^ 2 - 3", "1 - (2 - 3) / 4"] {
+            let rpn = crate::infix_to_rpn(infix.to_string()).unwrap();
+            let formatted = format_rpn_to_infix(&rpn).unwrap();
+            assert_eq!(parse(&formatted), parse(infix), "{} -> {} -> {}", infix, rpn, formatted);
+            assert_eq!(formatted, format!("{:#}", parse(infix).unwrap()));
+        }
+    }
+
+    #[test]
+    fn test_variables() {
+        let names = |input| {
+            let mut names: Vec<String> = parse(input).unwrap().variables().into_iter().collect();
+            names.sort();
+            return names;
+        };
+        assert_eq!(parse("x*x + 2*x*y + y*y").unwrap().variables(), HashSet::from(["x".to_string(), "y".to_string()]));
+        assert_eq!(names("max(a, -b) ^ sqrt(c_1)"), ["a", "b", "c_1"]);
+        assert_eq!(names("2 * pi * r"), ["r"]);
+        assert!(names("1 + 2").is_empty());
+
+        assert!(parse("1 + max(2, 3)").unwrap().is_constant());
+        assert!(parse("tau / e").unwrap().is_constant());
+        assert!(!parse("-(1 + x)").unwrap().is_constant());
+    }
+
+    #[test]
+    fn test_from_str() {
+        let expr: Expr = "x + 1".parse().unwrap();
+        assert_eq!(expr, parse("x + 1").unwrap());
+        assert_eq!("1 +".parse::<Expr>(), parse("1 +"));
+        assert_eq!(Expr::from(2.5), Expr::Num(2.5));
+        assert_eq!(Expr::from(-3), Expr::Int(-3));
+
+        for input in ["(1 + 2) * 3", "2 ^ 3 ^ 2", "-(x + 1) * sqrt(16)", "max(a, -b) % 1.5", "(1 < 2) == 3"] {
+            let printed = input.parse::<Expr>().map(|expr| expr.to_string());
+            assert_eq!(printed.as_deref(), Ok(input));
+            let expr: Expr = input.parse().unwrap();
+            assert_eq!(expr.to_string().parse::<Expr>(), Ok(expr));
+        }
+    }
+
+    #[test]
+    fn test_expr_builder() {
+        use ExprBuilder as E;
+
+        let expr = E::sub(E::mul(E::add(E::int(1), E::num(2.5)), E::int(3)), E::func("sqrt", vec![E::int(16)]));
+        assert_eq!(expr, parse("(1 + 2.5) * 3 - sqrt(16)").unwrap());
+        assert_eq!(expr.eval(), Ok(6.5));
+
+        let expr = E::div(E::neg(E::var("x")), E::rem(E::int(7), E::pow(E::num(2.0), E::int(2))));
+        assert_eq!(expr.to_string(), "-x / (7 % 2.0 ^ 2)");
+        let variables = HashMap::from([("x".to_string(), 6.0)]);
+        assert_eq!(expr.eval_with_vars(&variables), parse("-x / (7 % 2 ^ 2)").unwrap().eval_with_vars(&variables));
+        assert_eq!(E::binary(BinOpKind::Lt, E::num(1.0), E::num(2.0)).eval(), Ok(1.0));
+
+        // x^4 + x^3 + ... + 1, term by term.
+        let mut poly = E::int(1);
+        for n in 1..=4 {
+            poly = E::add(E::pow(E::var("x"), E::int(n)), poly);
+        }
+        let parsed = parse("x^4 + (x^3 + (x^2 + (x^1 + 1)))").unwrap();
+        assert_eq!(poly, parsed);
+        let variables = HashMap::from([("x".to_string(), 2.0)]);
+        assert_eq!(poly.eval_with_vars(&variables), Ok(31.0));
+    }
+
+    #[test]
+    fn test_not_and_bools() {
+        assert_eq!(parse("!x").unwrap(), Expr::Not(Box::new(Expr::Var("x".to_string()))));
+        assert_eq!(parse("true && !false").unwrap(), Expr::BinOp {
+            op: BinOpKind::And,
+            left: Box::new(Expr::Bool(true)),
+            right: Box::new(Expr::Not(Box::new(Expr::Bool(false)))),
+        });
+        assert_eq!("!(1 < 2)".parse::<Expr>().unwrap().eval(), Ok(0.0));
+        assert_eq!(parse("!x * 2 + true").unwrap().eval_with_vars(&HashMap::from([("x".to_string(), 0.0)])), Ok(3.0));
+
+        for input in ["!(1 < 2)", "true", "!x * 2", "!!false || x", "-!x", "!-x", "2 ^ (!x)"] {
+            assert_eq!(format_expression(input).as_deref(), Ok(input));
+        }
+        assert_eq!(format_expression("!(1<2)").unwrap(), "!(1 < 2)");
+        assert_eq!(format!("{:#}", parse("!x == false").unwrap()), "((!x) == false)");
+        assert_eq!(rpn_to_infix("1 2 < ! true &&"), Ok("!(1 < 2) && true".to_string()));
+        assert_eq!(PrettyPrinter::tree(&parse("!true").unwrap()).to_string(), "!\n└─ true");
+    }
+
+    #[test]
+    fn test_nesting_limit() {
+        let too_deep = |position| Some(ExpressionError::Parse(ParseError { kind: ParseErrorKind::NestingTooDeep, position }));
+        let deepest = MAX_NESTING - 1;
+
+        // As deep as a tree may be, everything that walks it still works.
+        for input in [
+            format!("{}x{}", "(".repeat(deepest), ")".repeat(deepest)),
+            format!("{}x", "-".repeat(deepest)),
+            format!("x{}", " + 1".repeat(deepest)),
+            format!("x{}", " ^ 2".repeat(deepest)),
+            format!("{}x{}", "sin(".repeat(deepest), ")".repeat(deepest)),
+        ] {
+            let expr = parse(&input).unwrap();
+            assert_eq!(parse(&expr.to_string()).unwrap(), expr);
+            let _ = (expr.eval_with_vars(&HashMap::from([("x".to_string(), 0.5)])), expr.diff("x").map(|d| d.simplify()));
+            let _ = (expr.fold_constants(), PrettyPrinter::tree(&expr).to_string(), format!("{:#}", expr));
+        }
+
+        assert_eq!(parse(&format!("{}1{}", "(".repeat(MAX_NESTING), ")".repeat(MAX_NESTING))).err(), too_deep(256));
+        assert_eq!(parse(&format!("{}1", "-".repeat(MAX_NESTING))).err(), too_deep(256));
+        assert_eq!(parse(&format!("1{}", " + 1".repeat(MAX_NESTING))).err(), too_deep(4 * 255 + 2));
+        assert_eq!(format_expression(&format!("2{}", " ^ 2".repeat(5000))).err(), too_deep(4 * 256));
+
+        let chain = |n| format!("1{}", " ~".repeat(n));
+        assert!(rpn_to_infix(&chain(deepest)).is_ok());
+        assert_eq!(
+            rpn_to_infix(&chain(MAX_NESTING)),
+            Err(ParseError { kind: ParseErrorKind::NestingTooDeep, position: 2 * MAX_NESTING }),
+        );
+    }
+
+    #[test]
+    fn test_deep_trees_without_recursion() {
+        use ExprBuilder as E;
+
+        let expr = parse("-max(x, 2.5) + !true").unwrap();
+        assert_eq!(
+            format!("{:?}", expr),
+            "BinOp { op: Add, left: UnaryMinus(Call { name: \"max\", args: [Var(\"x\"), Num(2.5)] }), right: Not(Bool(true)) }",
+        );
+        assert_eq!(format!("{:.2?}", expr), "BinOp { op: Add, left: UnaryMinus(..), right: Not(..) }");
+        assert_eq!(format!("{:?}", parse("f()").unwrap()), "Call { name: \"f\", args: [] }");
+        assert_ne!(expr, parse("-max(x, 2.5) + !false").unwrap());
+        assert_ne!(parse("f(1)").unwrap(), parse("f(1, 2)").unwrap());
+
+        // Far too deep to recurse on, with a small stack to make sure.
+        let worker = std::thread::Builder::new().stack_size(64 * 1024).spawn(|| {
+            let chain = |leaf| {
+                let mut expr = E::var(leaf);
+                for n in 0..500_000 {
+                    expr = E::add(E::int(n), expr);
+                }
+                expr
+            };
+            let expr = chain("x");
+            let copy = expr.clone();
+            assert!(copy == expr);
+            assert!(chain("y") != expr);
+            assert_eq!(
+                format!("{:.3?}", copy),
+                "BinOp { op: Add, left: Int(499999), right: BinOp { op: Add, left: Int(499998), right: BinOp { op: Add, left: .., right: .. } } }",
+            );
+            drop(expr);
+            drop(copy);
+        });
+        worker.unwrap().join().unwrap();
+    }
+
+    #[test]
+    fn test_to_debug_tree() {
+        let expr = parse("max(-x, 2.0) * !(1 < y) - 3").unwrap();
+        assert_eq!(
+            expr.to_debug_tree(),
+            "BinaryOp Sub
+  BinaryOp Mul
+    Call max
+      UnaryMinus
+        Var x
+      Num 2.0
+    Not
+      BinaryOp Lt
+        Int 1
+        Var y
+  Int 3
+",
+        );
+        assert_eq!(parse("f()").unwrap().to_debug_tree(), "Call f\n");
+
+        // A tree deep enough that printing it recursively, in a stack this
+        // small, would overflow.
+        let worker = std::thread::Builder::new().stack_size(64 * 1024).spawn(|| {
+            let mut expr = Expr::Bool(true);
+            for _ in 0..1000 {
+                expr = Expr::Not(Box::new(expr));
+            }
+            let tree = expr.to_debug_tree();
+            assert_eq!(tree.lines().count(), 1001);
+            assert_eq!(tree.lines().last(), Some(format!("{}Bool true", "  ".repeat(1000)).as_str()));
+        });
+        worker.unwrap().join().unwrap();
+    }
+}
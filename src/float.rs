@@ -0,0 +1,65 @@
+//! The float functions `core` lacks, from `libm`, for builds without
+//! `std`. They have the names of the `std` methods, so code calls them the
+//! same way either way. If a dependency links `std` anyway, its methods
+//! win and `Float` goes unused.
+
+#[allow(dead_code)]
+pub(crate) trait Float {
+    fn sin(self: Self) -> Self;
+    fn cos(self: Self) -> Self;
+    fn tan(self: Self) -> Self;
+    fn sqrt(self: Self) -> Self;
+    fn ln(self: Self) -> Self;
+    fn log(self: Self, base: Self) -> Self;
+    fn floor(self: Self) -> Self;
+    fn ceil(self: Self) -> Self;
+    fn powf(self: Self, exponent: Self) -> Self;
+    fn hypot(self: Self, other: Self) -> Self;
+    fn fract(self: Self) -> Self;
+}
+
+impl Float for f64 {
+    fn sin(self: Self) -> Self {
+        return libm::sin(self);
+    }
+
+    fn cos(self: Self) -> Self {
+        return libm::cos(self);
+    }
+
+    fn tan(self: Self) -> Self {
+        return libm::tan(self);
+    }
+
+    fn sqrt(self: Self) -> Self {
+        return libm::sqrt(self);
+    }
+
+    fn ln(self: Self) -> Self {
+        return libm::log(self);
+    }
+
+    fn log(self: Self, base: Self) -> Self {
+        return libm::log(self) / libm::log(base);
+    }
+
+    fn floor(self: Self) -> Self {
+        return libm::floor(self);
+    }
+
+    fn ceil(self: Self) -> Self {
+        return libm::ceil(self);
+    }
+
+    fn powf(self: Self, exponent: Self) -> Self {
+        return libm::pow(self, exponent);
+    }
+
+    fn hypot(self: Self, other: Self) -> Self {
+        return libm::hypot(self, other);
+    }
+
+    fn fract(self: Self) -> Self {
+        return self - libm::trunc(self);
+    }
+}
@@ -0,0 +1,112 @@
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+
+#[cfg(not(any(feature = "std", test)))]
+#[allow(unused_imports)]
+use crate::float::Float;
+use crate::once::Once;
+
+/// A function expressions can call by name, e.g. `sqrt` in `"sqrt(2)"`.
+#[derive(Debug, Clone, Copy)]
+pub enum Function {
+    Unary(fn(f64) -> f64),
+    Binary(fn(f64, f64) -> f64),
+    /// A function of the given number of arguments, passed as a slice.
+    Nary(usize, fn(&[f64]) -> f64),
+}
+
+impl Function {
+    /// The number of arguments the function takes.
+    pub fn arity(self: &Self) -> usize {
+        match self {
+            Function::Unary(_) => 1,
+            Function::Binary(_) => 2,
+            Function::Nary(arity, _) => *arity,
+        }
+    }
+
+    /// Calls the function. `args` must hold exactly `arity()` values.
+    pub fn call(self: &Self, args: &[f64]) -> f64 {
+        match self {
+            Function::Unary(f) => f(args[0]),
+            Function::Binary(f) => f(args[0], args[1]),
+            Function::Nary(_, f) => f(args),
+        }
+    }
+}
+
+/// The functions an expression can call. `Functions::default()` holds the
+/// built-ins; add more with `insert`.
+#[derive(Debug, Clone)]
+pub struct Functions {
+    functions: BTreeMap<String, Function>,
+}
+
+impl Functions {
+    /// Creates an empty table, without the built-ins.
+    pub fn new() -> Self {
+        Functions {
+            functions: BTreeMap::new(),
+        }
+    }
+
+    /// Adds `function` under `name`, replacing any function already there.
+    pub fn insert(self: &mut Self, name: &str, function: Function) {
+        self.functions.insert(name.to_string(), function);
+    }
+
+    pub fn get(self: &Self, name: &str) -> Option<Function> {
+        self.functions.get(name).copied()
+    }
+
+    pub fn contains(self: &Self, name: &str) -> bool {
+        self.functions.contains_key(name)
+    }
+}
+
+/// The built-in functions. Trigonometry is in radians, and `log` takes
+/// the base first: `log(2, 8)` is `3`. `if(c, a, b)` is `a` if `c` is
+/// true, or nonzero, and `b` otherwise. `coalesce` and `is_null` are for
+/// `Value::Null`; numbers are never null, so they are the first argument
+/// and 0.
+impl Default for Functions {
+    fn default() -> Self {
+        let mut functions = Functions::new();
+        functions.insert("sin", Function::Unary(f64::sin));
+        functions.insert("cos", Function::Unary(f64::cos));
+        functions.insert("tan", Function::Unary(f64::tan));
+        functions.insert("sqrt", Function::Unary(f64::sqrt));
+        functions.insert("abs", Function::Unary(f64::abs));
+        functions.insert("ln", Function::Unary(f64::ln));
+        functions.insert("floor", Function::Unary(f64::floor));
+        functions.insert("ceil", Function::Unary(f64::ceil));
+        functions.insert("max", Function::Binary(f64::max));
+        functions.insert("min", Function::Binary(f64::min));
+        functions.insert("pow", Function::Binary(f64::powf));
+        functions.insert("hypot", Function::Binary(f64::hypot));
+        functions.insert("log", Function::Binary(|base, value| value.log(base)));
+        functions.insert("if", Function::Nary(3, |args| if args[0] != 0.0 { args[1] } else { args[2] }));
+        functions.insert("coalesce", Function::Binary(|value, _| value));
+        functions.insert("is_null", Function::Unary(|_| 0.0));
+        return functions;
+    }
+}
+
+/// The built-in table, built once for the functions that don't take one.
+pub(crate) fn default_functions() -> &'static Functions {
+    static DEFAULT: Once<Functions> = Once::new();
+    return DEFAULT.get_or_init(Functions::default);
+}
+
+/// The built-ins with `sin`, `cos` and `tan` in degrees, for
+/// `AngleUnit::Degrees`.
+pub(crate) fn degree_functions() -> &'static Functions {
+    static DEGREES: Once<Functions> = Once::new();
+    return DEGREES.get_or_init(|| {
+        let mut functions = Functions::default();
+        functions.insert("sin", Function::Unary(|x| x.to_radians().sin()));
+        functions.insert("cos", Function::Unary(|x| x.to_radians().cos()));
+        functions.insert("tan", Function::Unary(|x| x.to_radians().tan()));
+        return functions;
+    });
+}
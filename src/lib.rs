@@ -1,397 +1,5522 @@
-#[derive(Debug)]
-struct Stack<T> {
-    elements: Vec<T>
+// The crate's house style spells out `self: &Self` receivers, explicit
+// `return`s and `let ... else { break; }` loops; keep clippy from fighting it.
+#![allow(
+    clippy::needless_arbitrary_self_type,
+    clippy::needless_return,
+    clippy::while_let_loop,
+    clippy::is_digit_ascii_radix
+)]
+// Everything but the `HashMap` conveniences works without `std`; see the
+// `alloc` feature. Tests always have `std`, so they get those too.
+#![no_std]
+
+extern crate alloc;
+#[cfg(any(feature = "std", test))]
+extern crate std;
+
+#[cfg(not(any(feature = "std", feature = "alloc")))]
+compile_error!("expresso needs either the `std` or the `alloc` feature");
+
+use alloc::borrow::Cow;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+use core::iter::Peekable;
+use core::str::CharIndices;
+#[cfg(any(feature = "std", test))]
+use std::collections::HashMap;
+
+#[cfg(not(any(feature = "std", test)))]
+#[allow(unused_imports)]
+use float::Float;
+
+#[cfg(feature = "bigint")]
+mod bigint;
+mod config;
+mod environment;
+mod evaluator;
+mod expr;
+#[cfg(not(any(feature = "std", test)))]
+mod float;
+mod functions;
+mod once;
+mod operators;
+mod rational;
+pub mod reference;
+pub mod stack;
+mod symbolic;
+mod value;
+#[cfg(feature = "wasm")]
+mod wasm;
+
+use environment::NoVariables;
+use evaluator::check_calls;
+use functions::{default_functions, degree_functions};
+use operators::default_operators;
+
+#[cfg(feature = "bigint")]
+pub use bigint::{evaluate_big, evaluate_rpn_big};
+#[cfg(feature = "bigint")]
+pub use num_bigint::BigInt;
+pub use config::{AngleUnit, Division, EvaluatorConfig, Limit, MissingVarPolicy, OverflowMode};
+pub use environment::{Environment, BUILTIN_CONSTANTS};
+pub use evaluator::{CompiledExpr, Evaluator};
+pub use expr::{format_expression, format_rpn_to_infix, parse, rpn_to_infix, BinOpKind, Expr, ExprBuilder, PrettyPrinter};
+pub use functions::{Function, Functions};
+pub use operators::{Associativity, OperatorFn, OperatorInfo, OperatorTable};
+pub use rational::Rational;
+pub use stack::Stack;
+pub use symbolic::simplify;
+pub use value::Value;
+#[cfg(feature = "wasm")]
+pub use wasm::{js_evaluate, js_infix_to_rpn};
+
+/// Errors that can occur while converting or evaluating an expression.
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ExpressionError {
+    /// A token showed up somewhere it can't be handled, e.g. an
+    /// operator that isn't supported.
+    UnexpectedToken(char),
+    /// A `(` without a matching `)`, or the other way around.
+    MismatchedParentheses,
+    /// An operator needed more operands than were available.
+    EmptyStack,
+    /// The right-hand side of a `/` was zero.
+    DivisionByZero,
+    /// A character that isn't part of the expression grammar.
+    UnknownCharacter(char),
+    /// Operands were left over once the whole input was consumed,
+    /// e.g. `"1 2 3 +"`.
+    TooManyOperands,
+    /// Postfix input that `format_rpn_to_infix` couldn't make into one
+    /// expression has values left over, the first of them starting at the
+    /// given byte offset: 2 in `"1 2 3 +"`, where `2 3 +` starts.
+    UnconsumedInput(usize),
+    /// The input contained nothing to evaluate.
+    EmptyInput,
+    /// A float literal, such as `1.5` or `1e3`, where only integers are
+    /// taken: in integer mode, and in exact and big-integer arithmetic.
+    FloatInIntegerMode(f64),
+    /// The result was too large to represent.
+    Overflow,
+    /// An integer operation overflowed with `OverflowMode::Checked`. `op`
+    /// is the operator as written.
+    IntegerOverflow {
+        op: String,
+        left: i64,
+        right: i64,
+    },
+    /// A number's exponent has no digits, as in `1e` or `1e+`.
+    MissingExponent,
+    /// A hex or binary literal with no digits or with digits it can't
+    /// have, as in `0x` or `0b102`, or a literal whose `_` doesn't group
+    /// digits, as in `1_`.
+    InvalidLiteral(String),
+    /// The result is not a number, e.g. a fractional power of a negative.
+    NotANumber,
+    /// An integer was raised to a negative power, which has no integer
+    /// result.
+    NegativeExponent,
+    /// A variable was used that has no value in the binding map.
+    UndefinedVariable(String),
+    /// A function was called that isn't in the function table.
+    UnknownFunction(String),
+    /// A function call in integer mode had a fractional result, e.g.
+    /// `sqrt(2)`.
+    NonIntegerResult,
+    /// A shift by a negative amount or by 64 or more bits.
+    InvalidShift(i64),
+    /// A bitwise operator was used in floating point mode.
+    IntegerOnly(String),
+    /// `Evaluator::with_operator` was given a symbol that can't be an
+    /// operator, such as a letter or `(`.
+    InvalidOperator(char),
+    /// An operator or function was given operands of types it doesn't
+    /// take, e.g. `true + 3`. Only `evaluate` tells types apart.
+    TypeMismatch {
+        op: String,
+        operands: Vec<String>,
+    },
+    /// A function was called with the wrong number of arguments.
+    WrongArity {
+        name: String,
+        expected: usize,
+        got: usize,
+    },
+    /// An infix expression isn't well formed; see `ParseError`.
+    Parse(ParseError),
+    /// An expression went past one of the limits of an `EvaluatorConfig`.
+    LimitExceeded {
+        which: Limit,
+    },
+    /// An `EvaluatorConfig` has the same character as its decimal and its
+    /// argument separator, so `max(1,5)` could be read either way.
+    SeparatorConflict(char),
 }
 
-impl<T> Stack<T> {
-    fn new() -> Self {
-        Stack {
-            elements: Vec::new(),
+/// Errors returned by the evaluators share a type with the rest of the crate.
+pub type EvalError = ExpressionError;
+
+impl fmt::Display for ExpressionError {
+    fn fmt(self: &Self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExpressionError::UnexpectedToken(c) => write!(f, "unexpected token: {}", c),
+            ExpressionError::MismatchedParentheses => write!(f, "mismatched parentheses"),
+            ExpressionError::EmptyStack => write!(f, "not enough operands"),
+            ExpressionError::DivisionByZero => write!(f, "division by zero"),
+            ExpressionError::UnknownCharacter(c) => write!(f, "unknown character: {}", c),
+            ExpressionError::TooManyOperands => write!(f, "too many operands"),
+            ExpressionError::UnconsumedInput(position) => write!(f, "unconsumed input at position {}", position),
+            ExpressionError::EmptyInput => write!(f, "empty input"),
+            ExpressionError::FloatInIntegerMode(n) => write!(f, "float literal {} in integer mode", n),
+            ExpressionError::Overflow => write!(f, "overflow"),
+            ExpressionError::IntegerOverflow { op, left, right } => write!(f, "{} {} {} overflows", left, op, right),
+            ExpressionError::MissingExponent => write!(f, "missing exponent digits"),
+            ExpressionError::InvalidLiteral(literal) => write!(f, "invalid number literal: {}", literal),
+            ExpressionError::NotANumber => write!(f, "result is not a number"),
+            ExpressionError::NegativeExponent => write!(f, "negative exponent in integer arithmetic"),
+            ExpressionError::UndefinedVariable(name) => write!(f, "undefined variable: {}", name),
+            ExpressionError::UnknownFunction(name) => write!(f, "unknown function: {}", name),
+            ExpressionError::NonIntegerResult => write!(f, "result is not an integer"),
+            ExpressionError::InvalidShift(amount) => write!(f, "invalid shift amount: {}", amount),
+            ExpressionError::IntegerOnly(op) => write!(f, "{} only works on integers", op),
+            ExpressionError::InvalidOperator(c) => write!(f, "{} can't be an operator", c),
+            ExpressionError::TypeMismatch { op, operands } => {
+                write!(f, "cannot apply {} to {}", op, operands.join(" and "))
+            },
+            ExpressionError::WrongArity { name, expected, got } => {
+                write!(f, "{} takes {} argument(s) but was given {}", name, expected, got)
+            },
+            ExpressionError::Parse(err) => write!(f, "{}", err),
+            ExpressionError::LimitExceeded { which } => write!(f, "{} limit exceeded", which),
+            ExpressionError::SeparatorConflict(c) => write!(f, "{} is both the decimal and the argument separator", c),
         }
     }
+}
 
-    fn len(self: &Self) -> usize {
-        self.elements.len()
-    }
+impl core::error::Error for ExpressionError {}
 
-    fn push(self: &mut Self, element: T) {
-        self.elements.push(element);
-    }
+/// What is wrong with a malformed expression.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ParseErrorKind {
+    /// A `(` that is never closed, or a `)` that was never opened.
+    UnbalancedParenthesis,
+    /// Two binary operators in a row, as in `"1 + * 2"`, with the span of
+    /// each.
+    ConsecutiveOperators {
+        first_span: Span,
+        second_span: Span,
+    },
+    /// A binary operator with nothing to its left, at the start of the
+    /// expression or of a group or argument, as in `"* 2"` or `"(* 2)"`.
+    LeadingOperator {
+        span: Span,
+    },
+    /// An operand is missing, as in `"(1 +)"`.
+    MissingOperand,
+    /// Parentheses with nothing between them that aren't a call's, as in
+    /// `"1 + ()"`. `span` covers both.
+    EmptyParens {
+        span: Span,
+    },
+    /// A call's argument is missing, as in `"max(1,,2)"`. `call_span` runs
+    /// from the function's name to its `)`, and `arg_index` counts from 0.
+    EmptyArgument {
+        call_span: Span,
+        arg_index: usize,
+    },
+    /// Two operands with no operator between them, as in `"1 2"`, with the
+    /// span of each and what to do about it.
+    MissingOperator {
+        left_span: Span,
+        right_span: Span,
+        #[cfg_attr(feature = "serde", serde(skip_deserializing, default = "missing_operator_hint"))]
+        hint: &'static str,
+    },
+    /// The expression ends with an operator, as in `"1 +"`, with the
+    /// operator's span.
+    TrailingOperator {
+        span: Span,
+    },
+    /// A character that isn't part of the expression grammar.
+    UnexpectedCharacter(char),
+    /// An integer literal that doesn't fit in an `i64`.
+    NumberTooLarge,
+    /// An `e` after a number with no exponent digits, as in `"1e"`.
+    MissingExponent,
+    /// A hex or binary literal that isn't one, as in `"0xGG"`.
+    InvalidLiteral,
+    /// Operations or parentheses nested deeper than an `Expr` tree may be,
+    /// as in `"((((…))))"` a thousand times over.
+    NestingTooDeep,
+    /// An absolute value's `|` that is never closed, or whose group closes
+    /// before it does, as in `"|x"` or `"(|x)|"`.
+    UnbalancedBar,
+}
+
+/// The `hint` of every `ParseErrorKind::MissingOperator`.
+fn missing_operator_hint() -> &'static str {
+    return "insert an operator, or enable implicit multiplication";
+}
 
-    fn pop(self: &mut Self) -> T {
-        if let Some(num) = self.elements.pop() {
-            return num;
-        } else {
-            panic!("Stack is empty. Nothing to pop!");
+/// A syntax error in an expression. `position` is the byte offset
+/// into the input where the problem was detected.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    pub position: usize,
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(self: &Self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseErrorKind::UnbalancedParenthesis => write!(f, "unbalanced parenthesis"),
+            ParseErrorKind::ConsecutiveOperators { .. } => write!(f, "consecutive operators"),
+            ParseErrorKind::LeadingOperator { .. } => write!(f, "leading operator"),
+            ParseErrorKind::MissingOperand => write!(f, "missing operand"),
+            ParseErrorKind::EmptyParens { .. } => write!(f, "empty parentheses"),
+            ParseErrorKind::EmptyArgument { .. } => write!(f, "empty argument"),
+            ParseErrorKind::MissingOperator { .. } => write!(f, "missing operator"),
+            ParseErrorKind::TrailingOperator { .. } => write!(f, "trailing operator"),
+            ParseErrorKind::UnexpectedCharacter(c) => write!(f, "unexpected character: {}", c),
+            ParseErrorKind::NumberTooLarge => write!(f, "number too large"),
+            ParseErrorKind::MissingExponent => write!(f, "missing exponent digits"),
+            ParseErrorKind::InvalidLiteral => write!(f, "invalid number literal"),
+            ParseErrorKind::NestingTooDeep => write!(f, "nesting too deep"),
+            ParseErrorKind::UnbalancedBar => write!(f, "unbalanced absolute value bar"),
         }
     }
+}
 
-    fn peek(self: &Self) -> Option<&T> {
-        self.elements.last()
+impl fmt::Display for ParseError {
+    fn fmt(self: &Self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at position {}", self.kind, self.position)
     }
 }
 
-struct Operator;
+impl core::error::Error for ParseError {}
 
-impl Operator {
-    fn is_valid(op: char) -> bool {
-        match op {
-            '+' | '-' | '/' | '*' | '^' => true,
-            _ => false,
-        }
+impl From<ParseError> for ExpressionError {
+    fn from(err: ParseError) -> Self {
+        return ExpressionError::Parse(err);
     }
+}
 
-    /// Get left or right associativity
-    /// `true` means left associative
-    /// `false` means right associative
-    fn get_associativity(op: char) -> bool {
-        match op {
-            '+' | '-' | '/' | '*' => true,
-            '^' => false,
-            _ => panic!("Unsupported operator: {}", op),
-        }
-    }
+/// Symbol used for unary minus on the operator stack and in RPN output,
+/// where it can't share `-` with subtraction: `"-(1 + 2)"` becomes
+/// `"1 2 + ~"`.
+const UNARY_MINUS: char = '~';
+
+/// Symbol used for logical not. Like unary minus it is prefix, but it needs
+/// no stand-in. `&&` and `||` are `∧` and `∨`.
+const NOT: char = '!';
 
-    /// Get precedence score of the operator
-    fn get_precedence(op: &char) -> u8 {
-        match op {
-            '+' | '-' => 2,
-            '/' | '*' => 3,
-            '^' => 4,
-            _ => panic!("Unsupported operator: {}", op),
+/// Where an evaluation looks up variables, functions and operators, and
+/// the settings of `evaluate_with_config`, if that is what it is.
+struct Context<'a> {
+    variables: &'a dyn Environment,
+    functions: &'a Functions,
+    operators: &'a OperatorTable,
+    config: Option<&'a EvaluatorConfig>,
+}
+
+impl Context<'static> {
+    /// No variables, and the built-in functions and operators.
+    fn builtin() -> Self {
+        Context {
+            variables: &NoVariables,
+            functions: default_functions(),
+            operators: default_operators(),
+            config: None,
         }
     }
 }
 
+/// Evaluates a postfix (RPN) expression such as `"1 2 +"`. Negation is
+/// written `~`, as produced by `infix_to_rpn`, and a `-` right before a
+/// number is its sign, so `"3 -4 +"` is -1. Literals with a fraction or an
+/// exponent are `FloatInIntegerMode`; `evaluate_rpn_f64` takes them.
+#[deprecated(note = "use `evaluate_rpn_value`, which takes integers and floats alike and keeps them apart")]
+pub fn evaluate_rpn(input: String) -> Result<i64, EvalError> {
+    return evaluate_rpn_with_ops(input, default_operators());
+}
 
-pub fn evaluate_rpn(input: String) -> u32 {
-    let mut st: Stack<u32> = Stack::new();
+/// Same as `evaluate_rpn`, but with the operators in `operators`.
+pub fn evaluate_rpn_with_ops(input: String, operators: &OperatorTable) -> Result<i64, EvalError> {
+    let context = Context { operators, ..Context::builtin() };
+    return evaluate_rpn_tokens_generic::<i64>(&tokenize_rpn(&input, operators)?, &context);
+}
 
-    let mut input_chars = input.chars();
+/// Floating point `evaluate_rpn` that looks identifiers up in `variables`,
+/// e.g. `"x 2 *"`.
+#[cfg(any(feature = "std", test))]
+pub fn evaluate_rpn_with_vars(input: String, variables: &HashMap<String, f64>) -> Result<f64, EvalError> {
+    return evaluate_rpn_with(input, variables, default_functions());
+}
 
-    loop {
-        let Some(mut current_char) = input_chars.next() else { break; };
+/// Same as `evaluate_rpn_with_vars`, but calls functions from `functions`
+/// instead of the built-ins. A function is written after its argument:
+/// `"16 sqrt"`.
+pub fn evaluate_rpn_with(input: String, variables: &dyn Environment, functions: &Functions) -> Result<f64, EvalError> {
+    let context = Context { variables, functions, operators: default_operators(), config: None };
+    return evaluate_rpn_tokens_generic::<f64>(&tokenize_rpn(&input, context.operators)?, &context);
+}
 
-        if current_char.is_whitespace() {
-            continue;
-        }
+/// Same as `evaluate_rpn`, but panics on malformed input instead of
+/// returning an error.
+pub fn evaluate_rpn_unchecked(input: String) -> i64 {
+    match evaluate_rpn_with_ops(input, default_operators()) {
+        Ok(result) => result,
+        Err(e) => panic!("Failed to evaluate RPN expression: {}", e),
+    }
+}
 
-        if current_char.is_digit(10) {
-            let mut num: String = String::new();
-            while current_char.is_digit(10) {
-                num += &current_char.to_string();
-                current_char = input_chars.next().unwrap();
-            }
-            st.push(num.parse::<u32>().unwrap());
-            continue;
-        }
+/// Floating point version of `evaluate_rpn`. Accepts decimal literals
+/// such as `"2.5 4 *"`.
+#[deprecated(note = "use `evaluate_rpn_value`, which takes integers and floats alike and keeps them apart")]
+pub fn evaluate_rpn_f64(input: String) -> Result<f64, EvalError> {
+    return evaluate_rpn_tokens_generic::<f64>(&tokenize_rpn(&input, default_operators())?, &Context::builtin());
+}
 
-        // Otherwise, we're probably dealing with an operator.
-        let right: u32 = st.pop();
-        let left: u32 = st.pop();
+/// Evaluates tokens that are already in postfix order. `Token::Minus`
+/// is always subtraction here and `Token::UnaryMinus` negation.
+pub fn evaluate_rpn_tokens(tokens: &[Token]) -> Result<i64, EvalError> {
+    return evaluate_rpn_tokens_generic::<i64>(tokens, &Context::builtin());
+}
+
+fn evaluate_rpn_tokens_generic<N: Numeric>(tokens: &[Token], context: &Context) -> Result<N, EvalError> {
+    return evaluate_postfix(tokens, context, true, &mut Stack::with_capacity(tokens.len()));
+}
+
+/// Evaluates postfix `tokens` on `st`, which starts out cleared. With
+/// `names_call`, any name with a function by that name is a call, as
+/// postfix input has no `(` to mark one. Postfix converted from infix
+/// marks calls as `Token::Function` instead, so `names_call` is false.
+fn evaluate_postfix<N: Numeric>(
+    tokens: &[Token],
+    context: &Context,
+    names_call: bool,
+    st: &mut Stack<N>,
+) -> Result<N, EvalError> {
+    let functions = context.functions;
+    st.clear();
 
-        match current_char {
-            '+' => st.push(right + left),
-            '-' => st.push(right - left),
-            '*' => st.push(right * left),
-            '/' => st.push(right / left),
-            '^' => st.push(right ^ left),
-            _ => panic!("Unexpected operator: {}", current_char),
+    for token in tokens {
+        match token {
+            Token::Number(n) => st.push(N::from_integer(*n)?),
+            Token::Float(f) => st.push(N::from_float(*f)?),
+            Token::Bool(b) => st.push(N::from_bool(*b)?),
+            // The function's arity says how many operands it takes.
+            Token::Ident(name) if names_call && functions.contains(name) => {
+                let result = call_postfix(functions, name, st)?;
+                st.push(result);
+            },
+            Token::Function(name) if functions.contains(name) => {
+                let result = call_postfix(functions, name, st)?;
+                st.push(result);
+            },
+            Token::Function(name) => return Err(ExpressionError::UnknownFunction(name.clone())),
+            Token::Ident(name) => st.push(lookup_variable(context, name)?),
+            Token::UnaryMinus => {
+                let operand = st.pop().ok_or(ExpressionError::EmptyStack)?;
+                st.push(operand.negate()?);
+            },
+            Token::Not => {
+                let operand = st.pop().ok_or(ExpressionError::EmptyStack)?;
+                st.push(operand.not()?);
+            },
+            Token::ParenLeft => return Err(ExpressionError::UnexpectedToken('(')),
+            Token::ParenRight => return Err(ExpressionError::UnexpectedToken(')')),
+            Token::Comma => return Err(ExpressionError::UnexpectedToken(',')),
+            Token::Chain(ops) => {
+                let mut operands: Vec<N> = Vec::new();
+                for _ in 0..=ops.len() {
+                    operands.push(st.pop().ok_or(ExpressionError::EmptyStack)?);
+                }
+                operands.reverse();
+                st.push(apply_chain(context, ops, &operands)?);
+            },
+            _ => {
+                let Some(op) = token.operator_symbol() else { unreachable!() };
+                let right = st.pop().ok_or(ExpressionError::EmptyStack)?;
+                let left = st.pop().ok_or(ExpressionError::EmptyStack)?;
+                st.push(apply_operator(context, op, left, right)?);
+            },
         }
     }
 
-    return st.pop();
+    match st.len() {
+        0 => return Err(ExpressionError::EmptyInput),
+        1 => return st.pop().ok_or(ExpressionError::EmptyStack),
+        _ => return Err(ExpressionError::TooManyOperands),
+    }
 }
 
-/// Converts an infix expression to a postfix expression
-/// using the Shunting Yard algorithm.
-///
-/// Reference: https://en.wikipedia.org/wiki/Shunting_yard_algorithm
-///
-/// `input` should be a infix expression.
-pub fn infix_to_rpn(input: String) -> String {
-    let mut st: Stack<char> = Stack::new();
-    let mut output: String = String::new();
+/// Calls `name` on as many operands from the top of `st` as it takes.
+fn call_postfix<N: Numeric>(functions: &Functions, name: &str, st: &mut Stack<N>) -> Result<N, ExpressionError> {
+    let arity = functions.get(name).map_or(0, |f| f.arity());
+    let mut args: Vec<N> = Vec::new();
+    for _ in 0..arity {
+        args.push(st.pop().ok_or(ExpressionError::EmptyStack)?);
+    }
+    args.reverse();
+    return call_function(functions, name, &args);
+}
 
-    let mut input_chars = input.chars();
+/// Tokenizes postfix input. Unlike infix input there is no context to
+/// tell a negation from a subtraction, so `-` subtracts and `~` negates,
+/// except that a `-` right before a number is its sign, as in `"-5 3 +"`.
+fn tokenize_rpn(input: &str, operators: &OperatorTable) -> Result<Vec<Token>, ExpressionError> {
+    let tokens = tokenize_rpn_spanned(input, operators)?;
+    return Ok(tokens.into_iter().map(|t| t.token).collect());
+}
+
+/// Same as `tokenize_rpn`, but keeps where each token came from.
+fn tokenize_rpn_spanned(input: &str, operators: &OperatorTable) -> Result<Vec<SpannedToken>, TokenizeError> {
+    // There are never more tokens than bytes.
+    let mut tokens: Vec<SpannedToken> = Vec::with_capacity(input.len());
+
+    let mut input_chars = input.char_indices().peekable();
 
     loop {
-        let Some(input_char) = input_chars.next() else { break; };
+        let Some((start, current_char)) = input_chars.next() else { break; };
 
-        if input_char.is_whitespace() {
+        if current_char.is_whitespace() {
             continue;
         }
 
-        if input_char.is_digit(10) {
-            output += &input_char.to_string();
-            output += " ";
+        if is_number_start(input, start, Notation::ASCII) {
+            let (token, end) = read_number(input, start, &mut input_chars, Notation::ASCII)?;
+            tokens.push(SpannedToken { token, span: Span { start, end } });
             continue;
         }
-        
-        if input_char == '(' {
-            st.push(input_char);
+
+        if is_identifier_start(current_char) {
+            let (token, end) = read_identifier(input, start, &mut input_chars);
+            tokens.push(SpannedToken { token, span: Span { start, end } });
             continue;
         }
 
-        if input_char == ')' {
-            let mut top = st.peek();
-            while top != Some(&'(') {
-                assert_ne!(st.len(), 0);
-                output += &st.pop().to_string();
-                output += " ";
-                top = st.peek();
-            }
-            assert_eq!(st.peek(), Some(&'('));
-            st.pop();
+        if let Some((token, end)) = read_multi_char_operator(current_char, start, &mut input_chars) {
+            tokens.push(SpannedToken { token, span: Span { start, end } });
             continue;
         }
 
-        if Operator::is_valid(input_char) {
-            let o1 = input_char;
-            let mut o2 = st.peek();
+        let digits_start = start + current_char.len_utf8();
+        if matches!(current_char, '-' | '−') && is_number_start(input, digits_start, Notation::ASCII) {
+            input_chars.next();
+            let (token, end) = read_number(input, digits_start, &mut input_chars, Notation::ASCII)?;
+            let token = match token {
+                Token::Number(n) => Token::Number(-n),
+                Token::Float(f) => Token::Float(-f),
+                token => token,
+            };
+            tokens.push(SpannedToken { token, span: Span { start, end } });
+            continue;
+        }
 
-            let o1_prec = Operator::get_precedence(&o1);
-            let o2_prec = Operator::get_precedence(&o2.unwrap_or(&'+'));
+        if current_char == '[' && !operators.is_custom('[') {
+            let (token, end) = read_chain(start, &mut input_chars)?;
+            tokens.push(SpannedToken { token, span: Span { start, end } });
+            continue;
+        }
 
-            while o2.is_some() && o2 != Some(&'(')
-                && (o2_prec > o1_prec || (o2_prec == o1_prec && Operator::get_associativity(o1) == true))
+        let token = match current_char {
+            '+' => Token::Plus,
+            '-' | '−' => Token::Minus,
+            '*' | '×' => Token::Asterisk,
+            '/' | '÷' => Token::Slash,
+            '%' => Token::Percent,
+            '^' => Token::Caret,
+            UNARY_MINUS => Token::UnaryMinus,
+            c if operators.is_custom(c) => Token::Operator(c),
+            _ => return Err(TokenizeError {
+                error: ExpressionError::UnknownCharacter(current_char),
+                span: char_span(start, current_char),
+            }),
+        };
+        tokens.push(SpannedToken { token, span: char_span(start, current_char) });
+    }
 
-            {
-                output += &st.pop().to_string();
-                output += " ";
-                o2 = st.peek();
-            }
+    return Ok(tokens);
+}
 
-            st.push(o1);
+/// Reads the rest of a `Token::Chain` in RPN, such as `[< <=]`, from
+/// just after its `[` at `start`. Returns it with where it ends.
+fn read_chain(start: usize, input_chars: &mut Peekable<CharIndices>) -> Result<(Token, usize), TokenizeError> {
+    let mut ops: Vec<char> = Vec::new();
+    loop {
+        let Some((position, c)) = input_chars.next() else {
+            return Err(TokenizeError { error: ExpressionError::UnexpectedToken('['), span: char_span(start, '[') });
+        };
+        if c.is_whitespace() {
+            continue;
         }
+        if c == ']' && ops.len() >= 2 {
+            return Ok((Token::Chain(ops), position + 1));
+        }
+        let op = read_multi_char_operator(c, position, input_chars).and_then(|(token, _)| token.operator_symbol());
+        let Some(op) = op.filter(|op| is_comparison(*op)) else {
+            return Err(TokenizeError { error: ExpressionError::UnknownCharacter(c), span: char_span(position, c) });
+        };
+        ops.push(op);
+    }
+}
+
+
+// ============== NUMERIC TYPES BELOW =================
+/// A number type the evaluators can work in. Implemented for `i64`
+/// (integer mode), `f64` (floating point mode), `Value`, `Rational`, and
+/// `BigInt` with the `bigint` feature.
+trait Numeric: Clone {
+    fn from_integer(n: i64) -> Result<Self, ExpressionError>;
+
+    fn from_float(f: f64) -> Result<Self, ExpressionError>;
+
+    /// Numbers stand for booleans as 1 and 0, like the comparisons give.
+    fn from_bool(b: bool) -> Result<Self, ExpressionError> {
+        return Self::from_integer(b as i64);
     }
 
-    while st.len() != 0 {
-        assert_ne!(st.peek(), Some(&'('));
-        output += &st.pop().to_string();
-        output += " ";
+    fn negate(self: Self) -> Result<Self, ExpressionError>;
+
+    /// Logical not.
+    fn not(self: Self) -> Result<Self, ExpressionError>;
+
+    /// Applies the binary operator `op`.
+    fn apply(op: char, left: Self, right: Self) -> Result<Self, ExpressionError>;
+
+    /// Calls `function`, which always works in floating point. `name` is
+    /// what it was called as, for errors.
+    fn call(name: &str, function: Function, args: &[Self]) -> Result<Self, ExpressionError>;
+
+    /// Applies the custom operator `op`, which also works in floating point.
+    fn apply_custom(op: char, apply: OperatorFn, left: Self, right: Self) -> Result<Self, ExpressionError>;
+
+    /// The number as a float, for the settings of `EvaluatorConfig`, or
+    /// `None` if it isn't a number.
+    fn as_number(self: &Self) -> Option<f64>;
+
+    /// The number if it is an `i64` integer, for `OverflowMode`.
+    fn as_integer(self: &Self) -> Option<i64> {
+        return None;
     }
 
-    // Strip off the last space that remains.
-    return output[..output.len() - 1].to_string();
+    /// `value`, for `MissingVarPolicy`. Only a `Value` can be null, but
+    /// only `evaluate_with_config`, which works in them, has a policy.
+    fn from_value(value: Value) -> Result<Self, ExpressionError> {
+        match value {
+            Value::Int(n) => return Self::from_integer(n),
+            Value::Float(f) => return Self::from_float(f),
+            Value::Bool(b) => return Self::from_bool(b),
+            Value::Null => return Err(ExpressionError::TypeMismatch { op: "null".to_string(), operands: Vec::new() }),
+        }
+    }
 }
 
+impl Numeric for i64 {
+    fn from_integer(n: i64) -> Result<Self, ExpressionError> {
+        Ok(n)
+    }
 
+    fn from_float(f: f64) -> Result<Self, ExpressionError> {
+        Err(ExpressionError::FloatInIntegerMode(f))
+    }
 
-fn evaluator_add_to_output(output: &mut Vec<u32>, n: u32) {
-    output.push(n);
+    fn negate(self: Self) -> Result<Self, ExpressionError> {
+        self.checked_neg().ok_or(ExpressionError::Overflow)
+    }
+
+    fn not(self: Self) -> Result<Self, ExpressionError> {
+        Ok((self == 0) as i64)
+    }
+
+    fn apply(op: char, left: Self, right: Self) -> Result<Self, ExpressionError> {
+        let result = match op {
+            '+' => left.checked_add(right),
+            '-' => left.checked_sub(right),
+            '*' => left.checked_mul(right),
+            '/' => {
+                if right == 0 {
+                    return Err(ExpressionError::DivisionByZero);
+                }
+                left.checked_div(right)
+            },
+            // The remainder takes the sign of `left`, so `-7 % 3` is `-1`.
+            '%' => {
+                if right == 0 {
+                    return Err(ExpressionError::DivisionByZero);
+                }
+                left.checked_rem(right)
+            },
+            '^' => left.checked_pow(integer_exponent(right)?),
+            '&' => Some(left & right),
+            '|' => Some(left | right),
+            '⊕' => Some(left ^ right),
+            // Bits shifted out are lost, and `>>` keeps the sign.
+            '«' => Some(left << shift_amount(right)?),
+            '»' => Some(left >> shift_amount(right)?),
+            // Any nonzero number is true.
+            '∧' => Some((left != 0 && right != 0) as i64),
+            '∨' => Some((left != 0 || right != 0) as i64),
+            _ => match compare(op, left, right) {
+                Some(result) => Some(result as i64),
+                None => return Err(ExpressionError::UnexpectedToken(op)),
+            },
+        };
+
+        return result.ok_or(ExpressionError::Overflow);
+    }
+
+    /// The result has to be a whole number, so `abs(-3)` works but
+    /// `sqrt(2)` doesn't.
+    fn call(_name: &str, function: Function, args: &[Self]) -> Result<Self, ExpressionError> {
+        let args: Vec<f64> = args.iter().map(|arg| *arg as f64).collect();
+        return integer_result(function.call(&args));
+    }
+
+    fn apply_custom(_op: char, apply: OperatorFn, left: Self, right: Self) -> Result<Self, ExpressionError> {
+        return integer_result(apply(left as f64, right as f64)?);
+    }
+
+    fn as_number(self: &Self) -> Option<f64> {
+        Some(*self as f64)
+    }
+
+    fn as_integer(self: &Self) -> Option<i64> {
+        Some(*self)
+    }
+}
+
+/// Converts a floating point result back to integer mode, which only
+/// works if it is a whole number.
+fn integer_result(result: f64) -> Result<i64, ExpressionError> {
+    let result = check_float(result)?;
+    if result.fract() != 0.0 {
+        return Err(ExpressionError::NonIntegerResult);
+    }
+    if result < i64::MIN as f64 || result >= i64::MAX as f64 {
+        return Err(ExpressionError::Overflow);
+    }
+    return Ok(result as i64);
 }
 
-fn evaluator_handle_pop(st: &mut Stack<char>, output: &mut Vec<u32>) -> Option<u32> {
-    let op = st.pop();
+impl Numeric for f64 {
+    fn from_integer(n: i64) -> Result<Self, ExpressionError> {
+        Ok(n as f64)
+    }
+
+    fn from_float(f: f64) -> Result<Self, ExpressionError> {
+        Ok(f)
+    }
 
-    if op == '(' {
-        return None;
+    fn negate(self: Self) -> Result<Self, ExpressionError> {
+        Ok(-self)
+    }
+
+    fn not(self: Self) -> Result<Self, ExpressionError> {
+        Ok((self == 0.0) as u8 as f64)
+    }
+
+    /// Division by zero, overflow to infinity and NaN are all errors.
+    fn apply(op: char, left: Self, right: Self) -> Result<Self, ExpressionError> {
+        let result = match op {
+            '+' => left + right,
+            '-' => left - right,
+            '*' => left * right,
+            '/' => {
+                if right == 0.0 {
+                    return Err(ExpressionError::DivisionByZero);
+                }
+                left / right
+            },
+            '%' => {
+                if right == 0.0 {
+                    return Err(ExpressionError::DivisionByZero);
+                }
+                left % right
+            },
+            '^' => left.powf(right),
+            '&' | '|' | '⊕' | '«' | '»' => {
+                return Err(ExpressionError::IntegerOnly(Token::from_operator_symbol(op).to_string()));
+            },
+            '∧' => (left != 0.0 && right != 0.0) as u8 as f64,
+            '∨' => (left != 0.0 || right != 0.0) as u8 as f64,
+            _ => match compare(op, left, right) {
+                Some(result) => result as u8 as f64,
+                None => return Err(ExpressionError::UnexpectedToken(op)),
+            },
+        };
+
+        return check_float(result);
+    }
+
+    fn call(_name: &str, function: Function, args: &[Self]) -> Result<Self, ExpressionError> {
+        return check_float(function.call(args));
     }
 
-    let right = output.pop().unwrap();
-    let left = output.pop().unwrap();
+    fn apply_custom(_op: char, apply: OperatorFn, left: Self, right: Self) -> Result<Self, ExpressionError> {
+        return check_float(apply(left, right)?);
+    }
+
+    fn as_number(self: &Self) -> Option<f64> {
+        Some(*self)
+    }
+}
 
+fn shift_amount(amount: i64) -> Result<u32, ExpressionError> {
+    if !(0..64).contains(&amount) {
+        return Err(ExpressionError::InvalidShift(amount));
+    }
+    return Ok(amount as u32);
+}
+
+/// Applies a comparison operator, or `None` if `op` isn't one. On the
+/// operator stack `<=`, `>=`, `==` and `!=` are `≤`, `≥`, `=` and `≠`.
+fn compare<N: PartialOrd>(op: char, left: N, right: N) -> Option<bool> {
     match op {
-        '+' => Some(left + right),
-        '-' => Some(left - right),
-        '*' => Some(left * right),
-        '/' => Some(left / right),
-        '^' => Some(left.pow(right)),
-        _ => panic!("Unexpected operator: {}", op),
+        '<' => Some(left < right),
+        '>' => Some(left > right),
+        '≤' => Some(left <= right),
+        '≥' => Some(left >= right),
+        '=' => Some(left == right),
+        '≠' => Some(left != right),
+        _ => None,
+    }
+}
+
+/// Turns NaN and infinite results into errors.
+fn check_float(result: f64) -> Result<f64, ExpressionError> {
+    if result.is_nan() {
+        return Err(ExpressionError::NotANumber);
     }
+    if result.is_infinite() {
+        return Err(ExpressionError::Overflow);
+    }
+    return Ok(result);
 }
 
+/// Converts an integer exponent to the `u32` that `checked_pow` expects.
+fn integer_exponent(exponent: i64) -> Result<u32, ExpressionError> {
+    if exponent < 0 {
+        return Err(ExpressionError::NegativeExponent);
+    }
+    return u32::try_from(exponent).map_err(|_| ExpressionError::Overflow);
+}
 
-/// Same as Shunting Yard Algorithm, but also evaluates the expression
-/// on-the-fly. Uses `Tokenizer`.
-pub fn sy_evaulate(input: String) -> u32 {
-    let mut st: Stack<char> = Stack::new();
-    let mut output: Vec<u32> = Vec::new();
+/// Formats a floating point result for display, hiding representation
+/// artifacts: `0.1 + 0.2` prints as `0.3` rather than
+/// `0.30000000000000004`.
+pub fn format_f64(value: f64) -> String {
+    // 15 significant digits is as many as an f64 reliably holds.
+    let rounded: f64 = format!("{:.14e}", value).parse().unwrap_or(value);
+    return rounded.to_string();
+}
+// ============== NUMERIC TYPES ABOVE =================
 
-    let mut input_chars = input.chars();
 
-    loop {
-        let Some(input_char) = input_chars.next() else { break; };
+/// Converts an infix expression to a postfix expression
+/// using the Shunting Yard algorithm.
+///
+/// Reference: <https://en.wikipedia.org/wiki/Shunting_yard_algorithm>
+///
+/// `input` should be a infix expression. Variables pass through as they
+/// are, and a call is the function's name after its arguments, so
+/// `"max(a, b) * 2"` becomes `"a b max 2 *"`. How many arguments a call
+/// takes isn't written: whatever reads the RPN back, `evaluate_rpn_with`
+/// or `rpn_to_infix`, looks it up in its function table.
+pub fn infix_to_rpn(input: String) -> Result<String, ExpressionError> {
+    return infix_to_rpn_with_ops(input, default_operators());
+}
 
-        if input_char.is_whitespace() {
-            continue;
+/// Same as `infix_to_rpn`, but with the precedence and associativity of
+/// the operators in `operators`.
+pub fn infix_to_rpn_with_ops(input: String, operators: &OperatorTable) -> Result<String, ExpressionError> {
+    return shunting_yard_to_rpn::<i64>(&tokenize_infix(&input, operators)?, operators, input.len());
+}
+
+/// Same as `infix_to_rpn`, but decimal literals like `3.14` are kept as a
+/// single token.
+pub fn infix_to_rpn_f64(input: String) -> Result<String, ExpressionError> {
+    return shunting_yard_to_rpn::<f64>(&tokenize_infix(&input, default_operators())?, default_operators(), input.len());
+}
+
+/// Reorders infix tokens, as produced by `Tokenizer::tokenize`, into
+/// postfix order for `evaluate_rpn_tokens`. Decimal literals are kept.
+/// The syntax is checked as in `sy_evaluate_tokens`.
+pub fn infix_to_rpn_tokens(tokens: &[Token]) -> Result<Vec<Token>, ExpressionError> {
+    check_token_syntax(tokens)?;
+    return shunting_yard::<f64>(tokens, default_operators());
+}
+
+/// The precedence of `token`, an operator or function on the Shunting Yard
+/// operator stack, where the other entries are `(`s.
+fn stack_precedence(token: &Token, operators: &OperatorTable) -> Result<u8, ExpressionError> {
+    match token {
+        Token::Function(_) => return Ok(OperatorTable::FUNCTION_PRECEDENCE),
+        Token::Chain(ops) => return operators.get_precedence(ops[ops.len() - 1]),
+        _ => {},
+    }
+    let Some(op) = token.operator_symbol() else { unreachable!() };
+    return operators.get_precedence(op);
+}
+
+/// The RPN of infix `tokens` read from `input_len` bytes.
+fn shunting_yard_to_rpn<N: Numeric>(
+    tokens: &[Token],
+    operators: &OperatorTable,
+    input_len: usize,
+) -> Result<String, ExpressionError> {
+    // RPN drops the parentheses but may add a space between tokens.
+    return Ok(rpn_string(&shunting_yard::<N>(tokens, operators)?, input_len + tokens.len()));
+}
+
+/// Joins postfix tokens, as produced by `infix_to_rpn_tokens`, into the RPN
+/// string `infix_to_rpn` gives, such as `1 2 + 3 *`.
+pub fn tokens_to_rpn_string(tokens: &[Token]) -> String {
+    return rpn_string(tokens, 2 * tokens.len());
+}
+
+/// `tokens_to_rpn_string`, in a string with room for `capacity` bytes.
+fn rpn_string(tokens: &[Token], capacity: usize) -> String {
+    let mut rpn = String::with_capacity(capacity);
+    for (i, token) in tokens.iter().enumerate() {
+        if i > 0 {
+            rpn.push(' ');
+        }
+        write_rpn_text(&mut rpn, token);
+    }
+    return rpn;
+}
+
+/// The token as written in RPN, where negation is `~`.
+fn rpn_text(token: &Token) -> String {
+    let mut text = String::new();
+    write_rpn_text(&mut text, token);
+    return text;
+}
+
+/// Appends `rpn_text(token)` to `out`.
+fn write_rpn_text(out: &mut String, token: &Token) {
+    use fmt::Write;
+
+    // Writing to a `String` can't fail.
+    let _ = match token {
+        Token::Float(f) => write!(out, "{}", f),
+        Token::UnaryMinus => write!(out, "{}", UNARY_MINUS),
+        _ => write!(out, "{}", token),
+    };
+}
+
+/// Whether binary operator `o1` has to pop `top`, the top of the operator
+/// stack, before it is pushed: `top` binds tighter, or as tightly and `o1`
+/// is left associative. Callers ask again after every pop.
+fn pops_top(o1: char, top: Option<&Token>, operators: &OperatorTable) -> Result<bool, ExpressionError> {
+    // `(` has no precedence; it only ever stops the popping.
+    let Some(top) = top.filter(|entry| **entry != Token::ParenLeft) else {
+        return Ok(false);
+    };
+    let o1_prec = operators.get_precedence(o1)?;
+    let o2_prec = stack_precedence(top, operators)?;
+    let o1_left = operators.get_associativity(o1)? == Associativity::Left;
+    return Ok(o2_prec > o1_prec || (o2_prec == o1_prec && o1_left));
+}
+
+/// Reorders infix tokens into postfix order. Numbers are checked against
+/// `N`, so integer mode rejects decimal literals.
+fn shunting_yard<N: Numeric>(tokens: &[Token], operators: &OperatorTable) -> Result<Vec<Token>, ExpressionError> {
+    let mut st: Stack<Token> = Stack::with_capacity(tokens.len());
+    let mut output: Vec<Token> = Vec::with_capacity(tokens.len());
+
+    for token in tokens {
+        match token {
+            Token::Number(n) => {
+                N::from_integer(*n)?;
+                output.push(token.clone());
+                continue;
+            },
+            Token::Float(f) => {
+                N::from_float(*f)?;
+                output.push(token.clone());
+                continue;
+            },
+            Token::Bool(_) | Token::Ident(_) => {
+                output.push(token.clone());
+                continue;
+            },
+            Token::Function(name) => {
+                st.push(Token::Function(name.clone()));
+                continue;
+            },
+            _ => {},
         }
 
-        if input_char.is_digit(10) {
-            evaluator_add_to_output(&mut output, input_char.to_digit(10).unwrap());
+        if *token == Token::ParenLeft {
+            st.push(Token::ParenLeft);
             continue;
         }
-        
-        if input_char == '(' {
-            st.push(input_char);
+
+        if *token == Token::Comma {
+            // Finish the argument so far; the call's `(` stays open.
+            while st.peek() != Some(&Token::ParenLeft) {
+                let Some(entry) = st.pop() else {
+                    return Err(ExpressionError::UnexpectedToken(','));
+                };
+                output.push(entry);
+            }
             continue;
         }
 
-        if input_char == ')' {
-            let mut top = st.peek();
-            while top != Some(&'(') {
-                assert_ne!(st.len(), 0);
-                let res = evaluator_handle_pop(&mut st, &mut output);
-                evaluator_add_to_output(&mut output, res.unwrap());
-                top = st.peek();
+        if *token == Token::ParenRight {
+            while st.peek() != Some(&Token::ParenLeft) {
+                let Some(entry) = st.pop() else {
+                    return Err(ExpressionError::MismatchedParentheses);
+                };
+                output.push(entry);
             }
-            assert_eq!(st.peek(), Some(&'('));
             st.pop();
+            // A function right below the `(` was waiting for this argument.
+            if let Some(Token::Function(name)) = st.peek() {
+                output.push(Token::Function(name.clone()));
+                st.pop();
+            }
             continue;
         }
 
-        if Operator::is_valid(input_char) {
-            let o1 = input_char;
-            let mut o2 = st.peek();
-
-            let o1_prec = Operator::get_precedence(&o1);
-            let o2_prec = Operator::get_precedence(&o2.unwrap_or(&'+'));
+        // A chain is only ever made here, from infix comparisons.
+        let Some(o1) = token.operator_symbol() else {
+            return Err(ExpressionError::UnexpectedToken('['));
+        };
 
-            while o2.is_some() && o2 != Some(&'(')
-                && (o2_prec > o1_prec || (o2_prec == o1_prec && Operator::get_associativity(o1) == true))
-
-            {
-                let res = evaluator_handle_pop(&mut st, &mut output).unwrap();
-                evaluator_add_to_output(&mut output, res);
-                o2 = st.peek();
-            }
+        if is_prefix(o1) {
+            // Prefix operators have nothing to their left to pop for.
+            st.push(token.clone());
+            continue;
+        }
 
-            st.push(o1);
+        while pops_top(o1, st.peek(), operators)? && chained(o1, st.peek(), operators)?.is_none() {
+            let Some(entry) = st.pop() else { break; };
+            output.push(entry);
         }
+
+        push_operator(token, o1, &mut st, operators)?;
     }
 
-    while st.len() != 0 {
-        assert_ne!(st.peek(), Some(&'('));
-        let res = evaluator_handle_pop(&mut st, &mut output).unwrap();
-        evaluator_add_to_output(&mut output, res);
+    loop {
+        let Some(entry) = st.pop() else { break; };
+        if entry == Token::ParenLeft {
+            return Err(ExpressionError::MismatchedParentheses);
+        }
+        output.push(entry);
     }
 
-    return output[0];
+    return Ok(output);
 }
 
+/// Pushes `token`, the binary operator `o1`, onto the operator stack
+/// `st`, as part of the chain at the top if it continues one.
+fn push_operator(
+    token: &Token,
+    o1: char,
+    st: &mut Stack<Token>,
+    operators: &OperatorTable,
+) -> Result<(), ExpressionError> {
+    match chained(o1, st.peek(), operators)? {
+        Some(chain) => {
+            st.pop();
+            st.push(chain);
+        },
+        None => st.push(token.clone()),
+    }
+    return Ok(());
+}
 
-// ============== TOKENIZER BELOW =================
-#[derive(Debug, PartialEq)]
-enum Tokens {
-    Number(u32),
-    Plus,
-    Minus,
-    Asterisk,
-    Slash,
-    Caret,
-    ParenLeft,
-    ParenRight,
+/// The chain that the comparison `o1` makes with `top`, the top of the
+/// operator stack, if that is a comparison or chain of them, as in
+/// `1 < x < 5`, rather than `(1 < x) < 5`. Comparisons only chain with
+/// those of the same precedence, which by default is all of them.
+fn chained(o1: char, top: Option<&Token>, operators: &OperatorTable) -> Result<Option<Token>, ExpressionError> {
+    let last = match top {
+        Some(Token::Chain(ops)) => ops[ops.len() - 1],
+        Some(token) => match token.operator_symbol() {
+            Some(op) => op,
+            None => return Ok(None),
+        },
+        None => return Ok(None),
+    };
+    if !is_comparison(o1) || !is_comparison(last) || operators.get_precedence(o1)? != operators.get_precedence(last)? {
+        return Ok(None);
+    }
+    let mut ops = match top {
+        Some(Token::Chain(ops)) => ops.clone(),
+        _ => Vec::from([last]),
+    };
+    ops.push(o1);
+    return Ok(Some(Token::Chain(ops)));
 }
 
-struct Tokenizer {
-    tokens: Vec<Tokens>,
-    raw_input: String,
+/// Whether `op` is the operator stack symbol of a comparison.
+fn is_comparison(op: char) -> bool {
+    return matches!(op, '<' | '>' | '≤' | '≥' | '=' | '≠');
 }
 
-impl Tokenizer {
-    fn new(input: String) -> Self {
-        let mut input_chars = input.chars();
-        let mut tokens: Vec<Tokens> = Vec::new();
+/// Applies `ops`, the comparisons of a `Token::Chain`, between each of
+/// `operands` and the next and ands the results, so `1 < x <= 5` is
+/// `1 < x && x <= 5` with `x` evaluated only once. Every comparison is
+/// made, as both sides of a `&&` are.
+fn apply_chain<N: Numeric>(context: &Context, ops: &[char], operands: &[N]) -> Result<N, ExpressionError> {
+    let mut result = apply_operator(context, ops[0], operands[0].clone(), operands[1].clone())?;
+    for (i, op) in ops.iter().enumerate().skip(1) {
+        let link = apply_operator(context, *op, operands[i].clone(), operands[i + 1].clone())?;
+        result = apply_operator(context, '∧', result, link)?;
+    }
+    return Ok(result);
+}
 
-        loop {
-            let Some(input_char) = input_chars.next() else { break; };
-
-            if input_char.is_digit(10) {
-                let mut num = "".to_string();
-                num.push(input_char);
-                loop {
-                    let Some(input_char) = input_chars.next() else { break; };
-                    if input_char.is_digit(10) {
-                        num.push(input_char);
-                    } else {
-                        break;
-                    }
-                }
-                tokens.push(Tokens::Number(num.parse::<u32>().unwrap()));
-                continue;
-            }
+/// Whether `op` is a prefix operator, which takes one operand.
+fn is_prefix(op: char) -> bool {
+    return op == UNARY_MINUS || op == NOT;
+}
 
-            match input_char {
-                '+' => tokens.push(Tokens::Plus),
-                '-' => tokens.push(Tokens::Minus),
-                '*' => tokens.push(Tokens::Asterisk),
-                '/' => tokens.push(Tokens::Slash),
-                '^' => tokens.push(Tokens::Caret),
-                '(' => tokens.push(Tokens::ParenLeft),
-                ')' => tokens.push(Tokens::ParenRight),
-                ' ' => continue,
-                _ => panic!("Unexpected character: {}", input_char),
-            }
-        }
+fn evaluator_add_to_output<N: Numeric>(output: &mut Vec<N>, n: N) {
+    output.push(n);
+}
 
-        Tokenizer {
-            tokens,
-            raw_input: input,
+/// Applies `entry`, just popped off the operator stack, to the operands at
+/// the end of `output`.
+fn evaluator_handle_pop<N: Numeric>(entry: Token, output: &mut Vec<N>, context: &Context) -> Result<N, ExpressionError> {
+    // Functions are called when their `)` is reached, and an opening
+    // parenthesis is only popped here when nothing closed it.
+    if matches!(entry, Token::Function(_) | Token::ParenLeft) {
+        return Err(ExpressionError::MismatchedParentheses);
+    }
+    if let Token::Chain(ops) = &entry {
+        if output.len() <= ops.len() {
+            return Err(ExpressionError::EmptyStack);
         }
+        let operands = output.split_off(output.len() - ops.len() - 1);
+        return apply_chain(context, ops, &operands);
     }
+    let Some(op) = entry.operator_symbol() else { unreachable!() };
 
-    fn default() -> Self {
-        Tokenizer {
-            tokens: Vec::new(),
-            raw_input: String::new(),
+    if is_prefix(op) {
+        let operand = output.pop().ok_or(ExpressionError::EmptyStack)?;
+        if op == NOT {
+            return operand.not();
         }
+        return operand.negate();
     }
 
-    fn iter(self: &Self) -> impl Iterator<Item=&Tokens> {
-        self.tokens.iter()
+    let right = output.pop().ok_or(ExpressionError::EmptyStack)?;
+    let left = output.pop().ok_or(ExpressionError::EmptyStack)?;
+
+    return apply_operator(context, op, left, right);
+}
+
+/// Applies a binary operator, using its evaluation function if it has one,
+/// and otherwise the settings in the context, if any.
+fn apply_operator<N: Numeric>(context: &Context, op: char, left: N, right: N) -> Result<N, ExpressionError> {
+    if let Some(apply) = context.operators.get(op).and_then(|info| info.apply) {
+        return N::apply_custom(op, apply, left, right);
+    }
+    match context.config {
+        Some(config) => return config.apply(op, left, right),
+        None => return N::apply(op, left, right),
     }
 }
-// ============== TOKENIZER ABOVE =================
 
-#[cfg(test)]
-mod tests {
-    use super::*;
 
-    #[test]
-    fn parse_rpn_with_digits() {
-        assert_eq!(evaluate_rpn("1 2 +".to_string()), 3);
-    }
+/// Same as Shunting Yard Algorithm, but also evaluates the expression
+/// on-the-fly. Uses `Tokenizer`.
+pub fn sy_evaulate(input: String) -> Result<i64, ExpressionError> {
+    return sy_evaluate_with_ops(input, default_operators());
+}
 
-    #[test]
-    fn parse_rpn_with_numbers() {
-        assert_eq!(evaluate_rpn("11 22 +".to_string()), 33);
-    }
+/// Same as `sy_evaulate`, but with the operators in `operators`.
+pub fn sy_evaluate_with_ops(input: String, operators: &OperatorTable) -> Result<i64, ExpressionError> {
+    let context = Context { operators, ..Context::builtin() };
+    return shunting_yard_evaluate::<i64>(ok_tokens(tokenize_infix(&input, operators)?), &context, None);
+}
 
-    #[test]
-    #[should_panic]
-    fn parse_faulty_rpn() {
-        assert_eq!(evaluate_rpn("11 + 22".to_string()), 33);
-    }
+/// Same as `sy_evaulate`, but on infix tokens from `Tokenizer::tokenize`,
+/// so one tokenization can be both evaluated and converted with
+/// `infix_to_rpn_tokens`. The syntax is checked just the same, but a
+/// `ParseError`'s position is the index of the token it is about.
+pub fn sy_evaluate_tokens(tokens: &[Token]) -> Result<i64, ExpressionError> {
+    check_token_syntax(tokens)?;
+    return shunting_yard_evaluate::<i64>(tokens.iter().cloned().map(Ok), &Context::builtin(), None);
+}
 
-    #[test]
-    fn test_infix_to_postfix() {
-        assert_eq!(infix_to_rpn("1 + 2 * 3 - 4".to_string()), "1 2 3 * + 4 -");
-    }
+/// Same as `sy_evaulate`, but takes the tokens one at a time, e.g. from a
+/// `TokenStream`, without collecting them first. There is no syntax check
+/// up front, so malformed input is only caught by the evaluation itself,
+/// e.g. as `MismatchedParentheses` or `TooManyOperands` rather than a
+/// `ParseError`.
+pub fn sy_evaluate_stream(tokens: impl IntoIterator<Item = Result<Token, ExpressionError>>) -> Result<i64, ExpressionError> {
+    return shunting_yard_evaluate::<i64>(tokens, &Context::builtin(), None);
+}
 
-    #[test]
-    fn test_sy_evaluator() {
-        assert_eq!(sy_evaulate("1 + 2 * 3 - 4".to_string()), 3);
-    }
+/// Checked tokens as the stream `shunting_yard_evaluate` takes.
+fn ok_tokens(tokens: Vec<Token>) -> impl Iterator<Item = Result<Token, ExpressionError>> {
+    return tokens.into_iter().map(Ok);
+}
 
-    #[test]
-    fn test_tokenizer() {
-        let tokens: Vec<Tokens> = vec![
-            Tokens::Number(1),
-            Tokens::Plus,
-            Tokens::Number(2),
-            Tokens::Asterisk,
-            Tokens::Number(3),
-            Tokens::Minus,
-            Tokens::Number(4),
-        ];
+/// Floating point version of `sy_evaulate`. Division is true division,
+/// so `"10 / 4"` is `2.5`.
+pub fn sy_evaluate_f64(input: String) -> Result<f64, ExpressionError> {
+    return shunting_yard_evaluate::<f64>(ok_tokens(tokenize_infix(&input, default_operators())?), &Context::builtin(), None);
+}
 
-        let resulting_tokens = Tokenizer::new("1 + 2 * 3 - 4".to_string()).tokens;
+/// Floating point `sy_evaulate` that looks identifiers up in `variables`,
+/// so `"x * 2 + y"` can be evaluated for any `x` and `y`.
+#[cfg(any(feature = "std", test))]
+pub fn sy_evaluate_with_vars(input: String, variables: &HashMap<String, f64>) -> Result<f64, ExpressionError> {
+    return sy_evaluate_with(input, variables, default_functions());
+}
 
-        for (token1, token2) in tokens.iter().zip(resulting_tokens.iter()) {
-            if token1 != token2 {
-                assert!(false);
-            }
-        }
+/// Evaluates an infix expression in floating point, looking variables up
+/// in `env`. Any `Environment` works, including a `HashMap<String, i64>`.
+pub fn evaluate_with_env(input: &str, env: &dyn Environment) -> Result<f64, EvalError> {
+    return sy_evaluate_with(input.to_string(), env, default_functions());
+}
+
+/// Same as `sy_evaluate_with_vars`, but calls functions from `functions`
+/// instead of the built-ins.
+pub fn sy_evaluate_with(input: String, variables: &dyn Environment, functions: &Functions) -> Result<f64, ExpressionError> {
+    let context = Context { variables, functions, operators: default_operators(), config: None };
+    return shunting_yard_evaluate::<f64>(ok_tokens(tokenize_infix(&input, context.operators)?), &context, None);
+}
+
+/// Evaluates an infix expression keeping integers, floats and booleans
+/// apart, for predicates such as `"(x > 2) && (x < 10)"`. Operators only
+/// take the types they make sense for, so `"true + 3"` and `"1 && 2"` are
+/// `TypeMismatch` errors rather than being coerced. Both sides of `&&` and
+/// `||` are always evaluated.
+pub fn evaluate(input: &str) -> Result<Value, EvalError> {
+    return evaluate_value_with_env(input, &NoVariables);
+}
+
+/// Same as `evaluate`, but looks variables up in `env`. Their values are
+/// floats.
+pub fn evaluate_value_with_env(input: &str, env: &dyn Environment) -> Result<Value, EvalError> {
+    let context = Context { variables: env, ..Context::builtin() };
+    return shunting_yard_evaluate::<Value>(ok_tokens(tokenize_infix(input, context.operators)?), &context, None);
+}
+
+/// Same as `evaluate`, but with the settings and limits in `config`, for
+/// input that can't be trusted. Going past a limit is a `LimitExceeded`
+/// error.
+pub fn evaluate_with_config(input: &str, config: &EvaluatorConfig) -> Result<Value, EvalError> {
+    return evaluate_with_config_env(input, &NoVariables, config);
+}
+
+/// Same as `evaluate_with_config`, but looks variables up in `env`, and
+/// those it doesn't have are what `config.missing_variables` says.
+pub fn evaluate_with_config_env(
+    input: &str,
+    env: &dyn Environment,
+    config: &EvaluatorConfig,
+) -> Result<Value, EvalError> {
+    config.check_length(input)?;
+    let tokens = tokenize_infix_with(input, default_operators(), false, config.notation()?)?;
+    config.check_depth(&tokens)?;
+    let functions = match config.angle {
+        AngleUnit::Radians => default_functions(),
+        AngleUnit::Degrees => degree_functions(),
+    };
+    let context = Context { variables: env, functions, config: Some(config), ..Context::builtin() };
+    return shunting_yard_evaluate::<Value>(ok_tokens(tokens), &context, None);
+}
+
+/// Evaluates an infix expression in exact fractions, so `"1/3 * 3"` is
+/// exactly 1 and `"2 ^ -2"` is `1/4`. Results that don't fit an `i64`
+/// fraction in lowest terms are `Overflow` errors, even part way through.
+/// Like integer mode there are no float literals, and functions only give
+/// whole numbers.
+pub fn evaluate_exact(input: &str) -> Result<Rational, EvalError> {
+    return shunting_yard_evaluate::<Rational>(ok_tokens(tokenize_infix(input, default_operators())?), &Context::builtin(), None);
+}
+
+/// Evaluates a postfix expression keeping integers, floats and booleans
+/// apart, like `evaluate` does for infix.
+pub fn evaluate_rpn_value(input: &str) -> Result<Value, EvalError> {
+    return evaluate_rpn_tokens_generic::<Value>(&tokenize_rpn(input, default_operators())?, &Context::builtin());
+}
+
+/// Same as `sy_evaulate`, but also returns a step for every token showing
+/// what the algorithm did with it and the state it left, then one for each
+/// operator still on the stack at the end of the input. The output queue of
+/// the last step holds just the result.
+pub fn sy_evaluate_traced(input: &str) -> Result<(i64, Vec<EvalStep>), ExpressionError> {
+    let mut steps: Vec<EvalStep> = Vec::new();
+    let result = shunting_yard_evaluate::<i64>(ok_tokens(tokenize_infix(input, default_operators())?), &Context::builtin(), Some(&mut steps))?;
+    return Ok((result, steps));
+}
+
+/// One step of `sy_evaluate_traced`: the token processed, and the operator
+/// stack (bottom to top) and output queue after processing it. Steps print
+/// as a row of a table, in the order of the fields.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EvalStep<N = i64> {
+    pub token: String,
+    pub operator_stack: Vec<String>,
+    pub output_queue: Vec<N>,
+    pub action: StepAction,
+}
+
+/// What a step of the Shunting Yard evaluator does with its token.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepAction {
+    /// A number or variable goes to the output queue.
+    PushOperand,
+    /// An operator, function or `(` goes on the operator stack.
+    PushOperator,
+    /// A binary operator first pops and applies operators that bind at
+    /// least as tightly.
+    PopOperator,
+    /// A `)` or `,` finishes a parenthesized expression or argument.
+    EvaluateSubexpression,
+    /// At the end of the input, an operator left on the stack is popped
+    /// and applied.
+    PopRemaining,
+}
+
+impl fmt::Display for StepAction {
+    fn fmt(self: &Self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StepAction::PushOperand => f.pad("push operand"),
+            StepAction::PushOperator => f.pad("push operator"),
+            StepAction::PopOperator => f.pad("pop operator"),
+            StepAction::EvaluateSubexpression => f.pad("evaluate subexpression"),
+            StepAction::PopRemaining => f.pad("pop remaining"),
+        }
+    }
+}
+
+impl<N: fmt::Display> fmt::Display for EvalStep<N> {
+    fn fmt(self: &Self, f: &mut fmt::Formatter) -> fmt::Result {
+        let output: Vec<String> = self.output_queue.iter().map(|n| n.to_string()).collect();
+        write!(f, "{:<8} {:<24} {:<16} {}", self.token, self.action, self.operator_stack.join(" "), output.join(" "))
+    }
+}
+
+fn trace_step<N: Numeric>(token: String, action: StepAction, st: &Stack<Token>, output: &[N]) -> EvalStep<N> {
+    return EvalStep {
+        token,
+        operator_stack: st.iter().map(rpn_text).collect(),
+        output_queue: output.to_vec(),
+        action,
+    };
+}
+
+fn step_action(token: &Token, st: &Stack<Token>, operators: &OperatorTable) -> Result<StepAction, ExpressionError> {
+    match token {
+        Token::Number(_) | Token::Float(_) | Token::Bool(_) | Token::Ident(_) => return Ok(StepAction::PushOperand),
+        Token::ParenLeft | Token::Function(_) | Token::UnaryMinus | Token::Not => return Ok(StepAction::PushOperator),
+        Token::ParenRight | Token::Comma => return Ok(StepAction::EvaluateSubexpression),
+        _ => {},
+    }
+
+    let Some(o1) = token.operator_symbol() else {
+        return Err(ExpressionError::UnexpectedToken('['));
+    };
+    if pops_top(o1, st.peek(), operators)? && chained(o1, st.peek(), operators)?.is_none() {
+        return Ok(StepAction::PopOperator);
+    }
+    return Ok(StepAction::PushOperator);
+}
+
+fn shunting_yard_evaluate<N: Numeric>(
+    tokens: impl IntoIterator<Item = Result<Token, ExpressionError>>,
+    context: &Context,
+    trace: Option<&mut Vec<EvalStep<N>>>,
+) -> Result<N, ExpressionError> {
+    return shunting_yard_evaluate_in(tokens, context, trace, &mut EvalBuffers::with_capacity(0));
+}
+
+/// The stacks `shunting_yard_evaluate` works in. A batch of evaluations
+/// can share one, so they are cleared rather than allocated each time, and
+/// each has room for as many entries as the tokens it is given, which
+/// none of them ever needs more than.
+struct EvalBuffers<N> {
+    st: Stack<Token>,
+    output: Vec<N>,
+    /// Arguments seen so far by each function call still open, and how
+    /// long `output` was when it started.
+    arg_counts: Vec<(usize, usize)>,
+}
+
+impl<N> EvalBuffers<N> {
+    /// Buffers with room for `capacity` tokens.
+    fn with_capacity(capacity: usize) -> Self {
+        let mut buffers = EvalBuffers { st: Stack::new(), output: Vec::new(), arg_counts: Vec::new() };
+        buffers.reserve(capacity);
+        return buffers;
+    }
+
+    /// Makes room for `capacity` tokens, once cleared.
+    fn reserve(self: &mut Self, capacity: usize) {
+        self.st.reserve(capacity);
+        self.output.reserve(capacity);
+        self.arg_counts.reserve(capacity);
+    }
+}
+
+/// Same as `shunting_yard_evaluate`, but in `buffers`.
+fn shunting_yard_evaluate_in<N: Numeric>(
+    tokens: impl IntoIterator<Item = Result<Token, ExpressionError>>,
+    context: &Context,
+    mut trace: Option<&mut Vec<EvalStep<N>>>,
+    buffers: &mut EvalBuffers<N>,
+) -> Result<N, ExpressionError> {
+    let tokens = tokens.into_iter();
+    buffers.st.clear();
+    buffers.output.clear();
+    buffers.arg_counts.clear();
+    // A stream may not know how many tokens it has; the stacks grow then.
+    buffers.reserve(tokens.size_hint().0);
+    let EvalBuffers { st, output, arg_counts } = buffers;
+
+    // Whether the last token ended an operand. A stream has had no syntax
+    // check, and an operand right after another one would otherwise just
+    // be left over, as in `"2(3 + 4)"`.
+    let mut after_operand = false;
+    for token in tokens {
+        let token = token?;
+        if after_operand && token.starts_operand() {
+            return Err(ExpressionError::TooManyOperands);
+        }
+        after_operand = token.ends_operand();
+        // What happens depends on the stack before the token.
+        let action = match trace {
+            Some(_) => Some(step_action(&token, st, context.operators)?),
+            None => None,
+        };
+        evaluate_token(&token, st, output, arg_counts, context)?;
+        if let (Some(steps), Some(action)) = (trace.as_deref_mut(), action) {
+            steps.push(trace_step(token.to_string(), action, st, output));
+        }
+    }
+
+    loop {
+        let Some(entry) = st.pop() else { break; };
+        let text = trace.as_ref().map(|_| entry.to_string());
+        let res = evaluator_handle_pop(entry, output, context)?;
+        evaluator_add_to_output(output, res);
+        if let (Some(steps), Some(text)) = (trace.as_deref_mut(), text) {
+            steps.push(trace_step(text, StepAction::PopRemaining, st, output));
+        }
+    }
+
+    match output.len() {
+        0 => return Err(ExpressionError::EmptyInput),
+        1 => return output.pop().ok_or(ExpressionError::EmptyStack),
+        _ => return Err(ExpressionError::TooManyOperands),
+    }
+}
+
+/// Processes one infix token: operands go to `output`, and operators go
+/// on `st` once they've popped and applied what binds at least as tightly.
+fn evaluate_token<N: Numeric>(
+    token: &Token,
+    st: &mut Stack<Token>,
+    output: &mut Vec<N>,
+    arg_counts: &mut Vec<(usize, usize)>,
+    context: &Context,
+) -> Result<(), ExpressionError> {
+    let operators = context.operators;
+    match token {
+        Token::Number(n) => {
+            evaluator_add_to_output(output, N::from_integer(*n)?);
+            return Ok(());
+        },
+        Token::Float(f) => {
+            evaluator_add_to_output(output, N::from_float(*f)?);
+            return Ok(());
+        },
+        Token::Bool(b) => {
+            evaluator_add_to_output(output, N::from_bool(*b)?);
+            return Ok(());
+        },
+        Token::Ident(name) => {
+            evaluator_add_to_output(output, lookup_variable(context, name)?);
+            return Ok(());
+        },
+        Token::Function(name) => {
+            st.push(Token::Function(name.clone()));
+            arg_counts.push((1, output.len()));
+            return Ok(());
+        },
+        _ => {},
+    }
+
+    if *token == Token::ParenLeft {
+        st.push(Token::ParenLeft);
+        return Ok(());
+    }
+
+    if *token == Token::Comma {
+        // Finish the argument so far; the call's `(` stays open.
+        while st.peek() != Some(&Token::ParenLeft) {
+            let Some(entry) = st.pop() else {
+                return Err(ExpressionError::UnexpectedToken(','));
+            };
+            let res = evaluator_handle_pop(entry, output, context)?;
+            evaluator_add_to_output(output, res);
+        }
+        let Some((count, _)) = arg_counts.last_mut() else {
+            return Err(ExpressionError::UnexpectedToken(','));
+        };
+        *count += 1;
+        return Ok(());
+    }
+
+    if *token == Token::ParenRight {
+        while st.peek() != Some(&Token::ParenLeft) {
+            let Some(entry) = st.pop() else {
+                return Err(ExpressionError::MismatchedParentheses);
+            };
+            let res = evaluator_handle_pop(entry, output, context)?;
+            evaluator_add_to_output(output, res);
+        }
+        st.pop();
+        // A function right below the `(` was waiting for these arguments.
+        if let Some(Token::Function(name)) = st.peek() {
+            let name = name.clone();
+            st.pop();
+            let (count, start) = arg_counts.pop().unwrap_or((1, 0));
+            // Nothing between the parentheses, as in `f()`.
+            let count = if count == 1 && output.len() == start { 0 } else { count };
+            if output.len() < count {
+                return Err(ExpressionError::EmptyStack);
+            }
+            let args = output.split_off(output.len() - count);
+            evaluator_add_to_output(output, call_function(context.functions, &name, &args)?);
+        }
+        return Ok(());
+    }
+
+    let Some(o1) = token.operator_symbol() else {
+        return Err(ExpressionError::UnexpectedToken('['));
+    };
+
+    if is_prefix(o1) {
+        // Prefix operators have nothing to their left to pop for.
+        st.push(token.clone());
+        return Ok(());
+    }
+
+    while pops_top(o1, st.peek(), operators)? && chained(o1, st.peek(), operators)?.is_none() {
+        let Some(entry) = st.pop() else { break; };
+        let res = evaluator_handle_pop(entry, output, context)?;
+        evaluator_add_to_output(output, res);
+    }
+
+    return push_operator(token, o1, st, operators);
+}
+
+/// The value of the variable `name`, or what `MissingVarPolicy` says it
+/// is if there is no such variable.
+fn lookup_variable<N: Numeric>(context: &Context, name: &str) -> Result<N, ExpressionError> {
+    if let Some(value) = environment::lookup(context.variables, name) {
+        return N::from_float(value);
+    }
+    match context.config.map_or(MissingVarPolicy::Error, |config| config.missing_variables) {
+        MissingVarPolicy::Error => return Err(ExpressionError::UndefinedVariable(name.to_string())),
+        MissingVarPolicy::Default(value) => return N::from_value(value),
+        MissingVarPolicy::Propagate => return N::from_value(Value::Null),
+    }
+}
+
+fn call_function<N: Numeric>(functions: &Functions, name: &str, args: &[N]) -> Result<N, ExpressionError> {
+    let Some(function) = functions.get(name) else {
+        return Err(ExpressionError::UnknownFunction(name.to_string()));
+    };
+    if args.len() != function.arity() {
+        return Err(ExpressionError::WrongArity {
+            name: name.to_string(),
+            expected: function.arity(),
+            got: args.len(),
+        });
+    }
+    return N::call(name, function, args);
+}
+
+
+// ============== TOKENIZER BELOW =================
+/// A single token of an infix expression.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Token {
+    Number(i64),
+    Float(f64),
+    /// `true` or `false`. Outside of `evaluate` they are 1 and 0.
+    Bool(bool),
+    /// A variable name, `[a-zA-Z_][a-zA-Z0-9_]*`.
+    Ident(String),
+    /// A name followed by `(`, as in `sqrt(2)`.
+    Function(String),
+    Plus,
+    Minus,
+    /// A `-` with no operand to its left, as in `-3` or `2 * (-1)`.
+    UnaryMinus,
+    Asterisk,
+    Slash,
+    Percent,
+    Caret,
+    /// `<`. The comparisons evaluate to 1 if they hold and 0 otherwise.
+    Lt,
+    /// `>`
+    Gt,
+    /// `<=`
+    Le,
+    /// `>=`
+    Ge,
+    /// `==`
+    Eq,
+    /// `!=`
+    Ne,
+    /// `&`. The bitwise operators only work in integer mode.
+    BitAnd,
+    /// `|`
+    BitOr,
+    /// `xor`, as `^` is exponentiation.
+    BitXor,
+    /// `<<`
+    Shl,
+    /// `>>`, an arithmetic shift.
+    Shr,
+    /// `&&`
+    And,
+    /// `||`
+    Or,
+    /// `!`, logical not.
+    Not,
+    ParenLeft,
+    ParenRight,
+    /// Separates the arguments of a function call.
+    Comma,
+    /// A custom operator from an `OperatorTable`.
+    Operator(char),
+    /// A run of comparisons, as in `1 < x <= 5`, which holds if each one
+    /// holds between the operands either side of it. Only in postfix,
+    /// where it comes after all of its operands and RPN writes it as
+    /// `[< <=]`; the comparisons are their operator stack symbols.
+    Chain(Vec<char>),
+}
+
+impl Token {
+    /// The operator token for `symbol`, the inverse of `operator_symbol`.
+    fn from_operator_symbol(symbol: char) -> Token {
+        match symbol {
+            '+' => Token::Plus,
+            '-' => Token::Minus,
+            UNARY_MINUS => Token::UnaryMinus,
+            '*' => Token::Asterisk,
+            '/' => Token::Slash,
+            '%' => Token::Percent,
+            '^' => Token::Caret,
+            '<' => Token::Lt,
+            '>' => Token::Gt,
+            '≤' => Token::Le,
+            '≥' => Token::Ge,
+            '=' => Token::Eq,
+            '≠' => Token::Ne,
+            '&' => Token::BitAnd,
+            '|' => Token::BitOr,
+            '⊕' => Token::BitXor,
+            '«' => Token::Shl,
+            '»' => Token::Shr,
+            '∧' => Token::And,
+            '∨' => Token::Or,
+            NOT => Token::Not,
+            c => Token::Operator(c),
+        }
+    }
+
+    /// Whether the token can start an operand. One right after a token
+    /// that `ends_operand` is missing the operator between them, or with
+    /// implicit multiplication, multiplied by what came before.
+    fn starts_operand(self: &Self) -> bool {
+        return matches!(
+            self,
+            Token::Number(_) | Token::Float(_) | Token::Bool(_) | Token::Ident(_) | Token::Function(_)
+                | Token::ParenLeft | Token::UnaryMinus | Token::Not
+        );
+    }
+
+    /// Whether the token can end an operand.
+    fn ends_operand(self: &Self) -> bool {
+        return matches!(
+            self,
+            Token::Number(_) | Token::Float(_) | Token::Bool(_) | Token::Ident(_) | Token::ParenRight
+        );
+    }
+
+    /// The symbol this token uses on the operator stack, if it is an
+    /// operator. Operators longer than one character use a single character
+    /// stand-in.
+    fn operator_symbol(self: &Self) -> Option<char> {
+        match self {
+            Token::Plus => Some('+'),
+            Token::Minus => Some('-'),
+            Token::UnaryMinus => Some(UNARY_MINUS),
+            Token::Asterisk => Some('*'),
+            Token::Slash => Some('/'),
+            Token::Percent => Some('%'),
+            Token::Caret => Some('^'),
+            Token::Lt => Some('<'),
+            Token::Gt => Some('>'),
+            Token::Le => Some('≤'),
+            Token::Ge => Some('≥'),
+            Token::Eq => Some('='),
+            Token::Ne => Some('≠'),
+            Token::BitAnd => Some('&'),
+            Token::BitOr => Some('|'),
+            Token::BitXor => Some('⊕'),
+            Token::Shl => Some('«'),
+            Token::Shr => Some('»'),
+            Token::And => Some('∧'),
+            Token::Or => Some('∨'),
+            Token::Not => Some(NOT),
+            Token::Operator(c) => Some(*c),
+            _ => None,
+        }
+    }
+}
+
+/// Prints the token as written in infix input. Floats always keep a
+/// decimal point, so `Float(2.0)` prints as `2.0` rather than `2`.
+impl fmt::Display for Token {
+    fn fmt(self: &Self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Number(n) => write!(f, "{}", n),
+            Token::Float(x) if x.fract() == 0.0 => write!(f, "{:.1}", x),
+            Token::Float(x) => write!(f, "{}", x),
+            Token::Bool(b) => write!(f, "{}", b),
+            Token::Ident(name) | Token::Function(name) => write!(f, "{}", name),
+            Token::UnaryMinus => write!(f, "-"),
+            Token::ParenLeft => write!(f, "("),
+            Token::ParenRight => write!(f, ")"),
+            Token::Comma => write!(f, ","),
+            Token::Le => write!(f, "<="),
+            Token::Ge => write!(f, ">="),
+            Token::Eq => write!(f, "=="),
+            Token::Ne => write!(f, "!="),
+            Token::BitXor => write!(f, "xor"),
+            Token::Shl => write!(f, "<<"),
+            Token::Shr => write!(f, ">>"),
+            Token::And => write!(f, "&&"),
+            Token::Or => write!(f, "||"),
+            Token::Chain(ops) => {
+                let ops: Vec<String> = ops.iter().map(|op| Token::from_operator_symbol(*op).to_string()).collect();
+                write!(f, "[{}]", ops.join(" "))
+            },
+            _ => {
+                let Some(symbol) = self.operator_symbol() else { unreachable!() };
+                write!(f, "{}", symbol)
+            },
+        }
+    }
+}
+
+/// Lists tokens for reading while debugging, one to a line with its kind,
+/// how it prints and its span:
+///
+/// ```
+/// use expresso::{dump_tokens, Tokenizer};
+///
+/// let tokens: Vec<_> = Tokenizer::new("max(x, 2)".to_string()).unwrap().collect();
+/// assert_eq!(dump_tokens(&tokens), "\
+/// Function   max      0..3
+/// ParenLeft  (        3..4
+/// Ident      x        4..5
+/// Comma      ,        5..6
+/// Number     2        7..8
+/// ParenRight )        8..9
+/// ");
+/// ```
+pub fn dump_tokens(tokens: &[SpannedToken]) -> String {
+    let mut dump = String::new();
+    for SpannedToken { token, span } in tokens {
+        let kind = match token {
+            Token::Number(_) => "Number",
+            Token::Float(_) => "Float",
+            Token::Bool(_) => "Bool",
+            Token::Ident(_) => "Ident",
+            Token::Function(_) => "Function",
+            Token::ParenLeft => "ParenLeft",
+            Token::ParenRight => "ParenRight",
+            Token::Comma => "Comma",
+            _ => "Operator",
+        };
+        dump.push_str(&format!("{:<10} {:<8} {}..{}\n", kind, token.to_string(), span.start, span.end));
+    }
+    return dump;
+}
+
+/// Joins tokens back into an infix string, such as `max(1, -2) * 3` from
+/// the tokens of `max( 1,-2 )*3`. Re-tokenizing the result gives the same
+/// tokens.
+pub fn tokens_to_infix(tokens: &[Token]) -> String {
+    return join_infix(tokens.iter().map(|token| (token, None)));
+}
+
+/// `tokens_to_infix` for tokens paired with the text to write for each,
+/// where it isn't the token's own.
+fn join_infix<'a>(tokens: impl Iterator<Item = (&'a Token, Option<&'a str>)>) -> String {
+    let mut infix = String::new();
+    let mut previous: Option<&Token> = None;
+
+    for (token, text) in tokens {
+        let tight = matches!(token, Token::ParenRight | Token::Comma)
+            || matches!(previous, None | Some(Token::ParenLeft | Token::Function(_) | Token::UnaryMinus | Token::Not));
+        if !tight {
+            infix.push(' ');
+        }
+        match text {
+            Some(text) => infix.push_str(text),
+            None => infix.push_str(&token.to_string()),
+        }
+        previous = Some(token);
+    }
+
+    return infix;
+}
+
+/// Old name of `Token`.
+#[deprecated(note = "renamed to `Token`")]
+pub type Tokens = Token;
+
+/// A byte range into the tokenizer's input, `start` inclusive and `end`
+/// exclusive, so `&input[span.start..span.end]` is the token's text.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// A token together with where it came from in the input.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: Span,
+}
+
+/// A tokenizer failure, with the span of the offending input.
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TokenizeError {
+    pub error: ExpressionError,
+    pub span: Span,
+}
+
+/// A character lossy tokenizing skipped because it couldn't read it, such
+/// as a stray `;` or a zero-width space, with where it was.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SkippedCharacter {
+    pub character: char,
+    pub span: Span,
+}
+
+impl fmt::Display for TokenizeError {
+    fn fmt(self: &Self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at position {}", self.error, self.span.start)
+    }
+}
+
+impl core::error::Error for TokenizeError {}
+
+impl From<TokenizeError> for ExpressionError {
+    fn from(err: TokenizeError) -> Self {
+        return err.error;
+    }
+}
+
+/// Splits an infix expression into `Token`s. Iterating a `Tokenizer`
+/// yields the tokens with their spans.
+#[derive(Default)]
+pub struct Tokenizer {
+    tokens: Vec<SpannedToken>,
+    /// The text the tokens were read from, or `None` if they were built
+    /// with `from_tokens`.
+    raw_input: Option<String>,
+    /// Index of the next token handed out by `Iterator::next`.
+    position: usize,
+}
+
+impl Tokenizer {
+    pub fn new(input: String) -> Result<Self, TokenizeError> {
+        return Tokenizer::with_operators(input, default_operators());
+    }
+
+    /// Same as `new`, but also recognizes the custom operators in
+    /// `operators`.
+    pub fn with_operators(input: String, operators: &OperatorTable) -> Result<Self, TokenizeError> {
+        return Tokenizer::with_notation(input, operators, Notation::ASCII);
+    }
+
+    /// Same as `with_operators`, but reads numbers and argument lists as
+    /// `notation` says.
+    pub(crate) fn with_notation(input: String, operators: &OperatorTable, notation: Notation) -> Result<Self, TokenizeError> {
+        let mut stream = TokenStream::with_operators(&input, operators);
+        stream.notation = notation;
+        // There are never more tokens than bytes.
+        let mut tokens: Vec<SpannedToken> = Vec::with_capacity(input.len());
+
+        loop {
+            let Some(token) = stream.next_spanned() else { break; };
+            tokens.push(token?);
+        }
+
+        Ok(Tokenizer {
+            tokens,
+            raw_input: Some(input),
+            position: 0,
+        })
+    }
+
+    /// A `Tokenizer` over tokens built in code rather than read from a
+    /// string. There is no input for them to point into, so their spans
+    /// are empty.
+    pub fn from_tokens(tokens: Vec<Token>) -> Self {
+        let mut tokenizer = Tokenizer::default();
+        tokenizer.extend(tokens);
+        return tokenizer;
+    }
+
+    /// Appends `token` after the others.
+    pub fn push(self: &mut Self, token: Token) {
+        self.tokens.push(SpannedToken { token, span: Span { start: 0, end: 0 } });
+    }
+
+    /// Appends every token of `tokens`, in order.
+    pub fn extend(self: &mut Self, tokens: impl IntoIterator<Item = Token>) {
+        for token in tokens {
+            self.push(token);
+        }
+    }
+
+    /// Tokenizes `input`, returning the tokens themselves.
+    pub fn tokenize(input: &str) -> Result<Vec<Token>, ExpressionError> {
+        return Ok(Tokenizer::new(input.to_string())?.map(|t| t.token).collect());
+    }
+
+    /// Same as `tokenize`, but skips what it can't read and goes on, for
+    /// tools that show every problem at once. Returns the tokens it read,
+    /// and the byte offset of each problem with its error.
+    pub fn tokenize_lenient(input: &str) -> (Vec<Token>, Vec<(usize, ExpressionError)>) {
+        let mut stream = TokenStream::new(input);
+        let mut tokens: Vec<Token> = Vec::new();
+        let mut errors: Vec<(usize, ExpressionError)> = Vec::new();
+        loop {
+            let Some(result) = stream.next_spanned() else { break; };
+            match result {
+                Ok(spanned) => tokens.push(spanned.token),
+                Err(err) => {
+                    errors.push((err.span.start, err.error));
+                    stream.recover();
+                },
+            }
+        }
+        return (tokens, errors);
+    }
+
+    /// Same as `tokenize`, but characters it can't read at all are skipped,
+    /// as if they were spaces, and returned with the tokens, so input with
+    /// stray garbage in it still reads. Those that mean something where
+    /// they are, such as the second `.` of `1.2.3`, are still errors.
+    pub fn tokenize_lossy(input: &str) -> Result<(Vec<Token>, Vec<SkippedCharacter>), ExpressionError> {
+        let (tokenizer, skipped) = Tokenizer::lossy(input.to_string(), default_operators(), Notation::ASCII)?;
+        return Ok((tokenizer.map(|t| t.token).collect(), skipped));
+    }
+
+    /// Same as `with_notation`, but skipping what `tokenize_lossy` does.
+    pub(crate) fn lossy(
+        input: String,
+        operators: &OperatorTable,
+        notation: Notation,
+    ) -> Result<(Self, Vec<SkippedCharacter>), TokenizeError> {
+        let mut stream = TokenStream::with_operators(&input, operators);
+        stream.notation = notation;
+        let mut tokens: Vec<SpannedToken> = Vec::with_capacity(input.len());
+        let mut skipped: Vec<SkippedCharacter> = Vec::new();
+
+        loop {
+            match stream.next_spanned() {
+                None => break,
+                Some(Ok(token)) => tokens.push(token),
+                Some(Err(TokenizeError { error: ExpressionError::UnknownCharacter(character), span })) => {
+                    skipped.push(SkippedCharacter { character, span });
+                    stream.recover();
+                },
+                Some(Err(error)) => return Err(error),
+            }
+        }
+
+        let tokenizer = Tokenizer { tokens, raw_input: Some(input), position: 0 };
+        return Ok((tokenizer, skipped));
+    }
+
+    /// The text the tokens were read from, which their spans point into,
+    /// or `None` if they were built with `from_tokens`.
+    pub fn input(self: &Self) -> Option<&str> {
+        return self.raw_input.as_deref();
+    }
+
+    /// How `token`, one of this tokenizer's, was written in the input, so
+    /// `007` rather than the 7 its `Token::Number` holds. `None` if there
+    /// is no input.
+    pub fn lexeme(self: &Self, token: &SpannedToken) -> Option<&str> {
+        return self.raw_input.as_deref()?.get(token.span.start..token.span.end);
+    }
+
+    /// Same as `tokens_to_infix`, but numbers are written as they were in
+    /// the input, so `"007 +1_000"` comes back as `"007 + 1_000"`.
+    pub fn to_infix(self: &Self) -> String {
+        return join_infix(self.tokens.iter().map(|spanned| {
+            let number = matches!(spanned.token, Token::Number(_) | Token::Float(_));
+            return (&spanned.token, self.lexeme(spanned).filter(|_| number));
+        }));
+    }
+
+    /// Iterates over the tokens not yet taken by `Iterator::next`,
+    /// without consuming them.
+    pub fn iter(self: &Self) -> core::slice::Iter<'_, SpannedToken> {
+        self.tokens[self.position..].iter()
+    }
+}
+
+impl Iterator for Tokenizer {
+    type Item = SpannedToken;
+
+    fn next(self: &mut Self) -> Option<SpannedToken> {
+        let token = self.tokens.get(self.position)?.clone();
+        self.position += 1;
+        return Some(token);
+    }
+
+    fn size_hint(self: &Self) -> (usize, Option<usize>) {
+        let left = self.tokens.len() - self.position;
+        return (left, Some(left));
+    }
+}
+
+impl<'a> IntoIterator for &'a Tokenizer {
+    type Item = &'a SpannedToken;
+    type IntoIter = core::slice::Iter<'a, SpannedToken>;
+
+    fn into_iter(self: Self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// How numbers and argument lists are written, from the `EvaluatorConfig`
+/// fields of the same names, and whether `√x` and `|x|` are, and `x²`,
+/// from `Evaluator::with_math_notation` and `with_superscripts`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Notation {
+    pub(crate) decimal_separator: char,
+    pub(crate) argument_separator: char,
+    pub(crate) fullwidth_digits: bool,
+    pub(crate) math_notation: bool,
+    pub(crate) superscripts: bool,
+}
+
+impl Notation {
+    /// `3.5`, `max(1, 2)` and only ASCII digits, as everywhere but
+    /// `evaluate_with_config`.
+    pub(crate) const ASCII: Notation = Notation {
+        decimal_separator: '.',
+        argument_separator: ',',
+        fullwidth_digits: false,
+        math_notation: false,
+        superscripts: false,
+    };
+
+    fn is_digit(self: &Self, c: char) -> bool {
+        return c.is_digit(10) || (self.fullwidth_digits && matches!(c, '０'..='９'));
+    }
+
+    /// `literal` as Rust parses it, with ASCII digits and a `.` point.
+    fn to_ascii<'a>(self: &Self, literal: &'a str) -> Cow<'a, str> {
+        if literal.is_ascii() && self.decimal_separator == '.' {
+            return Cow::Borrowed(literal);
+        }
+        let ascii = literal.chars().map(|c| match c {
+            c if c == self.decimal_separator => '.',
+            '０'..='９' => char::from(b'0' + (c as u32 - '０' as u32) as u8),
+            '−' => '-',
+            c => c,
+        });
+        return Cow::Owned(ascii.collect());
+    }
+}
+
+/// Splits an infix expression into tokens one at a time, where `Tokenizer`
+/// does it all up front, so a long generated expression is never held as
+/// tokens all at once. Yields the same tokens, and stops after the first
+/// error.
+pub struct TokenStream<'a> {
+    input: &'a str,
+    input_chars: Peekable<CharIndices<'a>>,
+    operators: &'a OperatorTable,
+    notation: Notation,
+    /// Whether the last token ended an operand, which makes a `-` binary.
+    after_operand: bool,
+    failed: bool,
+    /// Tokens read along with the last one, to hand out next, the first
+    /// of them last.
+    ahead: Vec<SpannedToken>,
+}
+
+impl<'a> TokenStream<'a> {
+    pub fn new(input: &'a str) -> Self {
+        return TokenStream::with_operators(input, default_operators());
+    }
+
+    /// Same as `new`, but also recognizes the custom operators in
+    /// `operators`.
+    pub fn with_operators(input: &'a str, operators: &'a OperatorTable) -> Self {
+        TokenStream {
+            input,
+            input_chars: input.char_indices().peekable(),
+            operators,
+            notation: Notation::ASCII,
+            after_operand: false,
+            failed: false,
+            ahead: Vec::new(),
+        }
+    }
+
+    /// Same as `next`, but with the token's span.
+    pub fn next_spanned(self: &mut Self) -> Option<Result<SpannedToken, TokenizeError>> {
+        if self.failed {
+            return None;
+        }
+        let result = match self.ahead.pop() {
+            Some(spanned) => Ok(spanned),
+            None => self.read_token().transpose()?,
+        };
+        match &result {
+            Ok(spanned) => {
+                self.after_operand = matches!(
+                    spanned.token,
+                    Token::Number(_) | Token::Float(_) | Token::Bool(_) | Token::Ident(_) | Token::ParenRight
+                );
+            },
+            Err(_) => self.failed = true,
+        }
+        return Some(result);
+    }
+
+    /// Lets the stream go on after an error, from just past the input it
+    /// rejected, so every problem can be reported rather than the first.
+    fn recover(self: &mut Self) {
+        self.failed = false;
+    }
+
+    fn read_token(self: &mut Self) -> Result<Option<SpannedToken>, TokenizeError> {
+        loop {
+            let Some((start, input_char)) = self.input_chars.next() else { return Ok(None); };
+
+            if is_number_start(self.input, start, self.notation) {
+                let read = read_number(self.input, start, &mut self.input_chars, self.notation);
+                if read.is_err() {
+                    // The rest of a malformed literal goes with it, so
+                    // `recover` doesn't read `.3` of `1.2.3` as a number.
+                    let point = self.notation.decimal_separator;
+                    while self.input_chars.next_if(|(_, c)| c.is_alphanumeric() || *c == '_' || *c == point).is_some() {}
+                }
+                let (token, end) = read?;
+                return Ok(Some(SpannedToken { token, span: Span { start, end } }));
+            }
+
+            if is_identifier_start(input_char) {
+                let (token, end) = read_identifier(self.input, start, &mut self.input_chars);
+                return Ok(Some(SpannedToken { token, span: Span { start, end } }));
+            }
+
+            if let Some((token, end)) = read_multi_char_operator(input_char, start, &mut self.input_chars) {
+                return Ok(Some(SpannedToken { token, span: Span { start, end } }));
+            }
+
+            if self.notation.superscripts && (superscript_digit(input_char).is_some() || input_char == '⁻') {
+                return self.read_superscript(start, input_char).map(Some);
+            }
+
+            let token = match input_char {
+                '+' => Token::Plus,
+                '-' | '−' if self.after_operand => Token::Minus,
+                '-' | '−' => Token::UnaryMinus,
+                '*' | '×' => Token::Asterisk,
+                '/' | '÷' => Token::Slash,
+                '%' => Token::Percent,
+                '^' => Token::Caret,
+                '(' => Token::ParenLeft,
+                ')' => Token::ParenRight,
+                c if c == self.notation.argument_separator => Token::Comma,
+                // With no `(` after it, `math_notation` gives it one.
+                '√' if self.notation.math_notation => Token::Function("sqrt".to_string()),
+                c if c.is_whitespace() => continue,
+                c if self.operators.is_custom(c) => Token::Operator(c),
+                _ => return Err(TokenizeError {
+                    error: ExpressionError::UnknownCharacter(input_char),
+                    span: char_span(start, input_char),
+                }),
+            };
+            return Ok(Some(SpannedToken { token, span: char_span(start, input_char) }));
+        }
+    }
+}
+
+impl TokenStream<'_> {
+    /// Reads a superscript exponent, as in `x²` or `2⁻¹`, whose first
+    /// character `first` at `start` has already been taken. Returns the
+    /// `^` it means, with an empty span as it isn't written, and leaves
+    /// the exponent in `ahead`.
+    fn read_superscript(self: &mut Self, start: usize, first: char) -> Result<SpannedToken, TokenizeError> {
+        let digits_start = if first == '⁻' { start + first.len_utf8() } else { start };
+        let mut end = start + first.len_utf8();
+        let mut exponent = superscript_digit(first).map(i64::from);
+        while let Some((offset, c)) = self.input_chars.next_if(|(_, c)| superscript_digit(*c).is_some()) {
+            let Some(digit) = superscript_digit(c) else { unreachable!() };
+            end = offset + c.len_utf8();
+            exponent = Some(exponent.unwrap_or(0).checked_mul(10).and_then(|n| n.checked_add(i64::from(digit))).ok_or(
+                TokenizeError { error: ExpressionError::Overflow, span: Span { start: digits_start, end } },
+            )?);
+        }
+        let Some(exponent) = exponent else {
+            return Err(TokenizeError { error: ExpressionError::MissingExponent, span: char_span(start, first) });
+        };
+
+        self.ahead.push(SpannedToken { token: Token::Number(exponent), span: Span { start: digits_start, end } });
+        if first == '⁻' {
+            self.ahead.push(SpannedToken { token: Token::UnaryMinus, span: char_span(start, first) });
+        }
+        return Ok(SpannedToken { token: Token::Caret, span: Span { start, end: start } });
+    }
+}
+
+/// The value of a superscript digit, as in `x²`.
+fn superscript_digit(c: char) -> Option<u32> {
+    match c {
+        '⁰' => return Some(0),
+        '¹' => return Some(1),
+        '²' => return Some(2),
+        '³' => return Some(3),
+        '⁴'..='⁹' => return Some(c as u32 - '⁴' as u32 + 4),
+        _ => return None,
+    }
+}
+
+impl Iterator for TokenStream<'_> {
+    type Item = Result<Token, ExpressionError>;
+
+    fn next(self: &mut Self) -> Option<Self::Item> {
+        return self.next_spanned().map(|result| result.map(|spanned| spanned.token).map_err(ExpressionError::from));
+    }
+}
+
+fn char_span(start: usize, c: char) -> Span {
+    return Span { start, end: start + c.len_utf8() };
+}
+
+/// Reads a number literal starting at byte `start` of `input`, whose first
+/// character has already been taken from `input_chars`. Literals with a
+/// point or an exponent, like `.5` and `1e-3`, are floats. Whatever ends the
+/// literal is left in `input_chars` for the caller. Returns the token and
+/// the byte offset just past the literal. `notation` says what the point
+/// is and which digits there are. An `_` after a digit groups digits, as in
+/// `1_000`.
+fn read_number(
+    input: &str,
+    start: usize,
+    input_chars: &mut Peekable<CharIndices>,
+    notation: Notation,
+) -> Result<(Token, usize), TokenizeError> {
+    if input[start..].starts_with('0') {
+        if input_chars.next_if(|(_, c)| *c == 'x' || *c == 'X').is_some() {
+            return read_radix_number(input, start, 16, input_chars);
+        }
+        if input_chars.next_if(|(_, c)| *c == 'b' || *c == 'B').is_some() {
+            return read_radix_number(input, start, 2, input_chars);
+        }
+    }
+
+    let point = notation.decimal_separator;
+    let mut seen_point = input[start..].starts_with(point);
+    // `_` groups digits, as in `1_000`, so it only goes after one.
+    let mut after_digit = !seen_point;
+
+    loop {
+        let next = input_chars.next_if(|(_, c)| notation.is_digit(*c) || *c == point || (*c == '_' && after_digit));
+        let Some((offset, next_char)) = next else { break; };
+        if next_char == point {
+            if seen_point {
+                return Err(TokenizeError {
+                    error: ExpressionError::UnexpectedToken(point),
+                    span: char_span(offset, point),
+                });
+            }
+            seen_point = true;
+        }
+        after_digit = notation.is_digit(next_char);
+    }
+    let mantissa_end = input_chars.peek().map_or(input.len(), |(offset, _)| *offset);
+    if input[start..mantissa_end].ends_with('_') {
+        return Err(TokenizeError {
+            error: ExpressionError::InvalidLiteral(input[start..mantissa_end].to_string()),
+            span: Span { start, end: mantissa_end },
+        });
+    }
+
+    // An exponent, as in `2.5e-4`, ends the literal.
+    let mut seen_exponent = false;
+    if let Some((e_offset, e)) = input_chars.next_if(|(_, c)| *c == 'e' || *c == 'E') {
+        input_chars.next_if(|(_, c)| matches!(c, '+' | '-' | '−'));
+        if input_chars.next_if(|(_, c)| notation.is_digit(*c)).is_none() {
+            return Err(TokenizeError { error: ExpressionError::MissingExponent, span: char_span(e_offset, e) });
+        }
+        while input_chars.next_if(|(_, c)| notation.is_digit(*c)).is_some() {}
+        if let Some((offset, c)) = input_chars.next_if(|(_, c)| *c == point || matches!(c, 'e' | 'E')) {
+            return Err(TokenizeError { error: ExpressionError::UnexpectedToken(c), span: char_span(offset, c) });
+        }
+        seen_exponent = true;
+    }
+
+    let end = input_chars.peek().map_or(input.len(), |(offset, _)| *offset);
+    let mut num = notation.to_ascii(&input[start..end]);
+    if num.contains('_') {
+        num = Cow::Owned(num.replace('_', ""));
+    }
+    let num = num.as_ref();
+
+    if seen_point || seen_exponent {
+        let f = num.parse::<f64>().unwrap();
+        if f.is_infinite() {
+            return Err(TokenizeError { error: ExpressionError::Overflow, span: Span { start, end } });
+        }
+        return Ok((Token::Float(f), end));
+    }
+    match num.parse::<i64>() {
+        Ok(n) => return Ok((Token::Number(n), end)),
+        Err(_) => return Err(TokenizeError {
+            error: ExpressionError::Overflow,
+            span: Span { start, end },
+        }),
+    }
+}
+
+/// Reads an operator that is, or starts like, one of two characters, such
+/// as `<` and `<=` or `&` and `&&`, starting with `c` at byte `start`,
+/// where `c` has already been taken from `input_chars`. Returns `None`,
+/// taking nothing more, if `c` doesn't start one; a lone `=` doesn't.
+fn read_multi_char_operator(c: char, start: usize, input_chars: &mut Peekable<CharIndices>) -> Option<(Token, usize)> {
+    if !matches!(c, '<' | '>' | '=' | '!' | '&' | '|') {
+        return None;
+    }
+    let second = input_chars.peek().map(|(_, next)| *next);
+
+    let token = match (c, second) {
+        ('<', Some('=')) => Token::Le,
+        ('>', Some('=')) => Token::Ge,
+        ('=', Some('=')) => Token::Eq,
+        ('!', Some('=')) => Token::Ne,
+        ('<', Some('<')) => Token::Shl,
+        ('>', Some('>')) => Token::Shr,
+        ('&', Some('&')) => Token::And,
+        ('|', Some('|')) => Token::Or,
+        ('<', _) => return Some((Token::Lt, start + 1)),
+        ('>', _) => return Some((Token::Gt, start + 1)),
+        ('!', _) => return Some((Token::Not, start + 1)),
+        ('&', _) => return Some((Token::BitAnd, start + 1)),
+        ('|', _) => return Some((Token::BitOr, start + 1)),
+        _ => return None,
+    };
+    input_chars.next();
+    return Some((token, start + 2));
+}
+
+/// Reads the digits of a hex literal such as `0xFF` or a binary one such
+/// as `0b1010`, whose prefix has already been taken from `input_chars`.
+/// Letters and digits run on into the literal, so `0xGG` and `0b12` are
+/// each one `InvalidLiteral` rather than a number and a name. An `_` between
+/// digits groups them, as in `0xFF_FF`.
+fn read_radix_number(
+    input: &str,
+    start: usize,
+    radix: u32,
+    input_chars: &mut Peekable<CharIndices>,
+) -> Result<(Token, usize), TokenizeError> {
+    while input_chars.next_if(|(_, c)| c.is_ascii_alphanumeric() || *c == '_').is_some() {}
+
+    let end = input_chars.peek().map_or(input.len(), |(offset, _)| *offset);
+    // `_` groups digits, so it only goes between them.
+    let written = &input[start + 2..end];
+    let digits = written.replace('_', "");
+    let grouped = !written.starts_with('_') && !written.ends_with('_');
+    if digits.is_empty() || !grouped || !digits.chars().all(|c| c.is_digit(radix)) {
+        return Err(TokenizeError {
+            error: ExpressionError::InvalidLiteral(input[start..end].to_string()),
+            span: Span { start, end },
+        });
+    }
+    match i64::from_str_radix(&digits, radix) {
+        Ok(n) => return Ok((Token::Number(n), end)),
+        Err(_) => return Err(TokenizeError {
+            error: ExpressionError::Overflow,
+            span: Span { start, end },
+        }),
+    }
+}
+
+/// Whether a number literal starts at byte `start` of `input`: a digit, or
+/// a `.` before one, as in `.5`.
+fn is_number_start(input: &str, start: usize, notation: Notation) -> bool {
+    let mut chars = input[start..].chars();
+    match chars.next() {
+        Some(c) if c == notation.decimal_separator => return chars.next().is_some_and(|c| notation.is_digit(c)),
+        Some(c) => return notation.is_digit(c),
+        None => return false,
+    }
+}
+
+fn is_identifier_start(c: char) -> bool {
+    return c.is_ascii_alphabetic() || c == '_';
+}
+
+/// Reads an identifier starting at byte `start` of `input`, like
+/// `read_number` does for number literals. A name followed by `(` is a
+/// function call.
+fn read_identifier(input: &str, start: usize, input_chars: &mut Peekable<CharIndices>) -> (Token, usize) {
+    while input_chars.next_if(|(_, c)| c.is_ascii_alphanumeric() || *c == '_').is_some() {}
+
+    let end = input_chars.peek().map_or(input.len(), |(offset, _)| *offset);
+    let name = input[start..end].to_string();
+    match name.as_str() {
+        "xor" => return (Token::BitXor, end),
+        "true" => return (Token::Bool(true), end),
+        "false" => return (Token::Bool(false), end),
+        _ => {},
+    }
+    if input[end..].trim_start().starts_with('(') {
+        return (Token::Function(name), end);
+    }
+    return (Token::Ident(name), end);
+}
+// ============== TOKENIZER ABOVE =================
+
+// ============== SYNTAX CHECK BELOW =================
+/// Checks that `input` is a well formed infix expression that calls only
+/// built-in functions, each with as many arguments as it takes, without
+/// evaluating anything. Returns the first
+/// problem found, the same error evaluating it would report for its syntax.
+/// Errors that depend on values, like division by zero, aren't detected.
+pub fn validate(input: &str) -> Result<(), ExpressionError> {
+    let tokens = tokenize_infix(input, default_operators())?;
+    return check_calls(&tokens, default_functions());
+}
+
+/// The names an expression uses, from `dependencies`. Each is listed once,
+/// in the order it first appears.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Dependencies {
+    /// Names used as values, other than the built-in constants.
+    pub variables: Vec<String>,
+    /// Names called as functions, known or not.
+    pub functions: Vec<String>,
+}
+
+/// Lists the variables and functions a well formed infix `input` uses,
+/// without evaluating it, so unbound variables and unknown functions are
+/// fine. A name is a function if a `(` follows it, so in `f(f)` it is both.
+pub fn dependencies(input: &str) -> Result<Dependencies, ParseError> {
+    let tokens: Vec<SpannedToken> = Tokenizer::new(input.to_string()).map_err(tokenize_parse_error)?.collect();
+    check_syntax(&tokens, input.len())?;
+
+    let mut dependencies = Dependencies::default();
+    for spanned in tokens {
+        let (names, name) = match spanned.token {
+            Token::Ident(name) if environment::is_constant(&name) => continue,
+            Token::Ident(name) => (&mut dependencies.variables, name),
+            Token::Function(name) => (&mut dependencies.functions, name),
+            _ => continue,
+        };
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    return Ok(dependencies);
+}
+
+/// Tokenizes infix input for the evaluators, rejecting malformed
+/// expressions with a `ParseError` that points at the problem.
+fn tokenize_infix(input: &str, operators: &OperatorTable) -> Result<Vec<Token>, ExpressionError> {
+    return tokenize_infix_with(input, operators, false, Notation::ASCII);
+}
+
+/// Same as `tokenize_infix`, but with each token's span.
+fn tokenize_infix_spanned(input: &str, operators: &OperatorTable) -> Result<Vec<SpannedToken>, ExpressionError> {
+    return tokenize_infix_spanned_with(input, operators, false, Notation::ASCII);
+}
+
+/// Same as `tokenize_infix`, but with `implicit_multiplication` a `*` is
+/// assumed wherever `implicit_multiplications` finds one missing, and
+/// numbers and argument lists are written as `notation` says.
+fn tokenize_infix_with(
+    input: &str,
+    operators: &OperatorTable,
+    implicit_multiplication: bool,
+    notation: Notation,
+) -> Result<Vec<Token>, ExpressionError> {
+    let tokens = tokenize_infix_spanned_with(input, operators, implicit_multiplication, notation)?;
+    return Ok(tokens.into_iter().map(|t| t.token).collect());
+}
+
+fn tokenize_infix_spanned_with(
+    input: &str,
+    operators: &OperatorTable,
+    implicit_multiplication: bool,
+    notation: Notation,
+) -> Result<Vec<SpannedToken>, ExpressionError> {
+    let tokenizer = Tokenizer::with_notation(input.to_string(), operators, notation).map_err(infix_tokenize_error)?;
+    return check_infix(tokenizer.tokens, input, implicit_multiplication, notation);
+}
+
+/// Same as `tokenize_infix_with`, but skipping what `tokenize_lossy` does,
+/// and returning what it skipped.
+fn tokenize_infix_lossy(
+    input: &str,
+    operators: &OperatorTable,
+    implicit_multiplication: bool,
+    notation: Notation,
+) -> Result<(Vec<Token>, Vec<SkippedCharacter>), ExpressionError> {
+    let (tokenizer, skipped) = Tokenizer::lossy(input.to_string(), operators, notation).map_err(infix_tokenize_error)?;
+    let tokens = check_infix(tokenizer.tokens, input, implicit_multiplication, notation)?;
+    return Ok((tokens.into_iter().map(|t| t.token).collect(), skipped));
+}
+
+/// The checks, `*`s and `Notation::math_notation` the infix tokenizers
+/// add to the tokens of `input`.
+fn check_infix(
+    mut tokens: Vec<SpannedToken>,
+    input: &str,
+    implicit_multiplication: bool,
+    notation: Notation,
+) -> Result<Vec<SpannedToken>, ExpressionError> {
+    if tokens.is_empty() {
+        return Err(ExpressionError::EmptyInput);
+    }
+    if notation.math_notation {
+        tokens = math_notation(tokens)?;
+    }
+    if implicit_multiplication {
+        // Back to front, so the positions still to insert at stay put.
+        let insertions = implicit_multiplications(&tokens);
+        tokens.reserve(insertions.len());
+        for i in insertions.into_iter().rev() {
+            let start = tokens[i].span.start;
+            tokens.insert(i, SpannedToken { token: Token::Asterisk, span: Span { start, end: start } });
+        }
+    }
+    check_syntax(&tokens, input.len())?;
+
+    return Ok(tokens);
+}
+
+/// Rewrites `|x|` as `abs(x)` and `√x` as `sqrt(x)`, for
+/// `Notation::math_notation`.
+///
+/// A `|` where an operand is expected opens a bar, and one where an
+/// operator is expected closes the bar that is open, if the innermost group
+/// open is a bar, so `||x| - 1|` is `abs(abs(x) - 1)` and `||x||` is
+/// `abs(abs(x))`. A `||` is two bars wherever either would be one, and
+/// otherwise an or, so one inside bars has to be in parentheses of its own,
+/// as in `|(a || b)|`. A `|` that is neither is the bitwise or it always is.
+///
+/// `√` takes the operand right after it, so `√4 ^ 2` is `sqrt(4) ^ 2`, and
+/// a `√` with parentheses after it is already a call.
+fn math_notation(tokens: Vec<SpannedToken>) -> Result<Vec<SpannedToken>, ParseError> {
+    let mut rewritten: Vec<SpannedToken> = Vec::with_capacity(tokens.len());
+    // Where each `(` and bar still open starts, and whether it is a bar.
+    let mut open: Vec<(usize, bool)> = Vec::new();
+    let mut expect_operand = true;
+
+    for SpannedToken { mut token, span } in tokens {
+        // The tokenizer took the `-` in `|x| - 1` for a negation.
+        if token == Token::UnaryMinus && !expect_operand {
+            token = Token::Minus;
+        }
+        let bars = match token {
+            Token::BitOr => 1,
+            Token::Or => 2,
+            _ => 0,
+        };
+        let in_bar = matches!(open.last(), Some((_, true)));
+        if bars == 0 || !(expect_operand || in_bar) {
+            match token {
+                Token::ParenLeft => open.push((span.start, false)),
+                Token::ParenRight => {
+                    if let Some((start, true)) = open.pop() {
+                        return Err(ParseError { kind: ParseErrorKind::UnbalancedBar, position: start });
+                    }
+                },
+                _ => {},
+            }
+            expect_operand = !token.ends_operand();
+            rewritten.push(SpannedToken { token, span });
+            continue;
+        }
+
+        for start in span.start..span.start + bars {
+            let bar = Span { start, end: start + 1 };
+            if expect_operand {
+                rewritten.push(SpannedToken { token: Token::Function("abs".to_string()), span: bar });
+                rewritten.push(SpannedToken { token: Token::ParenLeft, span: Span { start: bar.end, end: bar.end } });
+                open.push((start, true));
+            } else if let Some((_, true)) = open.last() {
+                rewritten.push(SpannedToken { token: Token::ParenRight, span: bar });
+                open.pop();
+                expect_operand = false;
+            } else {
+                rewritten.push(SpannedToken { token: Token::BitOr, span: bar });
+                expect_operand = true;
+            }
+        }
+    }
+    if let Some((start, _)) = open.iter().rev().find(|(_, bar)| *bar) {
+        return Err(ParseError { kind: ParseErrorKind::UnbalancedBar, position: *start });
+    }
+
+    // Back to front, so the operand of each `√` has had its own `√`s
+    // parenthesized already.
+    for i in (0..rewritten.len()).rev() {
+        if !is_bare_function(&rewritten, i) {
+            continue;
+        }
+        let Some(end) = operand_end(&rewritten, i + 1) else { continue; };
+        let (start, close) = (rewritten[i + 1].span.start, rewritten[end - 1].span.end);
+        rewritten.insert(end, SpannedToken { token: Token::ParenRight, span: Span { start: close, end: close } });
+        rewritten.insert(i + 1, SpannedToken { token: Token::ParenLeft, span: Span { start, end: start } });
+    }
+    return Ok(rewritten);
+}
+
+/// Whether `tokens[i]` is a function with no `(` after it, which only a
+/// `√` is.
+fn is_bare_function(tokens: &[SpannedToken], i: usize) -> bool {
+    let bare = tokens.get(i + 1).map(|t| &t.token) != Some(&Token::ParenLeft);
+    return matches!(tokens[i].token, Token::Function(_)) && bare;
+}
+
+/// The index just past the operand that starts at `tokens[start]`, its
+/// prefix operators and all, or `None` if none does.
+fn operand_end(tokens: &[SpannedToken], start: usize) -> Option<usize> {
+    let mut i = start;
+    while matches!(tokens.get(i)?.token, Token::UnaryMinus | Token::Not) || is_bare_function(tokens, i) {
+        i += 1;
+    }
+    match tokens[i].token {
+        Token::Number(_) | Token::Float(_) | Token::Bool(_) | Token::Ident(_) => return Some(i + 1),
+        Token::Function(_) => i += 1,
+        Token::ParenLeft => {},
+        _ => return None,
+    }
+    let mut depth: usize = 0;
+    for (j, spanned) in tokens.iter().enumerate().skip(i) {
+        match spanned.token {
+            Token::ParenLeft => depth += 1,
+            Token::ParenRight if depth == 1 => return Some(j + 1),
+            Token::ParenRight => depth -= 1,
+            _ => {},
+        }
+    }
+    return None;
+}
+
+/// The indices of the tokens a `*` is left out before: those that start an
+/// operand right after one that ends another, as in `2(3 + 4)`,
+/// `(1 + 2)(3 + 4)`, `2x` and `2 3`. Without implicit multiplication the
+/// same pairs are `MissingOperator` errors.
+fn implicit_multiplications(tokens: &[SpannedToken]) -> Vec<usize> {
+    let mut indices = Vec::new();
+    for (i, pair) in tokens.windows(2).enumerate() {
+        if pair[0].token.ends_operand() && pair[1].token.starts_operand() {
+            indices.push(i + 1);
+        }
+    }
+    return indices;
+}
+
+/// A tokenizer failure as a `ParseError`, for the functions that only
+/// report those.
+fn tokenize_parse_error(err: TokenizeError) -> ParseError {
+    let kind = match err.error {
+        ExpressionError::UnknownCharacter(c) | ExpressionError::UnexpectedToken(c) => {
+            ParseErrorKind::UnexpectedCharacter(c)
+        },
+        ExpressionError::MissingExponent => ParseErrorKind::MissingExponent,
+        ExpressionError::InvalidLiteral(_) => ParseErrorKind::InvalidLiteral,
+        _ => ParseErrorKind::NumberTooLarge,
+    };
+    return ParseError { kind, position: err.span.start };
+}
+
+/// The error the infix functions report for a tokenizer failure:
+/// characters that don't belong are a `ParseError`.
+fn infix_tokenize_error(err: TokenizeError) -> ExpressionError {
+    match err.error {
+        ExpressionError::UnknownCharacter(c) | ExpressionError::UnexpectedToken(c) => {
+            return ExpressionError::Parse(ParseError {
+                kind: ParseErrorKind::UnexpectedCharacter(c),
+                position: err.span.start,
+            });
+        },
+        ExpressionError::MissingExponent => {
+            return ExpressionError::Parse(ParseError { kind: ParseErrorKind::MissingExponent, position: err.span.start });
+        },
+        error => return error,
+    }
+}
+
+/// Checks that operands and operators alternate and that parentheses
+/// balance. `input_len` is where errors at the end of the input point.
+fn check_syntax(tokens: &[SpannedToken], input_len: usize) -> Result<(), ParseError> {
+    match syntax_errors(tokens, input_len).first() {
+        Some(error) => return Err(*error),
+        None => return Ok(()),
+    }
+}
+
+/// `check_syntax` for tokens that weren't read from a string, as passed to
+/// `sy_evaluate_tokens`. A `ParseError` points at the index of the token
+/// rather than at a byte offset.
+fn check_token_syntax(tokens: &[Token]) -> Result<(), ExpressionError> {
+    if tokens.is_empty() {
+        return Err(ExpressionError::EmptyInput);
+    }
+    let spanned: Vec<SpannedToken> = tokens
+        .iter()
+        .enumerate()
+        .map(|(i, token)| SpannedToken { token: token.clone(), span: Span { start: i, end: i + 1 } })
+        .collect();
+    return Ok(check_syntax(&spanned, tokens.len())?);
+}
+
+/// Every problem `check_syntax` can find, the one it reports first. After
+/// each problem the check goes on as if it had been fixed: a missing
+/// operator or operand is assumed, and a stray operator or `)` skipped.
+fn syntax_errors(tokens: &[SpannedToken], input_len: usize) -> Vec<ParseError> {
+    let mut errors: Vec<ParseError> = Vec::new();
+    let mut error = |kind, position| errors.push(ParseError { kind, position });
+
+    // Indices of the `(`s that are still open, and how many arguments
+    // each one has had before the current one if it is a call's.
+    let mut open_parens: Vec<(usize, usize)> = Vec::new();
+    let mut expect_operand = true;
+    let mut previous: Option<&SpannedToken> = None;
+    // The span of the operand that ended last.
+    let mut last_operand = Span { start: 0, end: 0 };
+
+    for (i, spanned) in tokens.iter().enumerate() {
+        let position = spanned.span.start;
+
+        if !expect_operand && spanned.token.starts_operand() {
+            let right_span = operand_start_span(tokens, i, input_len);
+            let hint = missing_operator_hint();
+            error(ParseErrorKind::MissingOperator { left_span: last_operand, right_span, hint }, position);
+            expect_operand = true;
+        }
+
+        match spanned.token {
+            Token::Number(_) | Token::Float(_) | Token::Bool(_) | Token::Ident(_) => {
+                last_operand = spanned.span;
+                expect_operand = false;
+            },
+            Token::ParenLeft => open_parens.push((i, 0)),
+            Token::ParenRight => {
+                let open = open_parens.pop();
+                let start = match open {
+                    Some((open, _)) if is_call(tokens, open) => tokens[open - 1].span.start,
+                    Some((open, _)) => tokens[open].span.start,
+                    None => position,
+                };
+                last_operand = Span { start, end: spanned.span.end };
+                if expect_operand {
+                    match (previous.map(|t| &t.token), open) {
+                        // A call without arguments, which is for its arity to allow.
+                        (Some(Token::ParenLeft), Some((open, _))) if is_call(tokens, open) => {},
+                        (Some(Token::ParenLeft), Some((open, _))) => {
+                            let span = Span { start: tokens[open].span.start, end: spanned.span.end };
+                            error(ParseErrorKind::EmptyParens { span }, position);
+                        },
+                        (Some(Token::Comma), Some((open, arg_index))) if is_call(tokens, open) => {
+                            let call_span = call_span(tokens, open, input_len);
+                            error(ParseErrorKind::EmptyArgument { call_span, arg_index }, position);
+                        },
+                        _ => error(ParseErrorKind::MissingOperand, position),
+                    }
+                    expect_operand = false;
+                }
+                if open.is_none() {
+                    error(ParseErrorKind::UnbalancedParenthesis, position);
+                }
+            },
+            Token::Comma => {
+                match open_parens.last_mut() {
+                    Some((open, arg_index)) if is_call(tokens, *open) => {
+                        if expect_operand {
+                            match previous.map(|t| &t.token) {
+                                Some(Token::ParenLeft | Token::Comma) => {
+                                    let call_span = call_span(tokens, *open, input_len);
+                                    error(ParseErrorKind::EmptyArgument { call_span, arg_index: *arg_index }, position);
+                                },
+                                _ => error(ParseErrorKind::MissingOperand, position),
+                            }
+                        }
+                        *arg_index += 1;
+                    },
+                    _ => error(ParseErrorKind::UnexpectedCharacter(','), position),
+                }
+                expect_operand = true;
+            },
+            // A function is always followed by the `(` of its arguments,
+            // and the prefix operators by their operand.
+            Token::Function(_) | Token::UnaryMinus | Token::Not => {},
+            _ => {
+                if expect_operand {
+                    match previous {
+                        Some(t) if t.token.operator_symbol().is_some() => {
+                            let (first_span, second_span) = (t.span, spanned.span);
+                            error(ParseErrorKind::ConsecutiveOperators { first_span, second_span }, position);
+                        },
+                        None | Some(SpannedToken { token: Token::ParenLeft | Token::Comma, .. }) => {
+                            error(ParseErrorKind::LeadingOperator { span: spanned.span }, position);
+                        },
+                        _ => error(ParseErrorKind::MissingOperand, position),
+                    }
+                }
+                expect_operand = true;
+            },
+        }
+
+        previous = Some(spanned);
+    }
+
+    if expect_operand {
+        match previous {
+            Some(t) if t.token.operator_symbol().is_some() => {
+                error(ParseErrorKind::TrailingOperator { span: t.span }, t.span.start);
+            },
+            _ => error(ParseErrorKind::MissingOperand, input_len),
+        }
+    }
+
+    // Innermost first.
+    while let Some((open, _)) = open_parens.pop() {
+        error(ParseErrorKind::UnbalancedParenthesis, tokens[open].span.start);
+    }
+
+    return errors;
+}
+
+/// Whether the `(` at `tokens[open]` starts the arguments of a call.
+fn is_call(tokens: &[SpannedToken], open: usize) -> bool {
+    return open > 0 && matches!(tokens[open - 1].token, Token::Function(_));
+}
+
+/// Where the group whose `(` is `tokens[open]` ends: after the matching
+/// `)`, or at `input_len` if there isn't one.
+fn group_end(tokens: &[SpannedToken], open: usize, input_len: usize) -> usize {
+    let mut depth = 0;
+    for spanned in &tokens[open..] {
+        match spanned.token {
+            Token::ParenLeft => depth += 1,
+            Token::ParenRight => {
+                depth -= 1;
+                if depth == 0 {
+                    return spanned.span.end;
+                }
+            },
+            _ => {},
+        }
+    }
+    return input_len;
+}
+
+/// The span of the call whose `(` is `tokens[open]`, from the function's
+/// name to the end of its arguments.
+fn call_span(tokens: &[SpannedToken], open: usize, input_len: usize) -> Span {
+    return Span { start: tokens[open - 1].span.start, end: group_end(tokens, open, input_len) };
+}
+
+/// The span of the operand `tokens[i]` starts: all of a group or call, or
+/// just the token otherwise.
+fn operand_start_span(tokens: &[SpannedToken], i: usize, input_len: usize) -> Span {
+    let start = tokens[i].span.start;
+    match tokens[i].token {
+        Token::ParenLeft => return Span { start, end: group_end(tokens, i, input_len) },
+        Token::Function(_) if is_call(tokens, i + 1) => return call_span(tokens, i + 1, input_len),
+        _ => return tokens[i].span,
+    }
+}
+
+/// The span of the first call to `name` in `tokens` with `args` arguments.
+fn call_with_arity(tokens: &[SpannedToken], name: &str, args: usize, input_len: usize) -> Option<Span> {
+    for (i, spanned) in tokens.iter().enumerate() {
+        if spanned.token != Token::Function(name.to_string()) || !is_call(tokens, i + 1) {
+            continue;
+        }
+        let mut depth = 0;
+        let mut count = 0;
+        for (j, inner) in tokens.iter().enumerate().skip(i + 1) {
+            match inner.token {
+                Token::ParenLeft => depth += 1,
+                Token::ParenRight => depth -= 1,
+                Token::Comma if depth == 1 => count += 1,
+                _ => {},
+            }
+            if depth == 0 {
+                // Commas separate arguments, unless there are none at all.
+                if j > i + 2 {
+                    count += 1;
+                }
+                break;
+            }
+        }
+        if count == args {
+            return Some(call_span(tokens, i + 1, input_len));
+        }
+    }
+    return None;
+}
+
+/// How bad a `Diagnostic` is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Severity {
+    /// The expression can't be evaluated.
+    Error,
+    /// The expression evaluates, but probably isn't written as intended.
+    Warning,
+}
+
+/// A problem `validate_all` found, with the span of the input it is about.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub span: Span,
+    pub message: String,
+}
+
+/// Same as `validate`, but reports every problem it can find, in order of
+/// where they are, rather than stopping at the first. Each syntax error is
+/// a `Severity::Error`; redundant parentheses, as in `((3))`, are a
+/// `Severity::Warning`.
+///
+/// If there are no errors, `sy_evaluate_f64` succeeds on `input`: a
+/// well formed expression is also evaluated, to catch what only shows up
+/// then, like division by zero or an unknown function.
+pub fn validate_all(input: &str) -> Vec<Diagnostic> {
+    let mut diagnostics: Vec<Diagnostic> = Vec::new();
+    let mut report = |severity, span, message: String| diagnostics.push(Diagnostic { severity, span, message });
+
+    let mut stream = TokenStream::new(input);
+    let mut tokens: Vec<SpannedToken> = Vec::new();
+    let mut tokenized = true;
+    loop {
+        let Some(result) = stream.next_spanned() else { break; };
+        match result {
+            Ok(token) => tokens.push(token),
+            Err(err) => {
+                let span = err.span;
+                let message = match infix_tokenize_error(err) {
+                    ExpressionError::Parse(error) => error.kind.to_string(),
+                    // A literal too large for its type, quoted as written.
+                    ExpressionError::Overflow => format!("number too large: {}", &input[span.start..span.end]),
+                    error => error.to_string(),
+                };
+                report(Severity::Error, span, message);
+                tokenized = false;
+                stream.recover();
+            },
+        }
+    }
+
+    if tokens.is_empty() {
+        if tokenized {
+            report(Severity::Error, Span { start: 0, end: input.len() }, ExpressionError::EmptyInput.to_string());
+        }
+        return diagnostics;
+    }
+
+    let errors = syntax_errors(&tokens, input.len());
+    for error in &errors {
+        let span = tokens.iter().find(|t| t.span.start == error.position).map_or(
+            Span { start: error.position, end: error.position },
+            |t| t.span,
+        );
+        report(Severity::Error, span, error.kind.to_string());
+    }
+
+    for span in redundant_parentheses(&tokens) {
+        report(Severity::Warning, span, "redundant parentheses".to_string());
+    }
+
+    if tokenized && errors.is_empty() {
+        if let Err(error) = sy_evaluate_f64(input.to_string()) {
+            // A call with the wrong number of arguments is taken to be the
+            // first one that has that many.
+            let call = match &error {
+                ExpressionError::WrongArity { name, got, .. } => call_with_arity(&tokens, name, *got, input.len()),
+                _ => None,
+            };
+            report(Severity::Error, call.unwrap_or(Span { start: 0, end: input.len() }), error.to_string());
+        }
+    }
+
+    diagnostics.sort_by_key(|diagnostic| diagnostic.span.start);
+    return diagnostics;
+}
+
+/// The spans of parenthesized expressions that are all of another
+/// parenthesized expression, like the inner pair of `((3))`. Unmatched
+/// parentheses are skipped.
+fn redundant_parentheses(tokens: &[SpannedToken]) -> Vec<Span> {
+    let mut redundant: Vec<Span> = Vec::new();
+    // Indices of the `(`s still open, with whether each one is a call's.
+    let mut open_parens: Vec<(usize, bool)> = Vec::new();
+    // The `(` index of the group that ended just before the current token.
+    let mut just_closed: Option<usize> = None;
+
+    for (i, spanned) in tokens.iter().enumerate() {
+        let closed = just_closed.take();
+        match spanned.token {
+            Token::ParenLeft => {
+                open_parens.push((i, is_call(tokens, i)));
+            },
+            Token::ParenRight => {
+                let Some((open, call)) = open_parens.pop() else { continue; };
+                if let Some(inner) = closed.filter(|inner| !call && *inner == open + 1) {
+                    redundant.push(Span { start: tokens[inner].span.start, end: tokens[i - 1].span.end });
+                }
+                just_closed = Some(open);
+            },
+            _ => {},
+        }
+    }
+
+    return redundant;
+}
+// ============== SYNTAX CHECK ABOVE =================
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+
+    /// A `ConsecutiveOperators` for the operators at byte ranges `first`
+    /// and `second`.
+    fn consecutive_operators(first: (usize, usize), second: (usize, usize)) -> ParseErrorKind {
+        let span = |(start, end)| Span { start, end };
+        return ParseErrorKind::ConsecutiveOperators { first_span: span(first), second_span: span(second) };
+    }
+
+    /// A `MissingOperator` between the operands at byte ranges `left` and
+    /// `right`.
+    fn missing_operator(left: (usize, usize), right: (usize, usize)) -> ParseErrorKind {
+        let span = |(start, end)| Span { start, end };
+        let hint = missing_operator_hint();
+        return ParseErrorKind::MissingOperator { left_span: span(left), right_span: span(right), hint };
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn parse_rpn_with_digits() {
+        assert_eq!(evaluate_rpn("1 2 +".to_string()).unwrap(), 3);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn parse_rpn_with_numbers() {
+        assert_eq!(evaluate_rpn("11 22 +".to_string()).unwrap(), 33);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn parse_faulty_rpn() {
+        assert_eq!(evaluate_rpn("11 + 22".to_string()), Err(ExpressionError::EmptyStack));
+    }
+
+    #[test]
+    fn test_infix_to_postfix() {
+        assert_eq!(infix_to_rpn("1 + 2 * 3 - 4".to_string()).unwrap(), "1 2 3 * + 4 -");
+        // Each pop compares against the new top of the stack.
+        assert_eq!(infix_to_rpn("1+2+3".to_string()).unwrap(), "1 2 + 3 +");
+        assert_eq!(infix_to_rpn("1 + 2 + 3 * 4 + 5".to_string()).unwrap(), "1 2 + 3 4 * + 5 +");
+        assert_eq!(infix_to_rpn("1 - 2 * 3 ^ 2 + 4".to_string()).unwrap(), "1 2 3 2 ^ * - 4 +");
+        assert_eq!(sy_evaulate("1 - 2 * 3 ^ 2 + 4".to_string()).unwrap(), -13);
+    }
+
+    #[test]
+    fn test_sy_evaluator() {
+        assert_eq!(sy_evaulate("1 + 2 * 3 - 4".to_string()).unwrap(), 3);
+    }
+
+    #[test]
+    fn test_tokenizer() {
+        let tokens: Vec<Token> = vec![
+            Token::Number(1),
+            Token::Plus,
+            Token::Number(2),
+            Token::Asterisk,
+            Token::Number(3),
+            Token::Minus,
+            Token::Number(4),
+        ];
+
+        let resulting_tokens = Tokenizer::tokenize("1 + 2 * 3 - 4").unwrap();
+
+        for (token1, token2) in tokens.iter().zip(resulting_tokens.iter()) {
+            assert_eq!(token1, token2);
+        }
+    }
+
+    #[test]
+    fn test_tokenizer_without_spaces() {
+        let tokens = Tokenizer::tokenize("12+34").unwrap();
+        assert_eq!(tokens, vec![Token::Number(12), Token::Plus, Token::Number(34)]);
+
+        let tokens = Tokenizer::tokenize("(1+23)*456").unwrap();
+        assert_eq!(tokens, vec![
+            Token::ParenLeft,
+            Token::Number(1),
+            Token::Plus,
+            Token::Number(23),
+            Token::ParenRight,
+            Token::Asterisk,
+            Token::Number(456),
+        ]);
+
+        let tokens = Tokenizer::tokenize("1.5^2").unwrap();
+        assert_eq!(tokens, vec![Token::Float(1.5), Token::Caret, Token::Number(2)]);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_unknown_character() {
+        assert_eq!(evaluate_rpn("1 2 $".to_string()), Err(ExpressionError::UnknownCharacter('$')));
+        assert_eq!(infix_to_rpn("1 $ 2".to_string()), Err(ExpressionError::Parse(ParseError { kind: ParseErrorKind::UnexpectedCharacter('$'), position: 2 })));
+        assert_eq!(sy_evaulate("1 $ 2".to_string()), Err(ExpressionError::Parse(ParseError { kind: ParseErrorKind::UnexpectedCharacter('$'), position: 2 })));
+        assert_eq!(Tokenizer::tokenize("1 $ 2"), Err(ExpressionError::UnknownCharacter('$')));
+    }
+
+    #[test]
+    fn test_mismatched_parentheses() {
+        assert_eq!(infix_to_rpn("1 + 2)".to_string()), Err(ExpressionError::Parse(ParseError { kind: ParseErrorKind::UnbalancedParenthesis, position: 5 })));
+        assert_eq!(infix_to_rpn("(1".to_string()), Err(ExpressionError::Parse(ParseError { kind: ParseErrorKind::UnbalancedParenthesis, position: 0 })));
+        assert_eq!(sy_evaulate("1 * 2)".to_string()), Err(ExpressionError::Parse(ParseError { kind: ParseErrorKind::UnbalancedParenthesis, position: 5 })));
+        assert_eq!(sy_evaulate("(1".to_string()), Err(ExpressionError::Parse(ParseError { kind: ParseErrorKind::UnbalancedParenthesis, position: 0 })));
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_empty_stack() {
+        assert_eq!(evaluate_rpn("1 +".to_string()), Err(ExpressionError::EmptyStack));
+        assert_eq!(sy_evaulate("1 *".to_string()), Err(ExpressionError::Parse(ParseError {
+            kind: ParseErrorKind::TrailingOperator { span: Span { start: 2, end: 3 } },
+            position: 2,
+        })));
+        assert_eq!(sy_evaulate("".to_string()), Err(ExpressionError::EmptyInput));
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_rpn_caret_is_exponentiation() {
+        assert_eq!(evaluate_rpn("2 3 ^".to_string()).unwrap(), 8);
+
+        let rpn = infix_to_rpn("2 ^ 3".to_string()).unwrap();
+        assert_eq!(evaluate_rpn(rpn).unwrap(), sy_evaulate("2 ^ 3".to_string()).unwrap());
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_rpn_too_many_operands() {
+        assert_eq!(evaluate_rpn("1 2 3 +".to_string()), Err(EvalError::TooManyOperands));
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_rpn_empty_input() {
+        assert_eq!(evaluate_rpn("".to_string()), Err(EvalError::EmptyInput));
+        assert_eq!(evaluate_rpn("   ".to_string()), Err(EvalError::EmptyInput));
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_rpn_number_at_end_of_input() {
+        assert_eq!(evaluate_rpn("5".to_string()).unwrap(), 5);
+        assert_eq!(evaluate_rpn("42".to_string()).unwrap(), 42);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_rpn_numbers_adjacent_to_operators() {
+        assert_eq!(evaluate_rpn("1 2+".to_string()).unwrap(), 3);
+        assert_eq!(evaluate_rpn("12 3+".to_string()).unwrap(), 15);
+        assert_eq!(evaluate_rpn("2 3 4*+".to_string()).unwrap(), 14);
+        assert_eq!(evaluate_rpn("10 20+ 30".to_string()), Err(EvalError::TooManyOperands));
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_rpn_negative_literals() {
+        assert_eq!(evaluate_rpn("-5 3 +".to_string()), Ok(-2));
+        assert_eq!(evaluate_rpn("10 -3 *".to_string()), Ok(-30));
+        assert_eq!(evaluate_rpn("-1 -2 -".to_string()), Ok(1));
+        // A `-` with no number right after it still subtracts.
+        assert_eq!(evaluate_rpn("5 3 - -2 *".to_string()), Ok(-4));
+        assert_eq!(evaluate_rpn_value("-.5 -1e1 *"), Ok(Value::Float(5.0)));
+        assert_eq!(evaluate_rpn("- 5".to_string()), Err(EvalError::EmptyStack));
+
+        assert_eq!(evaluate_rpn_f64("2.5 4 *".to_string()), Ok(10.0));
+        assert_eq!(evaluate_rpn_value("2.5 4 *"), Ok(Value::Float(10.0)));
+        assert_eq!(evaluate_rpn("2.5 4 *".to_string()), Err(EvalError::FloatInIntegerMode(2.5)));
+        assert_eq!(evaluate_rpn("-4 2 /".to_string()), Ok(-2));
+        // A literal, and the operator.
+        assert_eq!(evaluate_rpn("3 -4 +".to_string()), Ok(-1));
+        assert_eq!(evaluate_rpn("3 4 -".to_string()), Ok(-1));
+        assert_eq!(evaluate_rpn_f64("1.5e2 -2E-1 *".to_string()), Ok(-30.0));
+        assert_eq!(evaluate_rpn_f64("-1e3 4 /".to_string()), Ok(-250.0));
+
+        let tokens = tokenize_rpn("-4 2 /", default_operators()).unwrap();
+        assert_eq!(tokens, vec![Token::Number(-4), Token::Number(2), Token::Slash]);
+        assert_eq!(evaluate_rpn_tokens(&tokens), Ok(-2));
+    }
+
+    #[test]
+    fn test_rpn_unchecked() {
+        assert_eq!(evaluate_rpn_unchecked("1 2 +".to_string()), 3);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_rpn_unchecked_panics() {
+        evaluate_rpn_unchecked("1 +".to_string());
+    }
+
+    #[test]
+    fn test_division_by_zero() {
+        assert_eq!(sy_evaulate("1 / 0".to_string()), Err(ExpressionError::DivisionByZero));
+    }
+
+    #[test]
+    fn test_unexpected_token() {
+        assert_eq!(default_operators().get_precedence('('), Err(ExpressionError::UnexpectedToken('(')));
+        assert_eq!(default_operators().get_associativity(')'), Err(ExpressionError::UnexpectedToken(')')));
+    }
+
+    #[test]
+    fn test_multi_digit_numbers() {
+        assert_eq!(infix_to_rpn("12 + 3".to_string()).unwrap(), "12 3 +");
+        assert_eq!(infix_to_rpn("100 * 25 - 7".to_string()).unwrap(), "100 25 * 7 -");
+        assert_eq!(sy_evaulate("10 + 2".to_string()).unwrap(), 12);
+        assert_eq!(sy_evaulate("100 * 25 - 7".to_string()).unwrap(), 2493);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_infix_to_rpn_keeps_numbers_whole() {
+        assert_eq!(infix_to_rpn("12 + 3".to_string()).unwrap(), "12 3 +");
+        assert_eq!(infix_to_rpn("1234567 * 89".to_string()).unwrap(), "1234567 89 *");
+
+        for input in ["12 + 3", "99 * 99 + 1", "(250 + 750) * 10"] {
+            let rpn = infix_to_rpn(input.to_string()).unwrap();
+            assert_eq!(evaluate_rpn(rpn).unwrap(), sy_evaulate(input.to_string()).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_numbers_adjacent_to_parentheses() {
+        assert_eq!(infix_to_rpn("(12+34)*2".to_string()).unwrap(), "12 34 + 2 *");
+        assert_eq!(sy_evaulate("(12+34)*2".to_string()).unwrap(), 92);
+        assert_eq!(sy_evaulate("2*(100-1)".to_string()).unwrap(), 198);
+    }
+
+    #[test]
+    fn test_no_whitespace() {
+        assert_eq!(infix_to_rpn("12+3*45".to_string()).unwrap(), "12 3 45 * +");
+        assert_eq!(sy_evaulate("12+3*45".to_string()).unwrap(), 147);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_f64_evaluators() {
+        assert_eq!(evaluate_rpn_f64("2.5 4 *".to_string()).unwrap(), 10.0);
+        assert_eq!(evaluate_rpn_f64("10 4 /".to_string()).unwrap(), 2.5);
+        assert_eq!(sy_evaluate_f64("3.5 * 2 + 0.25".to_string()).unwrap(), 7.25);
+        assert_eq!(sy_evaluate_f64("10 / 4".to_string()).unwrap(), 2.5);
+        assert_eq!(sy_evaluate_f64("2 ^ 0.5 ^ 2".to_string()).unwrap(), 2.0_f64.powf(0.25));
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_infix_to_rpn_f64() {
+        assert_eq!(infix_to_rpn_f64("3.14 * 2".to_string()).unwrap(), "3.14 2 *");
+        assert_eq!(infix_to_rpn_f64("1.5 + 22 * 3".to_string()).unwrap(), "1.5 22 3 * +");
+
+        let rpn = infix_to_rpn_f64("1.5 + 22 * 3".to_string()).unwrap();
+        assert_eq!(evaluate_rpn_f64(rpn).unwrap(), sy_evaluate_f64("1.5 + 22 * 3".to_string()).unwrap());
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_f64_errors() {
+        assert_eq!(sy_evaluate_f64("1 / 0".to_string()), Err(ExpressionError::DivisionByZero));
+        assert_eq!(sy_evaluate_f64("1.2.3".to_string()), Err(ExpressionError::Parse(ParseError {
+            kind: ParseErrorKind::UnexpectedCharacter('.'),
+            position: 3,
+        })));
+        assert_eq!(evaluate_rpn_f64("10 400 ^".to_string()), Err(ExpressionError::Overflow));
+    }
+
+    #[test]
+    fn test_tokenizer_float() {
+        let tokens = Tokenizer::tokenize("2.75 * 2").unwrap();
+        assert_eq!(tokens[0], Token::Float(2.75));
+    }
+
+    #[test]
+    fn test_unary_minus() {
+        assert_eq!(sy_evaulate("-5 + 3".to_string()).unwrap(), -2);
+        assert_eq!(sy_evaulate("3 * (-2)".to_string()).unwrap(), -6);
+        assert_eq!(sy_evaulate("3 * -2".to_string()).unwrap(), -6);
+        assert_eq!(sy_evaulate("--3".to_string()).unwrap(), 3);
+        assert_eq!(sy_evaulate("-(1 + 2) * 4".to_string()).unwrap(), -12);
+        assert_eq!(sy_evaluate_f64("-2.5 * 2".to_string()).unwrap(), -5.0);
+    }
+
+    #[test]
+    fn test_unary_minus_with_caret() {
+        assert_eq!(sy_evaulate("-2^2".to_string()).unwrap(), -4);
+        assert_eq!(sy_evaulate("(-2)^2".to_string()).unwrap(), 4);
+        assert_eq!(sy_evaluate_f64("2^-1".to_string()).unwrap(), 0.5);
+        assert_eq!(sy_evaulate("2^-1".to_string()), Err(ExpressionError::NegativeExponent));
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_unary_minus_rpn() {
+        assert_eq!(infix_to_rpn("-5 + 3".to_string()).unwrap(), "5 ~ 3 +");
+        assert_eq!(infix_to_rpn("-2^2".to_string()).unwrap(), "2 2 ^ ~");
+        assert_eq!(infix_to_rpn("--3".to_string()).unwrap(), "3 ~ ~");
+        assert_eq!(evaluate_rpn("5 ~ 3 +".to_string()).unwrap(), -2);
+
+        for input in ["-5 + 3", "3 * (-2)", "--3", "-2^2"] {
+            let rpn = infix_to_rpn(input.to_string()).unwrap();
+            assert_eq!(evaluate_rpn(rpn).unwrap(), sy_evaulate(input.to_string()).unwrap());
+            let rpn = infix_to_rpn_f64(input.to_string()).unwrap();
+            assert_eq!(evaluate_rpn_f64(rpn).unwrap(), sy_evaluate_f64(input.to_string()).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_tokenizer_unary_minus() {
+        let tokens = Tokenizer::tokenize("-1-(-2)").unwrap();
+        assert_eq!(tokens, vec![
+            Token::UnaryMinus,
+            Token::Number(1),
+            Token::Minus,
+            Token::ParenLeft,
+            Token::UnaryMinus,
+            Token::Number(2),
+            Token::ParenRight,
+        ]);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_large_intermediate_values() {
+        assert_eq!(sy_evaulate("3000000000 * 3".to_string()).unwrap(), 9_000_000_000);
+        assert_eq!(sy_evaulate("2 ^ 40 / 2 ^ 38".to_string()).unwrap(), 4);
+        assert_eq!(evaluate_rpn("4294967296 2 *".to_string()).unwrap(), 8_589_934_592);
+    }
+
+    #[test]
+    fn test_negative_subtraction_results() {
+        assert_eq!(sy_evaulate("2 - 5".to_string()).unwrap(), -3);
+        assert_eq!(sy_evaulate("1 - 2 - 3".to_string()).unwrap(), -4);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_integer_overflow() {
+        assert_eq!(sy_evaulate("9223372036854775807 * 2".to_string()), Err(ExpressionError::Overflow));
+        assert_eq!(sy_evaulate("9223372036854775807 + 1".to_string()), Err(ExpressionError::Overflow));
+        assert_eq!(sy_evaulate("-9223372036854775807 - 2".to_string()), Err(ExpressionError::Overflow));
+        assert_eq!(sy_evaulate("2 ^ 63".to_string()), Err(ExpressionError::Overflow));
+        assert_eq!(evaluate_rpn("9223372036854775807 2 *".to_string()), Err(ExpressionError::Overflow));
+        assert_eq!(sy_evaulate("99999999999999999999".to_string()), Err(ExpressionError::Overflow));
+    }
+
+    #[test]
+    fn test_integer_and_float_division() {
+        assert_eq!(sy_evaulate("1 / 4".to_string()).unwrap(), 0);
+        assert_eq!(sy_evaluate_f64("1 / 4".to_string()).unwrap(), 0.25);
+        assert_eq!(sy_evaulate("7 / 2".to_string()).unwrap(), 3);
+        assert_eq!(sy_evaluate_f64("7 / 2".to_string()).unwrap(), 3.5);
+    }
+
+    #[test]
+    fn test_float_mode_shares_integer_grammar() {
+        for input in ["1 + 2 * 3 - 4", "(12+34)*2", "-2^2", "2 ^ 3 ^ 2"] {
+            let int_result = sy_evaulate(input.to_string()).unwrap();
+            assert_eq!(sy_evaluate_f64(input.to_string()).unwrap(), int_result as f64);
+            assert_eq!(infix_to_rpn_f64(input.to_string()).unwrap(), infix_to_rpn(input.to_string()).unwrap());
+        }
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_decimal_point_rejected_in_integer_mode() {
+        assert_eq!(sy_evaulate("1.5 + 1".to_string()), Err(ExpressionError::FloatInIntegerMode(1.5)));
+        // Exponents make floats too, with no `.` to point at.
+        assert_eq!(sy_evaulate("1e3".to_string()), Err(ExpressionError::FloatInIntegerMode(1000.0)));
+        assert_eq!(infix_to_rpn("1e3 + 2".to_string()), Err(ExpressionError::FloatInIntegerMode(1000.0)));
+        assert_eq!(evaluate_rpn_tokens(&[Token::Float(1.5)]), Err(ExpressionError::FloatInIntegerMode(1.5)));
+        assert_eq!(sy_evaulate("1e3".to_string()).unwrap_err().to_string(), "float literal 1000 in integer mode");
+        assert_eq!(evaluate_rpn_f64("1.2.3 1 +".to_string()), Err(ExpressionError::UnexpectedToken('.')));
+    }
+
+    #[test]
+    fn test_format_f64() {
+        assert_eq!(format_f64(sy_evaluate_f64("0.1 + 0.2".to_string()).unwrap()), "0.3");
+        assert_eq!(format_f64(sy_evaluate_f64("3.5 * 2 + 0.25".to_string()).unwrap()), "7.25");
+        assert_eq!(format_f64(7.000000000000001), "7");
+        assert_eq!(format_f64(0.25), "0.25");
+        assert_eq!(format_f64(-1.0 / 3.0), "-0.333333333333333");
+    }
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(Tokenizer::tokenize("2*(3+4)").unwrap(), vec![
+            Token::Number(2),
+            Token::Asterisk,
+            Token::ParenLeft,
+            Token::Number(3),
+            Token::Plus,
+            Token::Number(4),
+            Token::ParenRight,
+        ]);
+        assert_eq!(Tokenizer::tokenize("1 ~ 2"), Err(ExpressionError::UnknownCharacter('~')));
+    }
+
+    #[test]
+    fn test_evaluate_rpn_tokens() {
+        let tokens = vec![Token::Number(10), Token::Number(4), Token::Minus, Token::UnaryMinus];
+        assert_eq!(evaluate_rpn_tokens(&tokens).unwrap(), -6);
+        assert_eq!(evaluate_rpn_tokens(&[Token::Number(1), Token::Plus]), Err(ExpressionError::EmptyStack));
+        assert_eq!(evaluate_rpn_tokens(&[]), Err(ExpressionError::EmptyInput));
+        assert_eq!(evaluate_rpn_tokens(&[Token::ParenLeft]), Err(ExpressionError::UnexpectedToken('(')));
+    }
+
+    #[test]
+    fn test_tokenizer_drives_every_path() {
+        // Tabs and newlines are whitespace everywhere now that all the
+        // infix paths share one tokenizer.
+        assert_eq!(sy_evaulate("10\t+\n20 * 3".to_string()).unwrap(), 70);
+        assert_eq!(infix_to_rpn("10\t+\n20 * 3".to_string()).unwrap(), "10 20 3 * +");
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_unary_minus_after_operators_and_parens() {
+        for (input, expected) in [("(-3 + 4) * -2", -2), ("--5", 5), ("-3 - -3", 0), ("2 ^ 2 * -(1 + 1)", -8)] {
+            assert_eq!(sy_evaulate(input.to_string()).unwrap(), expected);
+            assert_eq!(sy_evaluate_f64(input.to_string()).unwrap(), expected as f64);
+
+            let rpn = infix_to_rpn(input.to_string()).unwrap();
+            assert_eq!(evaluate_rpn(rpn).unwrap(), expected);
+        }
+
+        assert_eq!(infix_to_rpn("(-3 + 4) * -2".to_string()).unwrap(), "3 ~ 4 + 2 ~ *");
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_rpn_non_commutative_operators() {
+        assert_eq!(evaluate_rpn("5 3 -".to_string()).unwrap(), 2);
+        assert_eq!(evaluate_rpn("6 2 /".to_string()).unwrap(), 3);
+        assert_eq!(evaluate_rpn("2 3 ^".to_string()).unwrap(), 8);
+        assert_eq!(evaluate_rpn("1 0 /".to_string()), Err(ExpressionError::DivisionByZero));
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_rpn_agrees_with_sy_evaluate() {
+        let expressions = [
+            "1 + 2 * 3 - 4",
+            "10 - 4 - 3",
+            "100 / 10 / 5",
+            "2 ^ 3 ^ 2",
+            "(8 - 3) * (6 / 2)",
+            "7 - 2 * 3 + 18 / 6",
+            "-(4 - 9) * 2",
+            "2 * 3 ^ 2 - 1",
+        ];
+
+        for input in expressions {
+            let rpn = infix_to_rpn(input.to_string()).unwrap();
+            assert_eq!(evaluate_rpn(rpn).unwrap(), sy_evaulate(input.to_string()).unwrap(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_tokenizer_spans() {
+        let input = "12 + (3.5*$)";
+        let err = Tokenizer::new(input.to_string()).err().unwrap();
+        assert_eq!(err, TokenizeError {
+            error: ExpressionError::UnknownCharacter('$'),
+            span: Span { start: 10, end: 11 },
+        });
+
+        let input = "12 + (3.5*4)";
+        let tokenizer = Tokenizer::new(input.to_string()).unwrap();
+        let texts: Vec<&str> = tokenizer.iter().map(|t| &input[t.span.start..t.span.end]).collect();
+        assert_eq!(texts, vec!["12", "+", "(", "3.5", "*", "4", ")"]);
+
+        let mut count = 0;
+        for spanned in &tokenizer {
+            assert!(spanned.span.start < spanned.span.end);
+            count += 1;
+        }
+        assert_eq!(count, 7);
+
+        let tokens: Vec<Token> = tokenizer.map(|t| t.token).collect();
+        assert_eq!(tokens, Tokenizer::tokenize(input).unwrap());
+    }
+
+    #[test]
+    fn test_tokenize_error_positions() {
+        let err = Tokenizer::new("1 + $ * 3".to_string()).err().unwrap();
+        assert_eq!(err.span, Span { start: 4, end: 5 });
+        assert_eq!(err.to_string(), "unknown character: $ at position 4");
+
+        let err = Tokenizer::new("1 + 1.2.3".to_string()).err().unwrap();
+        assert_eq!(err.error, ExpressionError::UnexpectedToken('.'));
+        assert_eq!(err.span, Span { start: 7, end: 8 });
+
+        let err = Tokenizer::new("1 + 99999999999999999999".to_string()).err().unwrap();
+        assert_eq!(err.error, ExpressionError::Overflow);
+        assert_eq!(err.span, Span { start: 4, end: 24 });
+    }
+
+    #[test]
+    fn test_variables() {
+        let variables = HashMap::from([("x".to_string(), 3.0), ("y_2".to_string(), 0.5)]);
+
+        assert_eq!(sy_evaluate_with_vars("x * 2 + y_2".to_string(), &variables).unwrap(), 6.5);
+        assert_eq!(sy_evaluate_with_vars("-x^2".to_string(), &variables).unwrap(), -9.0);
+        assert_eq!(sy_evaluate_with_vars("x-1".to_string(), &variables).unwrap(), 2.0);
+        assert_eq!(infix_to_rpn("x * 2 + y_2".to_string()).unwrap(), "x 2 * y_2 +");
+        assert_eq!(evaluate_rpn_with_vars("x 2 * y_2 +".to_string(), &variables).unwrap(), 6.5);
+        assert_eq!(parse("x * 2 + y_2").unwrap().eval_with_vars(&variables).unwrap(), 6.5);
+
+        assert_eq!(
+            sy_evaluate_with_vars("x + z".to_string(), &variables),
+            Err(ExpressionError::UndefinedVariable("z".to_string()))
+        );
+        assert_eq!(sy_evaulate("x + 1".to_string()), Err(ExpressionError::UndefinedVariable("x".to_string())));
+        assert_eq!(Tokenizer::tokenize("_a1+b").unwrap(), vec![
+            Token::Ident("_a1".to_string()),
+            Token::Plus,
+            Token::Ident("b".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn test_parse_error_positions() {
+        let parse_error = |input: &str| match sy_evaulate(input.to_string()) {
+            Err(ExpressionError::Parse(err)) => (err.kind, err.position),
+            other => panic!("expected a parse error for {:?}, got {:?}", input, other),
+        };
+
+        assert_eq!(parse_error("(1 + 2"), (ParseErrorKind::UnbalancedParenthesis, 0));
+        assert_eq!(parse_error("1 + (2 * (3)"), (ParseErrorKind::UnbalancedParenthesis, 4));
+        assert_eq!(parse_error("1 + 2)"), (ParseErrorKind::UnbalancedParenthesis, 5));
+        assert_eq!(parse_error("1 + * 2"), (consecutive_operators((2, 3), (4, 5)), 4));
+        assert_eq!(parse_error("* 2"), (ParseErrorKind::LeadingOperator { span: Span { start: 0, end: 1 } }, 0));
+        assert_eq!(parse_error("(* 2)"), (ParseErrorKind::LeadingOperator { span: Span { start: 1, end: 2 } }, 1));
+        assert_eq!(parse_error("1 + ()"), (ParseErrorKind::EmptyParens { span: Span { start: 4, end: 6 } }, 5));
+        assert_eq!(parse_error("(1 +)"), (ParseErrorKind::MissingOperand, 4));
+        assert_eq!(parse_error("("), (ParseErrorKind::MissingOperand, 1));
+        assert_eq!(parse_error("1 2"), (missing_operator((0, 1), (2, 3)), 2));
+        assert_eq!(parse_error("2 (3)"), (missing_operator((0, 1), (2, 5)), 2));
+        assert_eq!(parse_error("1 + 2 -"), (ParseErrorKind::TrailingOperator { span: Span { start: 6, end: 7 } }, 6));
+        assert_eq!(parse_error("2 * -"), (ParseErrorKind::TrailingOperator { span: Span { start: 4, end: 5 } }, 4));
+        assert_eq!(parse_error("1 + $ * 3"), (ParseErrorKind::UnexpectedCharacter('$'), 4));
+        assert_eq!(parse_error("1.2.3"), (ParseErrorKind::UnexpectedCharacter('.'), 3));
+
+        assert_eq!(infix_to_rpn("1 + * 2".to_string()).unwrap_err().to_string(), "consecutive operators at position 4");
+        // Well formed input still reports evaluation errors as before.
+        assert_eq!(sy_evaulate("1 / (2 - 2)".to_string()), Err(ExpressionError::DivisionByZero));
+        assert_eq!(sy_evaulate("1 - -2".to_string()).unwrap(), 3);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_functions() {
+        assert_eq!(sy_evaluate_f64("sqrt(16) + abs(-3)".to_string()).unwrap(), 7.0);
+        assert_eq!(sy_evaluate_f64("floor(2.7) * ceil(1.2)".to_string()).unwrap(), 4.0);
+        assert_eq!(sy_evaluate_f64("sin(0) + cos(0) + tan(0) + ln(1)".to_string()).unwrap(), 1.0);
+        assert_eq!(sy_evaluate_f64("sqrt(abs(-16))".to_string()).unwrap(), 4.0);
+        assert_eq!(sy_evaluate_f64("-sqrt (4)^2".to_string()).unwrap(), -4.0);
+        assert_eq!(sy_evaulate("abs(2 - 5) * 2".to_string()).unwrap(), 6);
+        // The function under a `(` is called when its `)` closes, not left on the stack.
+        assert_eq!(sy_evaulate("sqrt(16)".to_string()).unwrap(), 4);
+        assert_eq!(sy_evaulate("abs(-3)".to_string()).unwrap(), 3);
+        assert_eq!(sy_evaulate("abs((1 - 4) * (2)) - max((1), -(2))".to_string()).unwrap(), 5);
+        assert_eq!(infix_to_rpn("abs((1 - 4) * (2))".to_string()).unwrap(), "1 4 - 2 * abs");
+
+        assert_eq!(infix_to_rpn("sqrt(16) + abs(1 - 2)".to_string()).unwrap(), "16 sqrt 1 2 - abs +");
+        assert_eq!(evaluate_rpn_f64("16 sqrt 1 2 - abs +".to_string()).unwrap(), 5.0);
+        assert_eq!(evaluate_rpn("16 sqrt 1 2 - abs +".to_string()).unwrap(), 5);
+
+        assert_eq!(sy_evaluate_f64("foo(1)".to_string()), Err(ExpressionError::UnknownFunction("foo".to_string())));
+        assert_eq!(sy_evaluate_f64("sqrt(-1)".to_string()), Err(ExpressionError::NotANumber));
+        assert_eq!(sy_evaulate("sqrt(2)".to_string()), Err(ExpressionError::NonIntegerResult));
+        assert_eq!(sy_evaluate_f64("sqrt()".to_string()), Err(ExpressionError::WrongArity {
+            name: "sqrt".to_string(),
+            expected: 1,
+            got: 0,
+        }));
+    }
+
+    #[test]
+    fn test_custom_functions() {
+        let mut functions = Functions::new();
+        functions.insert("double", Function::Unary(|x| x * 2.0));
+        let variables = HashMap::from([("x".to_string(), 1.5)]);
+
+        assert_eq!(sy_evaluate_with("double(x) + 1".to_string(), &variables, &functions).unwrap(), 4.0);
+        assert_eq!(evaluate_rpn_with("x double".to_string(), &variables, &functions).unwrap(), 3.0);
+        assert_eq!(
+            sy_evaluate_with("sqrt(4)".to_string(), &variables, &functions),
+            Err(ExpressionError::UnknownFunction("sqrt".to_string()))
+        );
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_multi_argument_functions() {
+        assert_eq!(sy_evaluate_f64("max(3, 7)".to_string()).unwrap(), 7.0);
+        assert_eq!(sy_evaluate_f64("min(3, -7) + pow(2, 1 + 2)".to_string()).unwrap(), 1.0);
+        assert_eq!(sy_evaluate_f64("hypot(3, 4) * log(2, 8)".to_string()).unwrap(), 15.0);
+        assert_eq!(sy_evaluate_f64("max(min(1, 2), (3 - 1) * 2)".to_string()).unwrap(), 4.0);
+        assert_eq!(sy_evaulate("max(2, 3) * 2".to_string()).unwrap(), 6);
+
+        assert_eq!(infix_to_rpn("max(1 + 2, 3 * 4)".to_string()).unwrap(), "1 2 + 3 4 * max");
+        assert_eq!(evaluate_rpn_f64("1 2 + 3 4 * max".to_string()).unwrap(), 12.0);
+
+        let mut functions = Functions::default();
+        functions.insert("clamp", Function::Nary(3, |args| args[0].max(args[1]).min(args[2])));
+        let variables = HashMap::from([("x".to_string(), 150.0)]);
+        assert_eq!(sy_evaluate_with("clamp(x, 0, 100)".to_string(), &variables, &functions).unwrap(), 100.0);
+        assert_eq!(evaluate_rpn_with("x 0 100 clamp".to_string(), &variables, &functions).unwrap(), 100.0);
+
+        assert_eq!(sy_evaluate_f64("max(1)".to_string()), Err(ExpressionError::WrongArity {
+            name: "max".to_string(),
+            expected: 2,
+            got: 1,
+        }));
+        assert_eq!(sy_evaluate_f64("sqrt(1, 2)".to_string()), Err(ExpressionError::WrongArity {
+            name: "sqrt".to_string(),
+            expected: 1,
+            got: 2,
+        }));
+        for (input, kind, position) in [
+            ("1, 2", ParseErrorKind::UnexpectedCharacter(','), 1),
+            ("max((1, 2))", ParseErrorKind::UnexpectedCharacter(','), 6),
+            ("max(1, )", ParseErrorKind::EmptyArgument { call_span: Span { start: 0, end: 8 }, arg_index: 1 }, 7),
+            ("max(, 1)", ParseErrorKind::EmptyArgument { call_span: Span { start: 0, end: 8 }, arg_index: 0 }, 4),
+        ] {
+            assert_eq!(
+                sy_evaluate_f64(input.to_string()),
+                Err(ExpressionError::Parse(ParseError { kind, position })),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_evaluate_with_env() {
+        let env = HashMap::from([("x".to_string(), 3_i64), ("rate_2".to_string(), 2)]);
+        assert_eq!(evaluate_with_env("x * 2 + rate_2", &env).unwrap(), 8.0);
+        assert_eq!(
+            evaluate_with_env("x + y", &env),
+            Err(ExpressionError::UndefinedVariable("y".to_string()))
+        );
+
+        // Variables and functions don't collide: only a call looks up a
+        // function.
+        let env = HashMap::from([("max".to_string(), 10.0)]);
+        assert_eq!(evaluate_with_env("max(max, 2) + max", &env).unwrap(), 20.0);
+
+        struct Cells;
+        impl Environment for Cells {
+            fn lookup(self: &Self, name: &str) -> Option<f64> {
+                let row = name.strip_prefix('A')?;
+                return row.parse::<f64>().ok();
+            }
+        }
+        assert_eq!(evaluate_with_env("A1 + A41", &Cells).unwrap(), 42.0);
+        assert_eq!(parse("A2 * A3").unwrap().eval_with(&Cells, &Functions::default()).unwrap(), 6.0);
+        assert_eq!(evaluate_with_env("B1", &Cells), Err(ExpressionError::UndefinedVariable("B1".to_string())));
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_modulo() {
+        assert_eq!(sy_evaulate("10 % 3".to_string()).unwrap(), 1);
+        assert_eq!(sy_evaulate("(5 + 7) % 4".to_string()).unwrap(), 0);
+        assert_eq!(sy_evaulate("2 + 10 % 4".to_string()).unwrap(), 4);
+        assert_eq!(sy_evaulate("10 % 4 * 3".to_string()).unwrap(), 6);
+        assert_eq!(sy_evaulate("-7 % 3".to_string()).unwrap(), -1);
+        assert_eq!(sy_evaluate_f64("7.5 % 2".to_string()).unwrap(), 1.5);
+        assert_eq!(infix_to_rpn("20 % 6 % 4".to_string()).unwrap(), "20 6 % 4 %");
+        assert_eq!(evaluate_rpn("20 6 % 4 %".to_string()).unwrap(), 2);
+
+        assert_eq!(sy_evaulate("1 % 0".to_string()), Err(ExpressionError::DivisionByZero));
+        assert_eq!(sy_evaluate_f64("1 % 0".to_string()), Err(ExpressionError::DivisionByZero));
+        assert_eq!(evaluate_rpn("1 0 %".to_string()), Err(ExpressionError::DivisionByZero));
+        assert_eq!(sy_evaulate("(-9223372036854775807 - 1) % -1".to_string()), Err(ExpressionError::Overflow));
+    }
+
+    #[test]
+    fn test_function_call_syntax() {
+        assert_eq!(sy_evaluate_f64("sqrt(16) + max(2, 3) * abs(-5)".to_string()).unwrap(), 19.0);
+        assert_eq!(sy_evaluate_f64("max(sqrt(16), 3 + 2)".to_string()).unwrap(), 5.0);
+        assert_eq!(sy_evaluate_f64("abs(-5)".to_string()).unwrap(), 5.0);
+        assert_eq!(sy_evaluate_f64("(min(floor(2.5), ceil(0.5)))".to_string()).unwrap(), 1.0);
+        assert_eq!(parse("sqrt(16) + max(2, 3) * abs(-5)").unwrap().eval().unwrap(), 19.0);
+
+        assert_eq!(sy_evaluate_f64("max(1)".to_string()).unwrap_err().to_string(), "max takes 2 argument(s) but was given 1");
+        assert_eq!(sy_evaluate_f64("nope(1)".to_string()).unwrap_err().to_string(), "unknown function: nope");
+    }
+
+    #[test]
+    fn test_modulo_precedence_and_signs() {
+        assert_eq!(sy_evaulate("10 + 7 % 3".to_string()).unwrap(), 11);
+        assert_eq!(sy_evaulate("2 ^ 5 % 3".to_string()).unwrap(), 2);
+        assert_eq!(sy_evaulate("7 % 3 ^ 2".to_string()).unwrap(), 7);
+        assert_eq!(infix_to_rpn("10 + 7 % 3".to_string()).unwrap(), "10 7 3 % +");
+
+        // The result takes the sign of the left operand.
+        assert_eq!(sy_evaulate("-7 % 3".to_string()).unwrap(), -1);
+        assert_eq!(sy_evaulate("7 % -3".to_string()).unwrap(), 1);
+        assert_eq!(sy_evaulate("-7 % -3".to_string()).unwrap(), -1);
+        assert_eq!(sy_evaluate_f64("-7.5 % 2".to_string()).unwrap(), -1.5);
+    }
+
+    #[test]
+    fn test_sy_evaluate_traced() {
+        let (result, steps) = sy_evaluate_traced("2 * (3 + 4) - 5").unwrap();
+        assert_eq!(result, 9);
+        assert_eq!(result, sy_evaulate("2 * (3 + 4) - 5".to_string()).unwrap());
+
+        let tokens: Vec<&str> = steps.iter().map(|step| step.token.as_str()).collect();
+        assert_eq!(tokens, vec!["2", "*", "(", "3", "+", "4", ")", "-", "5", "-"]);
+
+        use StepAction::*;
+        let actions: Vec<StepAction> = steps.iter().map(|step| step.action).collect();
+        assert_eq!(actions, vec![
+            PushOperand,
+            PushOperator,
+            PushOperator,
+            PushOperand,
+            PushOperator,
+            PushOperand,
+            EvaluateSubexpression,
+            PopOperator,
+            PushOperand,
+            PopRemaining,
+        ]);
+
+        // State after the `)`, and after the `-` has folded the `*`.
+        assert_eq!(steps[6], EvalStep {
+            token: ")".to_string(),
+            operator_stack: vec!["*".to_string()],
+            output_queue: vec![2, 7],
+            action: EvaluateSubexpression,
+        });
+        assert_eq!(steps[7].operator_stack, vec!["-".to_string()]);
+        assert_eq!(steps[7].output_queue, vec![14]);
+
+        let (_, steps) = sy_evaluate_traced("-max(1, 2)").unwrap();
+        assert_eq!(steps[0].action, PushOperator);
+        assert_eq!(steps[3].operator_stack, vec!["~".to_string(), "max".to_string(), "(".to_string()]);
+        assert_eq!(steps[4].action, EvaluateSubexpression);
+        assert_eq!(steps[6].operator_stack, vec!["~".to_string()]);
+        assert_eq!(steps[6].output_queue, vec![2]);
+        assert_eq!(steps[7].action, PopRemaining);
+    }
+
+    #[test]
+    fn test_sy_evaluate_traced_matches_result() {
+        for input in ["1 + 2 * 3", "2 ^ 3 ^ 2", "-(4 - 6) * max(1, 3)", "1 << 2 | 1", "7", "(((1)))"] {
+            let (result, steps) = sy_evaluate_traced(input).unwrap();
+            assert_eq!(result, sy_evaulate(input.to_string()).unwrap(), "{}", input);
+            let last = steps.last().unwrap();
+            assert_eq!(last.output_queue, vec![result], "{}", input);
+            assert!(last.operator_stack.is_empty(), "{}", input);
+        }
+
+        let (_, steps) = sy_evaluate_traced("1 + 2 * 3").unwrap();
+        let pops: Vec<&str> = steps.iter()
+            .filter(|step| step.action == StepAction::PopRemaining)
+            .map(|step| step.token.as_str())
+            .collect();
+        assert_eq!(pops, vec!["*", "+"]);
+        assert_eq!(steps[5].output_queue, vec![1, 6]);
+
+        assert_eq!(steps[3].to_string(), "*        push operator            + *              1 2");
+        assert_eq!(steps[6].to_string(), "+        pop remaining                             7");
+    }
+
+    #[test]
+    fn test_custom_operator() {
+        let evaluator = Evaluator::default()
+            .with_operator('@', 9, Associativity::Left, |a, b| Ok((a + b) / 2.0))
+            .unwrap();
+        assert_eq!(evaluator.evaluate("2 @ 4").unwrap(), 3.0);
+        assert_eq!(evaluator.evaluate("2 @ 4 * 2 + 1").unwrap(), 7.0);
+        assert_eq!(evaluator.infix_to_rpn("1 + 2 @ 4").unwrap(), "1 2 4 @ +");
+        assert_eq!(evaluator.evaluate_rpn("1 2 4 @ +").unwrap(), 4.0);
+
+        // Unknown to the default table.
+        assert_eq!(
+            sy_evaluate_f64("2 @ 4".to_string()),
+            Err(ExpressionError::Parse(ParseError { kind: ParseErrorKind::UnexpectedCharacter('@'), position: 2 })),
+        );
+        assert_eq!(
+            evaluator.evaluate("2 @@ 4").unwrap_err(),
+            ExpressionError::Parse(ParseError { kind: consecutive_operators((2, 3), (3, 4)), position: 3 }),
+        );
+    }
+
+    #[test]
+    fn test_operator_symbols() {
+        let mean = |a: f64, b: f64| Ok((a + b) / 2.0);
+        for symbol in ['@', '#', '$', '?', ';', '¦', '→', '+', '^', '≤'] {
+            assert!(Evaluator::default().with_operator(symbol, 9, Associativity::Left, mean).is_ok(), "{}", symbol);
+        }
+        let evaluator = Evaluator::default().with_operator('→', 9, Associativity::Left, mean).unwrap();
+        assert_eq!(evaluator.evaluate("1 → 3"), Ok(2.0));
+
+        for symbol in ['a', 'Z', '1', '_', '(', ')', ' ', '\t', '.', ',', '~', '!', '×', '÷', '−', 'é', '１'] {
+            assert_eq!(
+                Evaluator::default().with_operator(symbol, 9, Associativity::Left, mean).err(),
+                Some(ExpressionError::InvalidOperator(symbol)),
+            );
+        }
+        assert_eq!(ExpressionError::InvalidOperator('(').to_string(), "( can't be an operator");
+    }
+
+    #[test]
+    fn test_override_operator() {
+        // `+` binding tighter than `*`.
+        let evaluator = Evaluator::default()
+            .with_operator('+', 10, Associativity::Left, |a, b| Ok(a + b))
+            .unwrap();
+        assert_eq!(evaluator.evaluate("1 + 2 * 3").unwrap(), 9.0);
+        assert_eq!(evaluator.infix_to_rpn("1 + 2 * 3").unwrap(), "1 2 + 3 *");
+        assert_eq!(sy_evaluate_f64("1 + 2 * 3".to_string()).unwrap(), 7.0);
+
+        let evaluator = Evaluator::default()
+            .with_operator('/', 9, Associativity::Left, |a, b| {
+                if b == 0.0 {
+                    return Err(ExpressionError::DivisionByZero);
+                }
+                return Ok((a / b).floor());
+            })
+            .unwrap();
+        assert_eq!(evaluator.evaluate("7 / 2").unwrap(), 3.0);
+        assert_eq!(evaluator.evaluate("7 / 0"), Err(ExpressionError::DivisionByZero));
+    }
+
+    #[test]
+    fn test_token_display() {
+        assert_eq!(Token::Number(42).to_string(), "42");
+        assert_eq!(Token::Float(2.0).to_string(), "2.0");
+        assert_eq!(Token::Float(0.25).to_string(), "0.25");
+        assert_eq!(Token::Function("sqrt".to_string()).to_string(), "sqrt");
+        assert_eq!(Token::UnaryMinus.to_string(), "-");
+        assert_eq!(Token::Percent.to_string(), "%");
+
+        let tokens = Tokenizer::tokenize("max( 1,-2 )*3").unwrap();
+        assert_eq!(tokens_to_infix(&tokens), "max(1, -2) * 3");
+    }
+
+    #[test]
+    fn test_tokens_round_trip() {
+        for input in ["1+2*3", "-(x - -2.0)^2", "log(2,8)%3", "  sqrt (16)/ 4.5", "2*-3"] {
+            let tokens = Tokenizer::tokenize(input).unwrap();
+            let infix = tokens_to_infix(&tokens);
+            assert_eq!(Tokenizer::tokenize(&infix).unwrap(), tokens, "{} -> {}", input, infix);
+        }
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_token_paths_agree() {
+        let expressions = [
+            "12 + 345",
+            "100 / 7 * 7",
+            "2 ^ 3 ^ 2",
+            "-(10 - 25) % 4",
+            "(1 + 2) * (3 + 4) - 5",
+            "max(10, 2 * 7) - abs(-3)",
+            "2 * -3 + 40",
+            "1 - 2 - 3",
+        ];
+        for input in expressions {
+            let tokens = Tokenizer::tokenize(input).unwrap();
+            let rpn_tokens = infix_to_rpn_tokens(&tokens).unwrap();
+            let expected = sy_evaulate(input.to_string()).unwrap();
+
+            assert_eq!(sy_evaluate_tokens(&tokens).unwrap(), expected, "{}", input);
+            assert_eq!(evaluate_rpn_tokens(&rpn_tokens).unwrap(), expected, "{}", input);
+            assert_eq!(evaluate_rpn(infix_to_rpn(input.to_string()).unwrap()).unwrap(), expected, "{}", input);
+        }
+
+        // Malformed input is rejected by all of them, with the same kind of
+        // error. Positions and spans differ, as tokens have indices, not
+        // offsets.
+        let kind = |result: Result<i64, ExpressionError>| match result {
+            Err(ExpressionError::Parse(err)) => Some(core::mem::discriminant(&err.kind)),
+            _ => None,
+        };
+        for input in ["1 2", "1 +", "2(3 + 4)", "* 2", "1 + * 2", "()"] {
+            let tokens = Tokenizer::tokenize(input).unwrap();
+            let expected = kind(sy_evaulate(input.to_string()));
+            assert!(expected.is_some(), "{}", input);
+            assert_eq!(kind(sy_evaluate_tokens(&tokens)), expected, "{}", input);
+        }
+        let missing_operator = ParseError { kind: missing_operator((0, 1), (1, 2)), position: 1 };
+        assert_eq!(sy_evaluate_tokens(&[Token::Number(1), Token::Number(2)]), Err(ExpressionError::Parse(missing_operator)));
+        assert_eq!(sy_evaluate_tokens(&[]), Err(ExpressionError::EmptyInput));
+    }
+
+    #[test]
+    fn test_infix_to_rpn_tokens() {
+        let tokens = Tokenizer::tokenize("-sqrt(16) + 2.5").unwrap();
+        assert_eq!(infix_to_rpn_tokens(&tokens).unwrap(), vec![
+            Token::Number(16),
+            Token::Function("sqrt".to_string()),
+            Token::UnaryMinus,
+            Token::Float(2.5),
+            Token::Plus,
+        ]);
+        let tokens = Tokenizer::tokenize("(1 + 2").unwrap();
+        let unbalanced = ParseError { kind: ParseErrorKind::UnbalancedParenthesis, position: 0 };
+        assert_eq!(infix_to_rpn_tokens(&tokens), Err(ExpressionError::Parse(unbalanced)));
+
+        // The same input `infix_to_rpn` rejects.
+        let missing_operator = ParseError { kind: missing_operator((0, 1), (1, 2)), position: 1 };
+        assert_eq!(infix_to_rpn_tokens(&[Token::Number(1), Token::Number(2)]), Err(ExpressionError::Parse(missing_operator)));
+        let trailing_span = Span { start: 1, end: 2 };
+        let trailing = ParseError { kind: ParseErrorKind::TrailingOperator { span: trailing_span }, position: 1 };
+        assert_eq!(infix_to_rpn_tokens(&[Token::Number(1), Token::Minus]), Err(ExpressionError::Parse(trailing)));
+        assert!(infix_to_rpn("1 2".to_string()).is_err() && infix_to_rpn("1 -".to_string()).is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        let tokens = Tokenizer::tokenize("max(1, -2.5) * x").unwrap();
+        let json = serde_json::to_string(&tokens).unwrap();
+        assert!(json.starts_with(r#"[{"Function":"max"},"ParenLeft",{"Number":1},"Comma""#));
+        assert_eq!(serde_json::from_str::<Vec<Token>>(&json).unwrap(), tokens);
+
+        assert_eq!(serde_json::to_string(&Token::Number(42)).unwrap(), r#"{"Number":42}"#);
+
+        let expr = parse("-sqrt(x) + 2 ^ 3").unwrap();
+        let json = serde_json::to_string(&expr).unwrap();
+        assert_eq!(serde_json::from_str::<Expr>(&json).unwrap(), expr);
+
+        for input in ["1 + + 2", "1 2"] {
+            let err = sy_evaulate(input.to_string()).unwrap_err();
+            let json = serde_json::to_string(&err).unwrap();
+            assert_eq!(serde_json::from_str::<ExpressionError>(&json).unwrap(), err);
+        }
+    }
+
+    #[test]
+    fn test_operator_table_register() {
+        let mut operators = OperatorTable::default();
+        assert_eq!(operators.get_precedence('*'), Ok(9));
+        assert_eq!(operators.get_associativity('^'), Ok(Associativity::Right));
+
+        // Subtraction grouping to the right and binding tighter than `*`.
+        operators.register('-', 10, Associativity::Right);
+        assert_eq!(infix_to_rpn_with_ops("10 - 4 - 3".to_string(), &operators).unwrap(), "10 4 3 - -");
+        assert_eq!(sy_evaluate_with_ops("10 - 4 - 3".to_string(), &operators).unwrap(), 9);
+        assert_eq!(sy_evaluate_with_ops("2 * 5 - 3".to_string(), &operators).unwrap(), 4);
+        assert_eq!(evaluate_rpn_with_ops("10 4 3 - -".to_string(), &operators).unwrap(), 9);
+        assert_eq!(sy_evaulate("10 - 4 - 3".to_string()).unwrap(), 3);
+
+        // A registered symbol without an implementation converts, but
+        // can't be evaluated.
+        operators.register('#', 1, Associativity::Left);
+        assert_eq!(infix_to_rpn_with_ops("1 + 2 # 3".to_string(), &operators).unwrap(), "1 2 + 3 #");
+        assert_eq!(evaluate_rpn_with_ops("1 2 #".to_string(), &operators), Err(ExpressionError::UnexpectedToken('#')));
+        assert_eq!(
+            infix_to_rpn("1 # 2".to_string()),
+            Err(ExpressionError::Parse(ParseError { kind: ParseErrorKind::UnexpectedCharacter('#'), position: 2 })),
+        );
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_mixed_precedence_chains() {
+        let cases = [
+            ("2 ^ 3 * 4 + 1", "2 3 ^ 4 * 1 +", 33),
+            ("2 + 10 % 4 * 3", "2 10 4 % 3 * +", 8),
+            ("1 + 2 * 3 ^ 2 - 4", "1 2 3 2 ^ * + 4 -", 15),
+            ("8 / 2 ^ 2 * 3 - 1 + 5", "8 2 2 ^ / 3 * 1 - 5 +", 10),
+            ("1 - 2 * 3 * 4 + 5", "1 2 3 * 4 * - 5 +", -18),
+            ("(1 + 2 * 3 ^ 2) * 2 - 1", "1 2 3 2 ^ * + 2 * 1 -", 37),
+            ("-2 ^ 2 * 3 + 1", "2 2 ^ ~ 3 * 1 +", -11),
+        ];
+        for (infix, rpn, value) in cases {
+            assert_eq!(infix_to_rpn(infix.to_string()).unwrap(), rpn, "{}", infix);
+            assert_eq!(sy_evaulate(infix.to_string()).unwrap(), value, "{}", infix);
+            assert_eq!(evaluate_rpn(rpn.to_string()).unwrap(), value, "{}", infix);
+            assert_eq!(parse(infix).unwrap().eval().unwrap(), value as f64, "{}", infix);
+        }
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_comparison_operators() {
+        let cases = [
+            ("1 < 2", 1),
+            ("2 < 1", 0),
+            ("3 > 2", 1),
+            ("2 <= 2", 1),
+            ("3 >= 4", 0),
+            ("2 + 2 == 4", 1),
+            ("2 * 3 != 6", 0),
+            ("1 + 1 < 2 * 2", 1),
+            ("1 < 2 < 3", 1),
+            ("3 > 2 > 1", 1),
+            ("(3 > 2) > 1", 0),
+            ("(1 < 2) * 10", 10),
+            ("-1>=-1", 1),
+        ];
+        for (input, value) in cases {
+            assert_eq!(sy_evaulate(input.to_string()).unwrap(), value, "{}", input);
+            assert_eq!(evaluate_rpn(infix_to_rpn(input.to_string()).unwrap()).unwrap(), value, "{}", input);
+        }
+
+        assert_eq!(infix_to_rpn("(1 + 1 <= 2) == 1".to_string()).unwrap(), "1 1 + 2 <= 1 ==");
+        assert_eq!(infix_to_rpn("1 + 1 <= 2 == 2".to_string()).unwrap(), "1 1 + 2 2 [<= ==]");
+        assert_eq!(sy_evaluate_f64("0.1 + 0.2 > 0.3".to_string()).unwrap(), 1.0);
+        assert_eq!(Tokenizer::tokenize("a!=b").unwrap(), vec![
+            Token::Ident("a".to_string()),
+            Token::Ne,
+            Token::Ident("b".to_string()),
+        ]);
+        assert_eq!(
+            sy_evaulate("1 = 1".to_string()),
+            Err(ExpressionError::Parse(ParseError { kind: ParseErrorKind::UnexpectedCharacter('='), position: 2 })),
+        );
+        assert_eq!(
+            sy_evaulate("1 < < 2".to_string()),
+            Err(ExpressionError::Parse(ParseError { kind: consecutive_operators((2, 3), (4, 5)), position: 4 })),
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        for input in ["1 + 2 * 3", "-(x - 1) / 0", "max(1, sqrt(y)) >= 2", "2 ^ -3"] {
+            assert_eq!(validate(input), Ok(()), "{}", input);
+        }
+
+        let parse_error = |kind, position| Err(ExpressionError::Parse(ParseError { kind, position }));
+        assert_eq!(validate("(1 + 2"), parse_error(ParseErrorKind::UnbalancedParenthesis, 0));
+        assert_eq!(validate("1 + 2)"), parse_error(ParseErrorKind::UnbalancedParenthesis, 5));
+        assert_eq!(validate("1 * / 2"), parse_error(consecutive_operators((2, 3), (4, 5)), 4));
+        assert_eq!(validate("* 2"), parse_error(ParseErrorKind::LeadingOperator { span: Span { start: 0, end: 1 } }, 0));
+        assert_eq!(validate("2 -"), parse_error(ParseErrorKind::TrailingOperator { span: Span { start: 2, end: 3 } }, 2));
+        assert_eq!(validate("2 $ 3"), parse_error(ParseErrorKind::UnexpectedCharacter('$'), 2));
+        assert_eq!(validate(""), Err(ExpressionError::EmptyInput));
+        assert_eq!(validate("1 + nope(2)"), Err(ExpressionError::UnknownFunction("nope".to_string())));
+
+        let arity = |name: &str, expected, got| {
+            return Err(ExpressionError::WrongArity { name: name.to_string(), expected, got });
+        };
+        assert_eq!(validate("sqrt(1, 2)"), arity("sqrt", 1, 2));
+        assert_eq!(validate("1 + max(1)"), arity("max", 2, 1));
+        assert_eq!(validate("min(sqrt(), 2)"), arity("sqrt", 1, 0));
+        // The same error as evaluating it gives.
+        assert_eq!(sy_evaluate_f64("sqrt(1, 2)".to_string()).err(), validate("sqrt(1, 2)").err());
+    }
+
+    #[test]
+    fn test_feed_continuation() {
+        let mut evaluator = Evaluator::default();
+        assert_eq!(evaluator.feed("(1 + 2 *"), Ok(None));
+        assert!(evaluator.is_pending());
+        assert_eq!(evaluator.feed("3"), Ok(None));
+        assert_eq!(evaluator.feed(")"), Ok(Some(7.0)));
+        assert!(!evaluator.is_pending());
+
+        assert_eq!(evaluator.feed("max(1,"), Ok(None));
+        assert_eq!(evaluator.feed("  4) -"), Ok(None));
+        assert_eq!(evaluator.feed(""), Ok(None));
+        assert_eq!(evaluator.feed("1"), Ok(Some(3.0)));
+
+        assert_eq!(evaluator.feed("   "), Ok(None));
+        assert!(!evaluator.is_pending());
+
+        assert_eq!(evaluator.feed("2 *"), Ok(None));
+        evaluator.reset();
+        assert_eq!(evaluator.feed("5"), Ok(Some(5.0)));
+    }
+
+    #[test]
+    fn test_feed_errors() {
+        let mut evaluator = Evaluator::default();
+        assert_eq!(evaluator.feed("1 +"), Ok(None));
+        assert_eq!(
+            evaluator.feed("* 2"),
+            Err(ExpressionError::Parse(ParseError { kind: consecutive_operators((2, 3), (4, 5)), position: 4 })),
+        );
+        assert!(!evaluator.is_pending());
+        assert_eq!(
+            evaluator.feed("1)"),
+            Err(ExpressionError::Parse(ParseError { kind: ParseErrorKind::UnbalancedParenthesis, position: 1 })),
+        );
+        assert_eq!(evaluator.feed("(4 / 0"), Ok(None));
+        assert_eq!(evaluator.feed(")"), Err(ExpressionError::DivisionByZero));
+        assert_eq!(evaluator.feed("4 / 2"), Ok(Some(2.0)));
+    }
+
+    #[test]
+    fn test_feed_answer() {
+        let mut evaluator = Evaluator::default();
+        assert_eq!(evaluator.answer(), None);
+        assert_eq!(evaluator.feed("ans"), Err(ExpressionError::UndefinedVariable("ans".to_string())));
+
+        assert_eq!(evaluator.feed("2 ^ 3"), Ok(Some(8.0)));
+        assert_eq!(evaluator.feed("_ + 5"), Ok(Some(13.0)));
+        assert_eq!(evaluator.feed("ans * 2"), Ok(Some(26.0)));
+        assert_eq!(evaluator.feed("ans / 0"), Err(ExpressionError::DivisionByZero));
+        assert_eq!(evaluator.answer(), Some(26.0));
+        assert_eq!(evaluator.feed("sqrt(ans -"), Ok(None));
+        assert_eq!(evaluator.feed("1)"), Ok(Some(5.0)));
+    }
+
+    #[test]
+    fn test_sy_evaluator_multi_digit() {
+        assert_eq!(sy_evaulate("10 + 20 * 3".to_string()).unwrap(), 70);
+        assert_eq!(sy_evaulate("12 + 3".to_string()).unwrap(), 15);
+        assert_eq!(sy_evaulate("(100 - 1) / 11".to_string()).unwrap(), 9);
+    }
+
+    #[test]
+    fn test_token_stream() {
+        for input in ["1 + 2 * 3", "-(x - -2.5) ^ 2", "max(1, 2) >= 2", "  sqrt (16)/ 4"] {
+            let streamed: Result<Vec<Token>, ExpressionError> = TokenStream::new(input).collect();
+            assert_eq!(streamed.unwrap(), Tokenizer::tokenize(input).unwrap(), "{}", input);
+        }
+
+        let mut stream = TokenStream::new("1 $ 2");
+        assert_eq!(stream.next(), Some(Ok(Token::Number(1))));
+        assert_eq!(stream.next_spanned(), Some(Err(TokenizeError {
+            error: ExpressionError::UnknownCharacter('$'),
+            span: Span { start: 2, end: 3 },
+        })));
+        assert_eq!(stream.next(), None);
+    }
+
+    #[test]
+    fn test_sy_evaluate_stream() {
+        let input = (1..=1000).map(|n| n.to_string()).collect::<Vec<String>>().join(" + ");
+        assert_eq!(sy_evaluate_stream(TokenStream::new(&input)).unwrap(), 500500);
+        assert_eq!(sy_evaluate_stream(TokenStream::new("10 + 20 * 3")).unwrap(), 70);
+        assert_eq!(sy_evaluate_stream(TokenStream::new("(1 + 2")), Err(ExpressionError::MismatchedParentheses));
+        assert_eq!(sy_evaluate_stream(TokenStream::new("1 + #")), Err(ExpressionError::UnknownCharacter('#')));
+
+        // Input `sy_evaulate` rejects is rejected here too, if not as a
+        // `ParseError`.
+        for input in ["2(3+4)", "1 2", "(1)(2)", "1 + 2 3", "max(1, 2) 3"] {
+            assert!(sy_evaulate(input.to_string()).is_err(), "{}", input);
+            assert_eq!(sy_evaluate_stream(TokenStream::new(input)), Err(ExpressionError::TooManyOperands), "{}", input);
+        }
+        assert_eq!(sy_evaluate_stream(TokenStream::new("")), Err(ExpressionError::EmptyInput));
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_bitwise_operators() {
+        let cases = [
+            ("1 << 4 | 3", "1 4 << 3 |", 19),
+            ("0xFF & 0x0F", "255 15 &", 15),
+            ("6 & 3 + 1", "6 3 1 + &", 4),
+            ("1 + 1 << 2", "1 1 + 2 <<", 8),
+            ("1 | 2 xor 3 & 1", "1 2 3 1 & xor |", 3),
+            ("5 & 4 == 4", "5 4 4 == &", 1),
+            ("1 << 2 < 5", "1 2 << 5 <", 1),
+            ("-16 >> 2", "16 ~ 2 >>", -4),
+            ("0x10 xor -1", "16 1 ~ xor", -17),
+            ("2 ^ 3 xor 1", "2 3 ^ 1 xor", 9),
+        ];
+        for (infix, rpn, value) in cases {
+            assert_eq!(infix_to_rpn(infix.to_string()).unwrap(), rpn, "{}", infix);
+            assert_eq!(sy_evaulate(infix.to_string()).unwrap(), value, "{}", infix);
+            assert_eq!(evaluate_rpn(rpn.to_string()).unwrap(), value, "{}", infix);
+        }
+
+        assert_eq!(sy_evaulate("1 << 64".to_string()), Err(ExpressionError::InvalidShift(64)));
+        assert_eq!(sy_evaulate("1 >> -1".to_string()), Err(ExpressionError::InvalidShift(-1)));
+        assert_eq!(sy_evaulate("1 << 63".to_string()).unwrap(), i64::MIN);
+        assert_eq!(sy_evaluate_f64("1 & 2".to_string()), Err(ExpressionError::IntegerOnly("&".to_string())));
+        assert_eq!(
+            evaluate_rpn_f64("1 2 xor".to_string()).unwrap_err().to_string(),
+            "xor only works on integers",
+        );
+        assert_eq!(sy_evaulate("0x + 1".to_string()), Err(ExpressionError::InvalidLiteral("0x".to_string())));
+        assert_eq!(sy_evaulate("0xFFFFFFFFFFFFFFFFF".to_string()), Err(ExpressionError::Overflow));
+        assert_eq!(tokens_to_infix(&Tokenizer::tokenize("1<<2>>3 xor 4").unwrap()), "1 << 2 >> 3 xor 4");
+    }
+
+    #[test]
+    fn test_builtin_constants() {
+        use core::f64::consts::{E, PI, TAU};
+
+        let none = HashMap::new();
+        assert_eq!(sy_evaluate_f64("pi".to_string()).unwrap(), PI);
+        assert_eq!(sy_evaluate_f64("2 * PI".to_string()).unwrap(), TAU);
+        assert_eq!(sy_evaluate_with_vars("e ^ 1".to_string(), &none).unwrap(), E);
+        assert_eq!(evaluate_rpn_with_vars("tau 2 /".to_string(), &none).unwrap(), PI);
+        assert_eq!(sy_evaluate_f64("phi".to_string()).unwrap(), (1.0 + 5.0_f64.sqrt()) / 2.0);
+        assert_eq!(parse("sin(pi / 2)").unwrap().eval().unwrap(), 1.0);
+
+        // Constants shadow variables, which are still looked up otherwise.
+        let variables = HashMap::from([("pi".to_string(), 3.0), ("x".to_string(), 2.0)]);
+        assert_eq!(sy_evaluate_with_vars("pi * x".to_string(), &variables).unwrap(), 2.0 * PI);
+        assert_eq!(
+            sy_evaluate_with_vars("Pi".to_string(), &variables),
+            Err(ExpressionError::UndefinedVariable("Pi".to_string())),
+        );
+        assert!(BUILTIN_CONSTANTS.iter().any(|(name, _)| *name == "tau"));
+    }
+
+    #[test]
+    fn test_evaluate_values() {
+        assert_eq!(evaluate("3 + 4 > 6").unwrap(), Value::Bool(true));
+        assert_eq!(evaluate("7 / 2").unwrap(), Value::Int(3));
+        assert_eq!(evaluate("7 / 2.0").unwrap(), Value::Float(3.5));
+        assert_eq!(evaluate("1 + 2 == 3 && 2 * 2 != 5").unwrap(), Value::Bool(true));
+        assert_eq!(evaluate("false || !(1 >= 2)").unwrap(), Value::Bool(true));
+        assert_eq!(evaluate("true && false || true").unwrap(), Value::Bool(true));
+        assert_eq!(evaluate("!true == false").unwrap(), Value::Bool(true));
+        assert_eq!(evaluate("(1 < 2) == (3 < 4)").unwrap(), Value::Bool(true));
+        assert_eq!(evaluate("sqrt(16) > 3").unwrap(), Value::Bool(true));
+
+        let x = HashMap::from([("x".to_string(), 5.0)]);
+        assert_eq!(evaluate_value_with_env("(x > 2) && (x < 10)", &x).unwrap(), Value::Bool(true));
+        assert_eq!(evaluate_value_with_env("x > 2 && x < 5", &x).unwrap(), Value::Bool(false));
+        assert_eq!(evaluate_value_with_env("x * 2", &x).unwrap(), Value::Float(10.0));
+
+        let mismatch = |op: &str, operands: &[&str]| ExpressionError::TypeMismatch {
+            op: op.to_string(),
+            operands: operands.iter().map(|t| t.to_string()).collect(),
+        };
+        assert_eq!(evaluate("true + 3"), Err(mismatch("+", &["bool", "int"])));
+        assert_eq!(evaluate("1 && 2"), Err(mismatch("&&", &["int", "int"])));
+        assert_eq!(evaluate("!1.5"), Err(mismatch("!", &["float"])));
+        assert_eq!(evaluate("-true"), Err(mismatch("-", &["bool"])));
+        assert_eq!(evaluate("abs(false)"), Err(mismatch("abs", &["bool"])));
+        // Comparing a comparison, rather than chaining them.
+        assert_eq!(evaluate("(1 < 2) < 3"), Err(mismatch("<", &["bool", "int"])));
+        assert_eq!(evaluate("true < false").unwrap_err().to_string(), "cannot apply < to bool and bool");
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_logical_operators_as_numbers() {
+        let cases = [
+            ("1 && 2", "1 2 &&", 1),
+            ("0 || 0", "0 0 ||", 0),
+            ("!0 + 1", "0 ! 1 +", 2),
+            ("!(2 > 1)", "2 1 > !", 0),
+            ("true + 3", "true 3 +", 4),
+            ("1 | 2 && 0", "1 2 | 0 &&", 0),
+            ("1 || 0 && 0", "1 0 0 && ||", 1),
+            ("!-1", "1 ~ !", 0),
+        ];
+        for (infix, rpn, value) in cases {
+            assert_eq!(infix_to_rpn(infix.to_string()).unwrap(), rpn, "{}", infix);
+            assert_eq!(sy_evaulate(infix.to_string()).unwrap(), value, "{}", infix);
+            assert_eq!(evaluate_rpn(rpn.to_string()).unwrap(), value, "{}", infix);
+        }
+        assert_eq!(sy_evaluate_f64("2.5 && !false".to_string()).unwrap(), 1.0);
+
+        assert_eq!(
+            Tokenizer::tokenize("!a&&b||c!=d&e").unwrap(),
+            vec![
+                Token::Not,
+                Token::Ident("a".to_string()),
+                Token::And,
+                Token::Ident("b".to_string()),
+                Token::Or,
+                Token::Ident("c".to_string()),
+                Token::Ne,
+                Token::Ident("d".to_string()),
+                Token::BitAnd,
+                Token::Ident("e".to_string()),
+            ],
+        );
+        assert_eq!(tokens_to_infix(&Tokenizer::tokenize("!true||false").unwrap()), "!true || false");
+        assert_eq!(
+            sy_evaulate("1 !2".to_string()),
+            Err(ExpressionError::Parse(ParseError { kind: missing_operator((0, 1), (2, 3)), position: 2 })),
+        );
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_tokens_to_rpn_string() {
+        for infix in ["1 + 2 * 3 - 4", "-(2 + 3) ^ 2", "max(1, -2) * 3", "1 << 2 || !false", "2.5 / 0.5"] {
+            let rpn = infix_to_rpn_tokens(&Tokenizer::tokenize(infix).unwrap()).unwrap();
+            assert_eq!(tokens_to_rpn_string(&rpn), infix_to_rpn_f64(infix.to_string()).unwrap(), "{}", infix);
+            assert_eq!(evaluate_rpn_f64(tokens_to_rpn_string(&rpn)), sy_evaluate_f64(infix.to_string()), "{}", infix);
+        }
+        assert_eq!(tokens_to_rpn_string(&[]), "");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip_evaluates_the_same() {
+        let variables = HashMap::from([("x".to_string(), 0.1), ("y".to_string(), 3.0)]);
+        for input in ["0.1 + 0.2 * x", "sqrt(2) / 3 - y ^ 0.5", "-(x - 1.7976931348623157) % 0.3", "x < y && y != 3"] {
+            let expr = parse(input).unwrap();
+            let json = serde_json::to_string(&expr).unwrap();
+            let restored: Expr = serde_json::from_str(&json).unwrap();
+            let value = expr.eval_with_vars(&variables).unwrap();
+            assert_eq!(restored.eval_with_vars(&variables).unwrap().to_bits(), value.to_bits(), "{}", input);
+        }
+
+        let spanned: Vec<SpannedToken> = Tokenizer::new("!a || 2.5".to_string()).unwrap().collect();
+        let json = serde_json::to_string(&spanned).unwrap();
+        assert_eq!(serde_json::from_str::<Vec<SpannedToken>>(&json).unwrap(), spanned);
+
+        for value in [Value::Int(-7), Value::Float(0.30000000000000004), Value::Bool(true)] {
+            let json = serde_json::to_string(&value).unwrap();
+            assert_eq!(serde_json::from_str::<Value>(&json).unwrap(), value);
+        }
+        assert_eq!(serde_json::to_string(&evaluate("3 > 2").unwrap()).unwrap(), r#"{"Bool":true}"#);
+    }
+
+    #[test]
+    fn test_value_arithmetic() {
+        assert_eq!(Value::Int(7) / Value::Int(2), Ok(Value::Int(3)));
+        assert_eq!(Value::Float(7.0) / Value::Int(2), Ok(Value::Float(3.5)));
+        assert_eq!(Value::Int(2) + Value::Float(0.5), Ok(Value::Float(2.5)));
+        assert_eq!(Value::Int(7) % Value::Int(-3), Ok(Value::Int(1)));
+        assert_eq!((Value::Int(3) * Value::Int(4)).and_then(|v| v - Value::Int(5)), Ok(Value::Int(7)));
+        assert_eq!(Value::Int(1) / Value::Int(0), Err(ExpressionError::DivisionByZero));
+        assert_eq!(Value::Int(i64::MAX) + Value::Int(1), Err(ExpressionError::Overflow));
+        assert!(matches!(Value::Bool(true) + Value::Int(1), Err(ExpressionError::TypeMismatch { .. })));
+
+        assert_eq!(Value::Int(3).as_f64(), 3.0);
+        assert_eq!(Value::Bool(true).as_f64(), 1.0);
+        assert_eq!(Value::Int(3).as_i64(), Some(3));
+        assert_eq!(Value::Float(3.0).as_i64(), None);
+
+        assert_eq!(Value::Int(3).to_string(), "3");
+        assert_eq!(Value::Float(3.0).to_string(), "3.0");
+        assert_eq!(Value::Float(3.5).to_string(), "3.5");
+        assert_eq!(evaluate("7.0 / 2").unwrap().to_string(), "3.5");
+
+        assert_eq!(evaluate_rpn_value("7 2 /").unwrap(), Value::Int(3));
+        assert_eq!(evaluate_rpn_value("7 2.0 / 3 >").unwrap(), Value::Bool(true));
+        assert_eq!(evaluate_rpn_value("1 2 &&"), evaluate("1 && 2"));
+    }
+
+    #[test]
+    fn test_validate_all() {
+        let diagnostic = |severity, start, end, message: &str| Diagnostic {
+            severity,
+            span: Span { start, end },
+            message: message.to_string(),
+        };
+        let error = |start, end, message| diagnostic(Severity::Error, start, end, message);
+
+        assert_eq!(validate_all("max(1, -2) * (3 + x_)"), vec![error(0, 21, "undefined variable: x_")]);
+        assert_eq!(validate_all("1 + 2"), vec![]);
+        assert_eq!(validate_all("(1 + * 2"), vec![error(0, 1, "unbalanced parenthesis"), error(5, 6, "consecutive operators")]);
+        assert_eq!(
+            validate_all("1 $ 2 ) #"),
+            vec![
+                error(2, 3, "unexpected character: $"),
+                error(4, 5, "missing operator"),
+                error(6, 7, "unbalanced parenthesis"),
+                error(8, 9, "unexpected character: #"),
+            ],
+        );
+        assert_eq!(validate_all("2 * () -"), vec![error(5, 6, "empty parentheses"), error(7, 8, "trailing operator")]);
+        assert_eq!(validate_all("1 +"), vec![error(2, 3, "trailing operator")]);
+        assert_eq!(validate_all("  "), vec![error(0, 2, "empty input")]);
+        assert_eq!(validate_all("1 / (2 - 2)"), vec![error(0, 11, "division by zero")]);
+        assert_eq!(validate_all("nope(1)"), vec![error(0, 7, "unknown function: nope")]);
+
+        assert_eq!(validate_all("((3)) + 1"), vec![diagnostic(Severity::Warning, 1, 4, "redundant parentheses")]);
+        assert_eq!(validate_all("sqrt((4)) + ((1) + 2)"), vec![]);
+        assert_eq!(
+            validate_all("(((x)))"),
+            vec![
+                error(0, 7, "undefined variable: x"),
+                diagnostic(Severity::Warning, 1, 6, "redundant parentheses"),
+                diagnostic(Severity::Warning, 2, 5, "redundant parentheses"),
+            ],
+        );
+
+        // The syntax error the evaluators report is among them.
+        for input in ["(1 + * 2", "1 $ 2 ) #", "2 * () -", "* 2", "(1 + (2"] {
+            let Err(ExpressionError::Parse(err)) = validate(input) else { panic!("{}", input) };
+            assert!(validate_all(input).iter().any(|d| d.span.start == err.position), "{}", input);
+        }
+    }
+
+    /// Awkward inputs, then random strings of grammar characters and of
+    /// raw bytes, the same each run.
+    fn noise_corpus() -> Vec<String> {
+        // A small linear congruential generator, for reproducible noise.
+        let mut state: u64 = 0x2545F4914F6CDD1D;
+        let mut next = move || {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            return (state >> 33) as usize;
+        };
+
+        let alphabet: Vec<char> = "0123456789.+-*/%^()<>=!&|,~ xeπ_$sqrtmax".chars().collect();
+        let mut corpus: Vec<String> = [
+            "1 + 2 * 3", "-(2 + 3) ^ 2", "max(1, 2", "1 / 0", "((1))", "1 << 64", "0x", "1.2.3", "99999999999999999999",
+            "!!true && 1 < 2", "sqrt(-1)", "()", ")(", ",", "1,2", "max(,)", "x", "", "-", "~1", "1e-3", "1e", ".5",
+            "1e3e4", "1e400",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+        for _ in 0..500 {
+            let len = next() % 12;
+            corpus.push((0..len).map(|_| alphabet[next() % alphabet.len()]).collect());
+            let bytes: Vec<u8> = (0..len).map(|_| next() as u8).collect();
+            corpus.push(String::from_utf8_lossy(&bytes).into_owned());
+        }
+        return corpus;
+    }
+
+    #[test]
+    fn test_validate_all_never_panics_and_only_passes_what_evaluates() {
+        for input in &noise_corpus() {
+            let diagnostics = validate_all(input);
+            for d in &diagnostics {
+                assert!(d.span.start <= d.span.end && d.span.end <= input.len(), "{:?}: {:?}", input, d);
+            }
+            if diagnostics.iter().all(|d| d.severity != Severity::Error) {
+                assert!(sy_evaluate_f64(input.to_string()).is_ok(), "{:?}", input);
+            } else if validate(input).is_err() {
+                assert!(sy_evaluate_f64(input.to_string()).is_err(), "{:?}", input);
+            }
+        }
+    }
+
+    #[test]
+    fn test_implicit_multiplication() {
+        let evaluator = Evaluator::default().with_implicit_multiplication(true);
+        assert_eq!(evaluator.evaluate("2(3+4)").unwrap(), 14.0);
+        assert_eq!(evaluator.evaluate("(1+2)(3+4)").unwrap(), 21.0);
+        assert_eq!(evaluator.evaluate("1/2(3)").unwrap(), evaluator.evaluate("1/2*3").unwrap());
+        assert_eq!(evaluator.evaluate("2pi").unwrap(), 2.0 * core::f64::consts::PI);
+        assert_eq!(evaluator.evaluate("3 max(1, 2)").unwrap(), 6.0);
+        assert_eq!(evaluator.infix_to_rpn("2(x + 1)").unwrap(), "2 x 1 + *");
+        assert_eq!(evaluator.evaluate("2 3").unwrap(), 6.0);
+
+        // Off by default.
+        let cases = [("2(3+4)", (0, 1), (1, 6)), ("(1+2)(3+4)", (0, 5), (5, 10)), ("2pi", (0, 1), (1, 3))];
+        for (input, left, right) in cases {
+            assert_eq!(
+                Evaluator::default().evaluate(input),
+                Err(ExpressionError::Parse(ParseError { kind: missing_operator(left, right), position: right.0 }))
+            );
+        }
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_scientific_notation() {
+        for (input, value) in [("1e3", 1000.0), ("2.5e-4", 0.00025), ("1E+6", 1e6), ("1.e2", 100.0), (".5", 0.5)] {
+            assert_eq!(Tokenizer::tokenize(input).unwrap(), vec![Token::Float(value)], "{}", input);
+        }
+        assert_eq!(sy_evaluate_f64("1e3 * 2 - .5".to_string()).unwrap(), 1999.5);
+        assert_eq!(evaluate_rpn_f64("1e3 .5 +".to_string()).unwrap(), 1000.5);
+        assert_eq!(parse("2.5e-4 * x").unwrap().to_string(), "0.00025 * x");
+
+        // What follows the exponent is left for the next token.
+        assert_eq!(Tokenizer::tokenize("1e3(").unwrap(), vec![Token::Float(1000.0), Token::ParenLeft]);
+        assert_eq!(Tokenizer::tokenize("2e-1)").unwrap(), vec![Token::Float(0.2), Token::ParenRight]);
+        assert_eq!(Evaluator::default().with_implicit_multiplication(true).evaluate("1e3(2)").unwrap(), 2000.0);
+
+        for (input, span) in [("1e", Span { start: 1, end: 2 }), ("1e+", Span { start: 1, end: 2 }), ("2.5E-x", Span { start: 3, end: 4 })] {
+            let err = Tokenizer::new(input.to_string()).err().unwrap();
+            assert_eq!((err.error, err.span), (ExpressionError::MissingExponent, span), "{}", input);
+        }
+        assert_eq!(
+            sy_evaluate_f64("2 * 1e".to_string()),
+            Err(ExpressionError::Parse(ParseError { kind: ParseErrorKind::MissingExponent, position: 5 }))
+        );
+        assert_eq!(
+            rpn_to_infix("1e+ 2 +"),
+            Err(ParseError { kind: ParseErrorKind::MissingExponent, position: 1 })
+        );
+
+        let err = Tokenizer::new("1e3e4".to_string()).err().unwrap();
+        assert_eq!((err.error, err.span), (ExpressionError::UnexpectedToken('e'), Span { start: 3, end: 4 }));
+        let err = Tokenizer::new("1e3.5".to_string()).err().unwrap();
+        assert_eq!((err.error, err.span), (ExpressionError::UnexpectedToken('.'), Span { start: 3, end: 4 }));
+        let err = Tokenizer::new("1 + 1e400".to_string()).err().unwrap();
+        assert_eq!((err.error, err.span), (ExpressionError::Overflow, Span { start: 4, end: 9 }));
+
+        // A point is only a number with a digit after it.
+        assert_eq!(Tokenizer::new("1 + .".to_string()).err().unwrap().error, ExpressionError::UnknownCharacter('.'));
+    }
+
+    #[test]
+    fn test_operator_on_empty_stack() {
+        // A leading `+` has nothing to pop and no operand to its left.
+        let leading_span = Span { start: 0, end: 1 };
+        let leading = ParseError { kind: ParseErrorKind::LeadingOperator { span: leading_span }, position: 0 };
+        assert_eq!(sy_evaulate("+ 3 + 4".to_string()), Err(ExpressionError::Parse(leading)));
+        assert_eq!(infix_to_rpn("+ 3 + 4".to_string()), Err(ExpressionError::Parse(leading)));
+        assert_eq!(sy_evaluate_stream(TokenStream::new("+ 3 + 4")), Err(ExpressionError::EmptyStack));
+
+        assert_eq!(sy_evaulate("3 + 4".to_string()).unwrap(), 7);
+        assert_eq!(infix_to_rpn("3 + 4".to_string()).unwrap(), "3 4 +");
+        assert_eq!(sy_evaluate_stream(TokenStream::new("3 + 4")).unwrap(), 7);
+        assert_eq!(infix_to_rpn("(3 + 4)".to_string()).unwrap(), "3 4 +");
+    }
+
+    #[test]
+    fn test_evaluate_batch() {
+        let evaluator = Evaluator::default().with_operator('@', 9, Associativity::Left, |a, b| Ok((a + b) / 2.0)).unwrap();
+        let inputs = [
+            "1 + 2 * 3", "max(1, 2) @ 4", "", "1 / 0", "(1 + 2", "sqrt(16) - -2", "x", "2 ^ 0.5", "max(1)", "7 % 4",
+        ];
+        let batch = evaluator.evaluate_batch(inputs);
+        assert_eq!(batch.len(), inputs.len());
+        for (input, result) in inputs.iter().zip(&batch) {
+            assert_eq!(*result, evaluator.evaluate(input), "{}", input);
+        }
+        assert_eq!(batch[0], Ok(7.0));
+        assert_eq!(batch[3], Err(ExpressionError::DivisionByZero));
+        assert!(evaluator.evaluate_batch([]).is_empty());
+    }
+
+    #[test]
+    fn test_compiled_expr() {
+        let evaluator = Evaluator::default().with_operator('@', 9, Associativity::Left, |a, b| Ok((a + b) / 2.0)).unwrap();
+        for input in ["3 * x ^ 2 + max(x, 2) / (1 + x)", "-x @ y - sqrt(y * y)", "(x > y) + (x <= 1) * 10", "pi * x"] {
+            let compiled = evaluator.compile(input).unwrap();
+            for (x, y) in [(0.0, 1.0), (2.5, -3.0), (-4.0, 0.5), (10.0, 7.0)] {
+                let env = HashMap::from([("x".to_string(), x), ("y".to_string(), y)]);
+                assert_eq!(compiled.eval(&env), evaluator.evaluate_with_env(input, &env), "{} at {}, {}", input, x, y);
+            }
+        }
+
+        // A variable may share a function's name.
+        let env = HashMap::from([("max".to_string(), 2.0)]);
+        assert_eq!(evaluator.compile("max + max(max, 3)").unwrap().eval(&env), Ok(5.0));
+
+        let compiled = evaluator.compile("x / y").unwrap();
+        assert_eq!(compiled.eval(&NoVariables), Err(ExpressionError::UndefinedVariable("x".to_string())));
+        let env = HashMap::from([("x".to_string(), 1.0), ("y".to_string(), 0.0)]);
+        assert_eq!(compiled.eval(&env), Err(ExpressionError::DivisionByZero));
+
+        assert!(matches!(evaluator.compile("1 +"), Err(ExpressionError::Parse(_))));
+        assert_eq!(evaluator.compile("f(1)").err(), Some(ExpressionError::UnknownFunction("f".to_string())));
+        assert_eq!(
+            evaluator.compile("1 + max(1, min(2))").err(),
+            Some(ExpressionError::WrongArity { name: "min".to_string(), expected: 2, got: 1 })
+        );
+        assert_eq!(
+            evaluator.compile("max(1, 2, 3)").err(),
+            Some(ExpressionError::WrongArity { name: "max".to_string(), expected: 2, got: 3 })
+        );
+    }
+
+    #[cfg(feature = "bigint")]
+    #[test]
+    fn test_evaluate_big() {
+        let big = |n: &str| n.parse::<BigInt>().unwrap();
+
+        assert_eq!(evaluate_big("2 ^ 200").unwrap(), big("1606938044258990275541962092341162602522202993782792835301376"));
+        assert_eq!(evaluate_big("2 ^ 200").unwrap().to_string().len(), 61);
+        assert_eq!(evaluate_big("-(2 ^ 64) + 1").unwrap(), big("-18446744073709551615"));
+        assert_eq!(evaluate_big("9223372036854775807 * 2").unwrap(), big("18446744073709551614"));
+        assert_eq!(evaluate_rpn_big("2 100 ^ 3 *").unwrap(), big("3802951800684688204490109616128"));
+        assert_eq!(evaluate_rpn_big("10 30 ^ 7 /").unwrap(), big("142857142857142857142857142857"));
+
+        // Same answers as integer mode wherever that has one.
+        for input in ["7 / 2", "-7 / 2", "-7 % 3", "2 ^ 10 - 1", "1 << 40 | 5", "-9 >> 1", "3 > 2 && !0", "max(3, 8) - abs(-2)"] {
+            assert_eq!(evaluate_big(input).unwrap(), BigInt::from(sy_evaulate(input.to_string()).unwrap()), "{}", input);
+        }
+        assert_eq!(evaluate_big("1 << 100").unwrap(), big("1267650600228229401496703205376"));
+
+        assert_eq!(evaluate_big("2 ^ 200 / 0"), Err(ExpressionError::DivisionByZero));
+        assert_eq!(evaluate_big("5 % (3 - 3)"), Err(ExpressionError::DivisionByZero));
+        assert_eq!(evaluate_big("2 ^ -1"), Err(ExpressionError::NegativeExponent));
+        assert_eq!(evaluate_big("1 << -1"), Err(ExpressionError::InvalidShift(-1)));
+        assert_eq!(evaluate_big("10 ^ 1000000"), Err(ExpressionError::Overflow));
+        assert_eq!(evaluate_big("1 ^ 1000000000"), Ok(BigInt::from(1)));
+        assert_eq!(evaluate_big("sqrt(2)"), Err(ExpressionError::NonIntegerResult));
+        assert_eq!(evaluate_big("1.5 + 1"), Err(ExpressionError::FloatInIntegerMode(1.5)));
+    }
+
+    #[test]
+    fn test_tokenize_lenient() {
+        let (tokens, errors) = Tokenizer::tokenize_lenient("1 $ + 2 # * €3 @");
+        assert_eq!(tokens, vec![Token::Number(1), Token::Plus, Token::Number(2), Token::Asterisk, Token::Number(3)]);
+        assert_eq!(errors, vec![
+            (2, ExpressionError::UnknownCharacter('$')),
+            (8, ExpressionError::UnknownCharacter('#')),
+            (12, ExpressionError::UnknownCharacter('€')),
+            (17, ExpressionError::UnknownCharacter('@')),
+        ]);
+
+        // Malformed numbers are skipped whole.
+        let (tokens, errors) = Tokenizer::tokenize_lenient("1.2.3 + 1e + x");
+        assert_eq!(tokens, vec![Token::Plus, Token::Plus, Token::Ident("x".to_string())]);
+        assert_eq!(errors, vec![(3, ExpressionError::UnexpectedToken('.')), (9, ExpressionError::MissingExponent)]);
+        let (tokens, errors) = Tokenizer::tokenize_lenient("1.2.3 + 4");
+        assert_eq!(tokens, vec![Token::Plus, Token::Number(4)]);
+        assert_eq!(errors, vec![(3, ExpressionError::UnexpectedToken('.'))]);
+        let (tokens, _) = Tokenizer::tokenize_lenient("1e5.3x * 2");
+        assert_eq!(tokens, vec![Token::Asterisk, Token::Number(2)]);
+
+        let (tokens, errors) = Tokenizer::tokenize_lenient("max(1, 2)");
+        assert_eq!(tokens, Tokenizer::tokenize("max(1, 2)").unwrap());
+        assert!(errors.is_empty());
+        assert_eq!(Tokenizer::tokenize_lenient(""), (vec![], vec![]));
+
+        // `tokenize` stays strict.
+        assert_eq!(Tokenizer::tokenize("1 $ + 2 #"), Err(ExpressionError::UnknownCharacter('$')));
+    }
+
+    #[test]
+    fn test_evaluate_with_config() {
+        let config = EvaluatorConfig::default();
+        let limit = |which| Err(ExpressionError::LimitExceeded { which });
+
+        let nested = |depth| format!("{}1{}", "(".repeat(depth), ")".repeat(depth));
+        assert_eq!(evaluate_with_config(&nested(64), &config), Ok(Value::Int(1)));
+        assert_eq!(evaluate_with_config(&nested(65), &config), limit(Limit::Depth));
+        assert_eq!(evaluate_with_config(&nested(100_000), &config), limit(Limit::Length));
+        assert_eq!(evaluate_with_config("max(1, (((2))))", &EvaluatorConfig { max_depth: 3, ..config }), limit(Limit::Depth));
+        assert_eq!(evaluate_with_config(&"1 + ".repeat(2000), &config), limit(Limit::Length));
+
+        let degrees = EvaluatorConfig { angle: AngleUnit::Degrees, ..config };
+        assert_eq!(evaluate_with_config("sin(90)", &config), Ok(Value::Float(90f64.sin())));
+        assert_eq!(evaluate_with_config("sin(90)", &degrees), Ok(Value::Float(1.0)));
+        assert_eq!(evaluate_with_config("cos(180)", &degrees), Ok(Value::Float(-1.0)));
+
+        let truncate = EvaluatorConfig { division: Division::Truncate, ..config };
+        assert_eq!(evaluate_with_config("1/2", &config), Ok(Value::Float(0.5)));
+        assert_eq!(evaluate_with_config("1/2", &truncate), Ok(Value::Int(0)));
+        assert_eq!(evaluate_with_config("1/2.0", &truncate), Ok(Value::Float(0.5)));
+        assert_eq!(evaluate_with_config("1/0", &config), Err(ExpressionError::DivisionByZero));
+
+        assert_eq!(
+            evaluate_with_config("9 ^ 9 ^ 9 ^ 9", &config),
+            Err(ExpressionError::IntegerOverflow { op: "^".to_string(), left: 9, right: 387_420_489 })
+        );
+        let small = EvaluatorConfig { max_power: 1e6, ..config };
+        assert_eq!(evaluate_with_config("10 ^ 6", &small), Ok(Value::Int(1_000_000)));
+        assert_eq!(evaluate_with_config("(-10) ^ 7", &small), limit(Limit::Power));
+
+        let close = EvaluatorConfig { epsilon: 1e-9, ..config };
+        assert_eq!(evaluate_with_config("0.1 + 0.2 == 0.3", &config), Ok(Value::Bool(false)));
+        assert_eq!(evaluate_with_config("0.1 + 0.2 == 0.3", &close), Ok(Value::Bool(true)));
+        assert_eq!(evaluate_with_config("0.1 + 0.2 != 0.3", &close), Ok(Value::Bool(false)));
+        assert_eq!(evaluate_with_config("true == true", &close), Ok(Value::Bool(true)));
+
+        assert_eq!(
+            evaluate_with_config(&nested(65), &config).unwrap_err().to_string(),
+            "nesting depth limit exceeded"
+        );
+    }
+
+    #[test]
+    fn test_evaluate_exact() {
+        let exact = |input| evaluate_exact(input).map(|r| r.to_string());
+        assert_eq!(evaluate_exact("1/3 * 3"), Ok(Rational::from(1)));
+        assert_eq!(evaluate_exact("2/4"), Rational::new(1, 2));
+        assert_eq!(exact("2/4"), Ok("1/2".to_string()));
+        assert_eq!(exact("1/2 + 1/3"), Ok("5/6".to_string()));
+        assert_eq!(exact("1 / -3"), Ok("-1/3".to_string()));
+        assert_eq!(exact("(2/3) ^ -2"), Ok("9/4".to_string()));
+        assert_eq!(exact("(-2/3) ^ -3"), Ok("-27/8".to_string()));
+        assert_eq!(exact("7/2 % 1"), Ok("1/2".to_string()));
+        assert_eq!(exact("-7/2 % 1"), Ok("-1/2".to_string()));
+        assert_eq!(exact("1/3 < 1/2"), Ok("1".to_string()));
+        assert_eq!(exact("abs(-6/2)"), Ok("3".to_string()));
+        assert_eq!(exact("6 & 3"), Ok("2".to_string()));
+
+        assert_eq!(Rational::new(3, -6).unwrap(), Rational::new(-1, 2).unwrap());
+        assert_eq!(Rational::new(3, -6).unwrap().denominator(), 2);
+        assert_eq!(Rational::new(1, 0), Err(ExpressionError::DivisionByZero));
+        assert!(Rational::new(1, 3).unwrap() < Rational::new(1, 2).unwrap());
+        assert_eq!(Rational::new(-3, 4).unwrap().as_f64(), -0.75);
+
+        assert_eq!(evaluate_exact("1/0"), Err(ExpressionError::DivisionByZero));
+        assert_eq!(evaluate_exact("0 ^ -1"), Err(ExpressionError::DivisionByZero));
+        assert_eq!(evaluate_exact("4 ^ (1/2)"), Err(ExpressionError::NonIntegerResult));
+        assert_eq!(evaluate_exact("sqrt(2)"), Err(ExpressionError::NonIntegerResult));
+        assert_eq!(evaluate_exact("1/2 & 1"), Err(ExpressionError::IntegerOnly("&".to_string())));
+        assert_eq!(evaluate_exact("0.5"), Err(ExpressionError::FloatInIntegerMode(0.5)));
+
+        // The numerator overflows part way through, even though the result would fit.
+        assert_eq!(evaluate_exact("9223372036854775807 * 2 / 2"), Err(ExpressionError::Overflow));
+        assert_eq!(evaluate_exact("1/3 + 1/9223372036854775807"), Err(ExpressionError::Overflow));
+        assert_eq!(evaluate_exact("-(-9223372036854775807 - 1)"), Err(ExpressionError::Overflow));
+        assert_eq!(evaluate_exact("(1/2) ^ 64"), Err(ExpressionError::Overflow));
+    }
+
+    /// Every function that takes input, on `input`. They may fail, but only
+    /// with an error.
+    #[allow(deprecated)]
+    fn run_every_entry_point(input: &str) {
+        let owned = || input.to_string();
+        let _ = evaluate_rpn(owned());
+        let _ = evaluate_rpn_f64(owned());
+        let _ = evaluate_rpn_with_vars(owned(), &HashMap::from([("x".to_string(), 2.0)]));
+        let _ = evaluate_rpn_value(input);
+        let _ = infix_to_rpn(owned());
+        let _ = infix_to_rpn_f64(owned());
+        let _ = sy_evaulate(owned());
+        let _ = sy_evaluate_f64(owned());
+        let _ = sy_evaluate_traced(input);
+        let _ = sy_evaluate_stream(TokenStream::new(input));
+        let _ = evaluate(input);
+        let _ = evaluate_exact(input);
+        let _ = evaluate_with_config(input, &EvaluatorConfig::default());
+        #[cfg(feature = "bigint")]
+        let _ = evaluate_big(input);
+        let _ = validate(input);
+        let _ = validate_all(input);
+        let _ = simplify(input);
+        let _ = format_expression(input);
+        let _ = rpn_to_infix(input);
+        let _ = format_rpn_to_infix(input);
+        if let Ok(expr) = parse(input) {
+            let _ = expr.eval();
+            let _ = expr.fold_constants();
+            let _ = expr.diff("x");
+            let _ = PrettyPrinter::tree(&expr).to_string();
+        }
+
+        let (tokens, _) = Tokenizer::tokenize_lenient(input);
+        let _ = sy_evaluate_tokens(&tokens);
+        let _ = infix_to_rpn_tokens(&tokens);
+        let _ = evaluate_rpn_tokens(&tokens);
+        let _ = tokens_to_infix(&tokens);
+
+        let mut evaluator = Evaluator::default().with_implicit_multiplication(true);
+        let _ = evaluator.evaluate(input);
+        let _ = evaluator.evaluate_rpn(input);
+        let _ = evaluator.infix_to_rpn(input);
+        if let Ok(compiled) = evaluator.compile(input) {
+            let _ = compiled.eval(&NoVariables);
+        }
+        for line in input.lines() {
+            let _ = evaluator.feed(line);
+        }
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_no_panic_on_any_input() {
+        let mut corpus = noise_corpus();
+        corpus.extend(
+            [
+                "", " ", "\t\n", "+", "- - -", "* / %", "^^", ")", "(", "(()", "())", ")))(((", "1 +", "1 2 +", "+ 1 2",
+                "1 2 3", "é", "１ + ２", "1 € 2", "\u{0}", "\u{200b}1", "max(", "max(1,", "sqrt)", "1 ~", "~", ".", "e",
+                "0x", "0xZZ", "1 / 0", "-9223372036854775808", "9223372036854775807 + 1", "2 ^ 99 ^ 99",
+            ]
+            .iter()
+            .map(|s| s.to_string()),
+        );
+
+        // Deeply nested input, which a recursive parser or printer would
+        // overflow the stack on.
+        let deep = 5_000;
+        corpus.extend([
+            format!("{}1{}", "(".repeat(deep), ")".repeat(deep)),
+            format!("{}1", "-".repeat(deep)),
+            format!("{}1", "!".repeat(deep)),
+            format!("1{}", " ~".repeat(deep)),
+            format!("1{}", " 1 +".repeat(deep)),
+            format!("1{}", " + 1".repeat(deep)),
+            format!("2{}", " ^ 2".repeat(deep)),
+            format!("{}1{}", "abs(".repeat(deep), ")".repeat(deep)),
+        ]);
+
+        // Nesting a recursive parser or printer would run out of stack on.
+        let deep = 3000;
+        corpus.extend([
+            format!("{}1{}", "(".repeat(deep), ")".repeat(deep)),
+            format!("{}1", "-".repeat(deep)),
+            format!("{}1", "!".repeat(deep)),
+            format!("1{}", " ~".repeat(deep)),
+            format!("1{}", " 1 +".repeat(deep)),
+            format!("1{}", " + 1".repeat(deep)),
+            format!("2{}", " ^ 2".repeat(deep)),
+            format!("{}1{}", "abs(".repeat(deep), ")".repeat(deep)),
+        ]);
+
+        for input in &corpus {
+            let result = std::panic::catch_unwind(|| run_every_entry_point(input));
+            assert!(result.is_ok(), "panicked on {:?}", input);
+        }
+
+        // Characters outside the grammar are errors, never skipped.
+        for input in ["1 $ 2", "1 + 2 #", "€", "1 2 @ +", "1 + ２"] {
+            assert!(infix_to_rpn(input.to_string()).is_err(), "{:?}", input);
+            assert!(evaluate_rpn(input.to_string()).is_err(), "{:?}", input);
+            assert!(sy_evaluate_f64(input.to_string()).is_err(), "{:?}", input);
+        }
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_hex_and_binary_literals() {
+        assert_eq!(evaluate_rpn("0xFF 0b10 +".to_string()).unwrap(), 257);
+        assert_eq!(sy_evaulate("0x10 + 0b100".to_string()).unwrap(), 20);
+        assert_eq!(sy_evaulate("0XfF - 0B1".to_string()).unwrap(), 254);
+        assert_eq!(sy_evaulate("007 + 0".to_string()).unwrap(), 7);
+        assert_eq!(sy_evaulate("-0b1010*(0x2)".to_string()).unwrap(), -20);
+        assert_eq!(Tokenizer::tokenize("0b1010").unwrap(), vec![Token::Number(10)]);
+
+        for literal in ["0xGG", "0b102", "0b", "0x1g", "0b_1"] {
+            let expected = Err(ExpressionError::InvalidLiteral(literal.to_string()));
+            assert_eq!(sy_evaulate(format!("1 + {}", literal)), expected, "{}", literal);
+            assert_eq!(evaluate_rpn(format!("1 {} +", literal)), expected, "{}", literal);
+        }
+        assert_eq!(
+            sy_evaulate("0b2".to_string()).unwrap_err().to_string(),
+            "invalid number literal: 0b2",
+        );
+        assert_eq!(
+            rpn_to_infix("1 0xZ +"),
+            Err(ParseError { kind: ParseErrorKind::InvalidLiteral, position: 2 }),
+        );
+        assert_eq!(
+            sy_evaulate("0b1111111111111111111111111111111111111111111111111111111111111111".to_string()),
+            Err(ExpressionError::Overflow),
+        );
+    }
+
+    #[test]
+    fn test_dependencies() {
+        let strings = |names: &[&str]| names.iter().map(|name| name.to_string()).collect::<Vec<String>>();
+        let deps = |input| dependencies(input).unwrap();
+
+        assert_eq!(deps("max(x, y)"), Dependencies { variables: strings(&["x", "y"]), functions: strings(&["max"]) });
+        assert_eq!(deps("x + x * x").variables, strings(&["x"]));
+        assert_eq!(deps("b * a + b - c").variables, strings(&["b", "a", "c"]));
+        assert_eq!(
+            deps("f(g(z, max(y, z)), f(w)) + sqrt (y)"),
+            Dependencies { variables: strings(&["z", "y", "w"]), functions: strings(&["f", "g", "max", "sqrt"]) },
+        );
+        // A bare name and a call by the same name are each listed.
+        assert_eq!(deps("f(f) + f"), Dependencies { variables: strings(&["f"]), functions: strings(&["f"]) });
+        assert_eq!(deps("2 * pi * r"), Dependencies { variables: strings(&["r"]), functions: vec![] });
+        assert_eq!(deps("1 + 2"), Dependencies::default());
+
+        // Unbound variables and unknown functions are what it is for.
+        assert!(sy_evaluate_f64("nope(x)".to_string()).is_err());
+        assert_eq!(deps("nope(x)").functions, strings(&["nope"]));
+
+        let trailing = ParseErrorKind::TrailingOperator { span: Span { start: 2, end: 3 } };
+        assert_eq!(dependencies("x +"), Err(ParseError { kind: trailing, position: 2 }));
+        assert_eq!(dependencies("x $ y"), Err(ParseError { kind: ParseErrorKind::UnexpectedCharacter('$'), position: 2 }));
+        assert_eq!(dependencies("0xZ"), Err(ParseError { kind: ParseErrorKind::InvalidLiteral, position: 0 }));
+    }
+
+    #[test]
+    fn test_overflow_modes() {
+        let evaluate_in = |mode, input| {
+            let config = EvaluatorConfig { overflow: mode, ..EvaluatorConfig::default() };
+            return evaluate_with_config(input, &config);
+        };
+        let overflow = |op: &str, left, right| Err(ExpressionError::IntegerOverflow { op: op.to_string(), left, right });
+        let int = |n| Ok(Value::Int(n));
+        let min = "(-9223372036854775807 - 1)";
+
+        let cases = [
+            ("9223372036854775807 + 1", overflow("+", i64::MAX, 1), int(i64::MIN), int(i64::MAX)),
+            (&format!("{} - 1", min), overflow("-", i64::MIN, 1), int(i64::MAX), int(i64::MIN)),
+            ("4611686018427387904 * -3", overflow("*", 1 << 62, -3), int(1 << 62), int(i64::MIN)),
+            ("2 ^ 63", overflow("^", 2, 63), int(i64::MIN), int(i64::MAX)),
+            ("(-2) ^ 63", int(i64::MIN), int(i64::MIN), int(i64::MIN)),
+            ("(-2) ^ 64", overflow("^", -2, 64), int(0), int(i64::MAX)),
+            ("(-3) ^ 41", overflow("^", -3, 41), int(-3i64.wrapping_pow(41)), int(i64::MIN)),
+            ("3 ^ 4294967296", overflow("^", 3, 1 << 32), int(3i64.wrapping_pow(u32::MAX).wrapping_mul(3)), int(i64::MAX)),
+            ("(-1) ^ 9223372036854775807", int(-1), int(-1), int(-1)),
+        ];
+        for (input, checked, wrapping, saturating) in cases {
+            assert_eq!(evaluate_in(OverflowMode::Checked, input), checked, "{}", input);
+            assert_eq!(evaluate_in(OverflowMode::Wrapping, input), wrapping, "{}", input);
+            assert_eq!(evaluate_in(OverflowMode::Saturating, input), saturating, "{}", input);
+        }
+
+        // Neither of these is an overflow, so the mode doesn't matter.
+        for mode in [OverflowMode::Checked, OverflowMode::Wrapping, OverflowMode::Saturating] {
+            assert_eq!(evaluate_in(mode, "2 ^ -1"), Err(ExpressionError::NegativeExponent));
+            assert_eq!(evaluate_in(mode, "9223372036854775807 + 1.0"), Ok(Value::Float(9223372036854775808.0)));
+        }
+
+        assert_eq!(
+            evaluate_in(OverflowMode::Checked, "9223372036854775807 + 1").unwrap_err().to_string(),
+            "9223372036854775807 + 1 overflows"
+        );
+    }
+
+    #[test]
+    fn test_tokenizer_from_tokens() {
+        let mut tokenizer = Tokenizer::from_tokens(vec![Token::Number(3), Token::Plus]);
+        tokenizer.push(Token::Number(4));
+        tokenizer.extend([Token::Asterisk, Token::Number(2)]);
+        assert_eq!(tokenizer.iter().next().map(|t| t.span), Some(Span { start: 0, end: 0 }));
+        assert_eq!(tokenizer.input(), None);
+        assert_eq!(Tokenizer::new("3 + 4".to_string()).unwrap().input(), Some("3 + 4"));
+
+        let tokens: Vec<Token> = tokenizer.map(|t| t.token).collect();
+        assert_eq!(tokens, Tokenizer::tokenize("3 + 4 * 2").unwrap());
+        let rpn = infix_to_rpn_tokens(&tokens).unwrap();
+        assert_eq!(evaluate_rpn_tokens(&rpn), Ok(11));
+    }
+
+    #[test]
+    fn test_unicode_operators() {
+        // `\u{a0}` is a no-break space, as pasted from a document.
+        let unicode = "(7 × 3\u{a0}− 1) ÷ −2 + 2^−1";
+        assert_eq!(evaluate(unicode), evaluate("(7 * 3 - 1) / -2 + 2^-1"));
+        assert_eq!(infix_to_rpn(unicode.to_string()), infix_to_rpn("(7 * 3 - 1) / -2 + 2^-1".to_string()));
+        assert_eq!(evaluate_rpn_value("6 2 ÷ 4 × 1 −"), Ok(Value::Int(11)));
+        assert_eq!(evaluate("1.5e−1 × 2"), Ok(Value::Float(0.3)));
+    }
+
+    #[test]
+    fn test_config_notation() {
+        let european = EvaluatorConfig { decimal_separator: ',', argument_separator: ';', ..EvaluatorConfig::default() };
+        assert_eq!(evaluate_with_config("3,5 * 2", &european), Ok(Value::Float(7.0)));
+        assert_eq!(evaluate_with_config("max(1,5; ,25) + 1", &european), Ok(Value::Float(2.5)));
+        assert!(matches!(evaluate_with_config("3.5", &european), Err(ExpressionError::Parse(_))));
+        assert!(matches!(evaluate_with_config("3,5", &EvaluatorConfig::default()), Err(ExpressionError::Parse(_))));
+
+        let conflict = EvaluatorConfig { decimal_separator: ',', ..EvaluatorConfig::default() };
+        assert_eq!(evaluate_with_config("1", &conflict), Err(ExpressionError::SeparatorConflict(',')));
+
+        let fullwidth = EvaluatorConfig { fullwidth_digits: true, ..EvaluatorConfig::default() };
+        assert_eq!(evaluate_with_config("１２３ + ４.５", &fullwidth), Ok(Value::Float(127.5)));
+        assert!(evaluate_with_config("１２３", &EvaluatorConfig::default()).is_err());
+    }
+
+    #[test]
+    fn test_operator_shape_errors() {
+        let span = |start, end| Span { start, end };
+        for (input, kind, position) in [
+            ("2 * / 3", ParseErrorKind::ConsecutiveOperators { first_span: span(2, 3), second_span: span(4, 5) }, 4),
+            ("1 ^^ 2", ParseErrorKind::ConsecutiveOperators { first_span: span(2, 3), second_span: span(3, 4) }, 3),
+            ("/ 3", ParseErrorKind::LeadingOperator { span: span(0, 1) }, 0),
+            ("max(1, * 2)", ParseErrorKind::LeadingOperator { span: span(7, 8) }, 7),
+            ("3 *", ParseErrorKind::TrailingOperator { span: span(2, 3) }, 2),
+            ("(1 + 2) <=", ParseErrorKind::TrailingOperator { span: span(8, 10) }, 8),
+        ] {
+            let expected = ExpressionError::Parse(ParseError { kind, position });
+            // The one-shot evaluators and the parse-only paths agree.
+            assert_eq!(sy_evaulate(input.to_string()).unwrap_err(), expected, "{}", input);
+            assert_eq!(validate(input).unwrap_err(), expected, "{}", input);
+            assert_eq!(infix_to_rpn(input.to_string()).unwrap_err(), expected, "{}", input);
+            assert_eq!(expr::parse(input).unwrap_err(), expected, "{}", input);
+        }
+
+        // A unary minus is not a second operator.
+        for (input, expected) in [("-3", -3), ("2 * -3", -6), ("(-3)", -3), ("max(1, -2)", 1), ("2 - -3", 5)] {
+            assert_eq!(sy_evaulate(input.to_string()), Ok(expected), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_empty_groups_and_arguments() {
+        let span = |start, end| Span { start, end };
+        for (input, kind, position) in [
+            ("()", ParseErrorKind::EmptyParens { span: span(0, 2) }, 1),
+            ("1 + ()", ParseErrorKind::EmptyParens { span: span(4, 6) }, 5),
+            ("max(1,,2)", ParseErrorKind::EmptyArgument { call_span: span(0, 9), arg_index: 1 }, 6),
+            ("2 * max(1, sqrt(4),)", ParseErrorKind::EmptyArgument { call_span: span(4, 20), arg_index: 2 }, 19),
+            ("f((), 2)", ParseErrorKind::EmptyParens { span: span(2, 4) }, 3),
+        ] {
+            let expected = ExpressionError::Parse(ParseError { kind, position });
+            assert_eq!(sy_evaluate_f64(input.to_string()).unwrap_err(), expected, "{}", input);
+            assert_eq!(expr::parse(input).unwrap_err(), expected, "{}", input);
+        }
+
+        // A call without arguments is for the function's arity to allow.
+        let arity = || ExpressionError::WrongArity { name: "max".to_string(), expected: 2, got: 0 };
+        assert_eq!(sy_evaluate_f64("1 + max()".to_string()), Err(arity()));
+        assert_eq!(expr::parse("1 + max()").unwrap().eval(), Err(arity()));
+        assert_eq!(Evaluator::default().evaluate("1 + max()"), Err(arity()));
+        assert_eq!(Evaluator::default().compile("1 + max()").err(), Some(arity()));
+        assert_eq!(validate_all("1 + max()"), vec![Diagnostic {
+            severity: Severity::Error,
+            span: span(4, 9),
+            message: arity().to_string(),
+        }]);
+
+        let evaluator = Evaluator::default().with_function("answer", Function::Nary(0, |_| 42.0));
+        assert_eq!(evaluator.evaluate("answer() + max(answer(), 1)").unwrap(), 84.0);
+        assert_eq!(evaluator.compile("answer() * 2").unwrap().eval(&environment::NoVariables).unwrap(), 84.0);
+        let mut functions = Functions::default();
+        functions.insert("answer", Function::Nary(0, |_| 42.0));
+        assert_eq!(sy_evaluate_with("answer() - 2".to_string(), &environment::NoVariables, &functions).unwrap(), 40.0);
+    }
+
+    #[test]
+    fn test_missing_operator_spans() {
+        for (input, left, right) in [("2 3 + 1", (0, 1), (2, 3)), ("(1+2) 5", (0, 5), (6, 7))] {
+            let Err(ExpressionError::Parse(err)) = sy_evaulate(input.to_string()) else { panic!("{}", input) };
+            assert_eq!(err.kind, missing_operator(left, right), "{}", input);
+            assert_eq!(validate(input), Err(ExpressionError::Parse(err)), "{}", input);
+            let ParseErrorKind::MissingOperator { left_span, right_span, hint } = err.kind else { unreachable!() };
+            // The gap between the operands is only whitespace.
+            assert_eq!(input[left_span.end..right_span.start].trim(), "", "{}", input);
+            assert_eq!(err.position, right_span.start, "{}", input);
+            assert_eq!(hint, "insert an operator, or enable implicit multiplication");
+        }
+        assert_eq!(sy_evaulate("sqrt(4) (2)".to_string()).unwrap_err(), ExpressionError::Parse(ParseError {
+            kind: missing_operator((0, 7), (8, 11)),
+            position: 8,
+        }));
+
+        // Implicit multiplication reads the same gaps as products.
+        let evaluator = Evaluator::default().with_implicit_multiplication(true);
+        assert_eq!(evaluator.evaluate("2 3 + 1").unwrap(), 7.0);
+        assert_eq!(evaluator.evaluate("(1+2) 5").unwrap(), 15.0);
+        assert_eq!(evaluator.infix_to_rpn("(1+2) 5").unwrap(), "1 2 + 5 *");
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_number_lexemes() {
+        let input = "007 + max(1_000, 0xFF_FF) * 1.50";
+        let tokenizer = Tokenizer::new(input.to_string()).unwrap();
+        let lexemes: Vec<&str> = tokenizer
+            .iter()
+            .filter(|t| matches!(t.token, Token::Number(_) | Token::Float(_)))
+            .filter_map(|t| tokenizer.lexeme(t))
+            .collect();
+        assert_eq!(lexemes, vec!["007", "1_000", "0xFF_FF", "1.50"]);
+        assert_eq!(tokenizer.to_infix(), input);
+        assert_eq!(Tokenizer::new("007+1_000".to_string()).unwrap().to_infix(), "007 + 1_000");
+        // The values are what get evaluated, and re-emitting loses nothing.
+        let tokens: Vec<Token> = tokenizer.iter().map(|t| t.token.clone()).collect();
+        assert_eq!(Tokenizer::tokenize(&tokenizer.to_infix()).unwrap(), tokens);
+        assert_eq!(tokens_to_infix(&tokens), "7 + max(1000, 65535) * 1.5");
+        assert_eq!(Tokenizer::from_tokens(tokens).to_infix(), "7 + max(1000, 65535) * 1.5");
+
+        assert_eq!(sy_evaulate("1_000 + 007".to_string()), Ok(1007));
+        assert_eq!(evaluate_rpn("1_000 -2 *".to_string()), Ok(-2000));
+        assert_eq!(sy_evaluate_f64("1_000.25 * 4".to_string()), Ok(4001.0));
+        for literal in ["1_", "1_000_", "0x_1", "0xF_"] {
+            assert_eq!(sy_evaulate(literal.to_string()), Err(ExpressionError::InvalidLiteral(literal.to_string())));
+        }
+        assert_eq!(validate_all("99_999_999_999_999_999_999"), vec![Diagnostic {
+            severity: Severity::Error,
+            span: Span { start: 0, end: 26 },
+            message: "number too large: 99_999_999_999_999_999_999".to_string(),
+        }]);
+    }
+
+    #[test]
+    fn test_dump_tokens() {
+        let tokens: Vec<SpannedToken> = Tokenizer::new("-1.5 <= x_1".to_string()).unwrap().collect();
+        assert_eq!(dump_tokens(&tokens), "Operator   -        0..1\nFloat      1.5      1..4\nOperator   <=       5..7\nIdent      x_1      8..11\n");
+        assert_eq!(dump_tokens(&[]), "");
+    }
+
+    #[test]
+    fn test_rpn_with_variables_and_calls() {
+        let vars: HashMap<String, f64> =
+            [("price", 2.5), ("qty", 4.0), ("fee", 1.0), ("a", 3.0), ("b", -7.0)].map(|(k, v)| (k.to_string(), v)).into();
+        let infix_value = |input: &str| sy_evaluate_with(input.to_string(), &vars, default_functions());
+
+        for (infix, rpn) in [
+            ("price * qty + fee", "price qty * fee +"),
+            ("max(a, b) * 2", "a b max 2 *"),
+            ("-sqrt(a * a + b) / qty", "a a * b + sqrt ~ qty /"),
+            ("hypot(min(a, b), abs(b)) ^ 2", "a b min b abs hypot 2 ^"),
+            ("pi * a ^ 2 - floor(fee)", "pi a 2 ^ * fee floor -"),
+        ] {
+            assert_eq!(infix_to_rpn(infix.to_string()).unwrap(), rpn);
+            assert_eq!(evaluate_rpn_with(rpn.to_string(), &vars, default_functions()), infix_value(infix), "{}", rpn);
+            let back = rpn_to_infix(rpn).unwrap();
+            assert_eq!(infix_value(&back), infix_value(infix), "{} came back as {}", infix, back);
+        }
+
+        // A call takes as many operands as its function's arity says.
+        assert_eq!(rpn_to_infix("1 2 3 max max").unwrap(), "max(1, max(2, 3))");
+        assert_eq!(rpn_to_infix("a 16 sqrt hypot").unwrap(), "hypot(a, sqrt(16))");
+        assert_eq!(rpn_to_infix("2 max"), Err(ParseError { kind: ParseErrorKind::MissingOperand, position: 2 }));
+
+        let mut functions = default_functions().clone();
+        functions.insert("mid", Function::Nary(3, |args| args[1]));
+        let rpn = Evaluator::default().with_function("mid", Function::Nary(3, |args| args[1])).infix_to_rpn("mid(a, b, 1) + 1");
+        assert_eq!(rpn.as_deref(), Ok("a b 1 mid 1 +"));
+        assert_eq!(evaluate_rpn_with(rpn.unwrap(), &vars, &functions), Ok(-6.0));
+    }
+
+    #[test]
+    fn test_feed_line_continuation() {
+        let lines = |input: &str| {
+            let mut evaluator = Evaluator::default();
+            let fed = input.lines().map(|line| evaluator.feed(line));
+            let results: Vec<_> = fed.filter(|result| result != &Ok(None)).collect();
+            (results, evaluator.is_pending())
+        };
+
+        // A trailing operator, an open `(` and a trailing `\` each continue.
+        assert_eq!(lines("1 +\n2"), (vec![Ok(Some(3.0))], false));
+        assert_eq!(lines("(1 +\n 2) * 3"), (vec![Ok(Some(9.0))], false));
+        assert_eq!(lines("1 \\\n+ 2"), (vec![Ok(Some(3.0))], false));
+        assert_eq!(lines("1\n+ 2"), (vec![Ok(Some(1.0)), Err(ExpressionError::Parse(ParseError {
+            kind: ParseErrorKind::LeadingOperator { span: Span { start: 0, end: 1 } },
+            position: 0,
+        }))], false));
+        assert_eq!(lines("1 + 2\n3 * 4"), (vec![Ok(Some(3.0)), Ok(Some(12.0))], false));
+        assert_eq!(lines("2 \\"), (vec![], true));
+        let mut evaluator = Evaluator::default();
+        assert_eq!((evaluator.feed("2 \\"), evaluator.finish(), evaluator.finish()), (Ok(None), Ok(Some(2.0)), Ok(None)));
+        assert!((evaluator.feed("1 +"), evaluator.finish()).1.is_err());
+
+        // Comments, with a `\` before one.
+        assert_eq!(lines("2 * \\ # doubled\n(3 + 4) # the sum"), (vec![Ok(Some(14.0))], false));
+        assert_eq!(lines("# only a comment\n5"), (vec![Ok(Some(5.0))], false));
+        let hash = Evaluator::default().with_operator('#', 1, Associativity::Left, |a, b| Ok(a - b)).unwrap();
+        assert_eq!(hash.clone().feed("5 # 3"), Ok(Some(2.0)));
+
+        // Positions count through the `\` and the comment, so they still
+        // point at the right line and column of what was typed.
+        let typed = "1 + \\ # more\n2 $ 3";
+        let mut evaluator = Evaluator::default();
+        assert_eq!(evaluator.feed("1 + \\ # more"), Ok(None));
+        let Err(ExpressionError::Parse(error)) = evaluator.feed("2 $ 3") else { panic!() };
+        assert_eq!(error, ParseError { kind: ParseErrorKind::UnexpectedCharacter('$'), position: 15 });
+        let line_start = typed[..error.position].rfind('\n').unwrap() + 1;
+        assert_eq!((typed[..line_start].lines().count() + 1, error.position - line_start), (2, 2));
+    }
+
+    #[test]
+    fn test_lossy_tokenizing() {
+        let skipped = |character, start, end| SkippedCharacter { character, span: Span { start, end } };
+        let evaluator = Evaluator::default();
+
+        let zero_width = evaluator.evaluate_lossy("1 + 2 \u{200B} * 3", &NoVariables);
+        assert_eq!(zero_width, Ok((7.0, vec![skipped('\u{200B}', 6, 9)])));
+        assert_eq!(
+            evaluator.evaluate_lossy("\u{201C}2\u{201D} + 3;", &NoVariables),
+            Ok((5.0, vec![skipped('\u{201C}', 0, 3), skipped('\u{201D}', 4, 7), skipped(';', 11, 12)]))
+        );
+        assert_eq!(evaluator.evaluate_lossy("2 * 3", &NoVariables), Ok((6.0, vec![])));
+        assert_eq!(
+            Tokenizer::tokenize_lossy("1 $ - 2"),
+            Ok((vec![Token::Number(1), Token::Minus, Token::Number(2)], vec![skipped('$', 2, 3)]))
+        );
+
+        // A skipped character still separates what is either side of it.
+        let vars = HashMap::from([("x".to_string(), 2.0), ("y".to_string(), 3.0), ("xy".to_string(), 10.0)]);
+        assert!(matches!(evaluator.evaluate_lossy("x\u{200B}y", &vars), Err(ExpressionError::Parse(_))));
+        assert!(matches!(evaluator.evaluate_lossy("1\u{200B}2", &vars), Err(ExpressionError::Parse(_))));
+        let implicit = Evaluator::default().with_implicit_multiplication(true);
+        assert_eq!(implicit.evaluate_lossy("x\u{200B}y", &vars), Ok((6.0, vec![skipped('\u{200B}', 1, 4)])));
+
+        // What means something where it is is still an error, as is what
+        // is left once the rest is skipped.
+        let point = ParseError { kind: ParseErrorKind::UnexpectedCharacter('.'), position: 3 };
+        assert_eq!(evaluator.evaluate_lossy("1.2.3;", &NoVariables), Err(ExpressionError::Parse(point)));
+        assert_eq!(evaluator.evaluate_lossy(";", &NoVariables), Err(ExpressionError::EmptyInput));
+
+        // Strict stays the default.
+        let strict = [("1 + 2 \u{200B} * 3", '\u{200B}', 6), ("\u{201C}2\u{201D} + 3;", '\u{201C}', 0)];
+        for (input, character, position) in strict {
+            let error = ParseError { kind: ParseErrorKind::UnexpectedCharacter(character), position };
+            assert_eq!(evaluator.evaluate(input), Err(ExpressionError::Parse(error)));
+            assert_eq!(Tokenizer::tokenize(input), Err(ExpressionError::UnknownCharacter(character)));
+        }
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_chained_comparisons() {
+        // The range check, as it is written in maths.
+        let evaluator = Evaluator::default();
+        for x in [0.0, 0.999, 1.0, 2.5, 4.999, 5.0, 6.0] {
+            let vars = HashMap::from([("x".to_string(), x)]);
+            let expected = (1.0..5.0).contains(&x);
+            assert_eq!(evaluator.evaluate_with_env("1 <= x < 5", &vars), Ok(expected as u8 as f64), "{}", x);
+            assert_eq!(evaluator.compile("1 <= x < 5").unwrap().eval(&vars), Ok(expected as u8 as f64), "{}", x);
+            assert_eq!(evaluate_value_with_env("1 <= x < 5", &vars), Ok(Value::Bool(expected)), "{}", x);
+            assert_eq!(parse("1 <= x < 5").unwrap().eval_with_vars(&vars), Ok(expected as u8 as f64), "{}", x);
+        }
+
+        // Chains of three, in mixed directions, and with equality.
+        for (input, value) in [
+            ("1 < 2 < 3 < 4", true),
+            ("1 < 2 < 3 < 3", false),
+            ("4 > 3 >= 3 > 1", true),
+            ("1 < 3 > 2", true),
+            ("1 < 3 > 4", false),
+            ("3 > 1 < 2", true),
+            ("1 < 2 == 2", true),
+            ("2 == 2 < 3", true),
+            ("2 != 2 < 3", false),
+            ("1 < 1 + 1 < 2 * 2", true),
+            ("1 < 2 < 3 && 0 > 1", false),
+            ("(1 < 2 < 3) == true", true),
+            ("1 < 5 > 2 < 4", true),
+        ] {
+            assert_eq!(evaluate(input), Ok(Value::Bool(value)), "{}", input);
+            assert_eq!(sy_evaluate_f64(input.to_string()), Ok(value as u8 as f64), "{}", input);
+            let rpn = infix_to_rpn_f64(input.to_string()).unwrap();
+            assert_eq!(evaluate_rpn_f64(rpn), Ok(value as u8 as f64), "{}", input);
+            assert_eq!(parse(input).unwrap().eval(), Ok(value as u8 as f64), "{}", input);
+        }
+        assert_eq!(infix_to_rpn("1 < 2 + 3 >= 4 == 1".to_string()).unwrap(), "1 2 3 + 4 1 [< >= ==]");
+
+        // Parenthesized, a comparison is compared as it is, and in `Value`
+        // mode a boolean can't be compared with a number.
+        let mismatch = |op: &str, operands: [&str; 2]| ExpressionError::TypeMismatch {
+            op: op.to_string(),
+            operands: operands.iter().map(|operand| operand.to_string()).collect(),
+        };
+        let vars = HashMap::from([("x".to_string(), 2.0)]);
+        assert_eq!(evaluate_value_with_env("(1 <= x) < 5", &vars), Err(mismatch("<", ["bool", "int"])));
+        assert_eq!(evaluate_value_with_env("1 <= (x < 5)", &vars), Err(mismatch("<=", ["int", "bool"])));
+        assert_eq!(evaluator.evaluate_with_env("(1 <= x) < 5", &vars), Ok(1.0));
+        assert_eq!(evaluator.evaluate_with_env("(5 <= x) < 5", &vars), Ok(1.0));
+        assert_eq!(evaluator.evaluate_with_env("5 <= x < 5", &vars), Ok(0.0));
+        // A link in a chain of mixed directions can mismatch too.
+        assert_eq!(evaluate("1 < 3 > true"), Err(mismatch(">", ["int", "bool"])));
+        assert_eq!(evaluate("true > 1 < 3"), Err(mismatch(">", ["bool", "int"])));
+
+        // RPN writes a chain after all its operands.
+        assert_eq!(evaluate_rpn_value("1 2 2 [< <=]"), Ok(Value::Bool(true)));
+        assert_eq!(evaluate_rpn("1 2 [< <]".to_string()), Err(ExpressionError::EmptyStack));
+        assert!(evaluate_rpn("1 2 [<]".to_string()).is_err());
+        assert!(evaluate_rpn("1 2 3 [< +]".to_string()).is_err());
+        assert!(evaluate_rpn("1 2 3 [< <".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_chain_evaluates_middle_once() {
+        use core::sync::atomic::{AtomicUsize, Ordering};
+
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+        fn counted(x: f64) -> f64 {
+            CALLS.fetch_add(1, Ordering::SeqCst);
+            return x;
+        }
+
+        let evaluator = Evaluator::default().with_function("mid", Function::Unary(counted));
+        assert_eq!(evaluator.evaluate("1 < mid(3) < 5"), Ok(1.0));
+        assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+        assert_eq!(evaluator.evaluate("1 < mid(3) <= mid(4) < 5"), Ok(1.0));
+        assert_eq!(CALLS.load(Ordering::SeqCst), 3);
+        assert_eq!(evaluator.compile("1 < mid(7) < 5").unwrap().eval(&NoVariables), Ok(0.0));
+        assert_eq!(CALLS.load(Ordering::SeqCst), 4);
+        assert_eq!(evaluator.evaluate_rpn(&evaluator.infix_to_rpn("0 < mid(1) < 2").unwrap()), Ok(1.0));
+        assert_eq!(CALLS.load(Ordering::SeqCst), 5);
+    }
+
+    #[test]
+    fn test_math_notation() {
+        let evaluator = Evaluator::default().with_math_notation(true).unwrap();
+        let vars = HashMap::from([("x".to_string(), 3.0), ("y".to_string(), 4.0), ("n".to_string(), -3.0)]);
+        for (input, value) in [
+            ("√9", 3.0),
+            ("√(1+3)", 2.0),
+            ("√(x^2 + y^2)", 5.0),
+            ("|x - y|", 1.0),
+            ("√y", 2.0),
+            ("√4^2", 4.0),
+            ("√√16", 2.0),
+            ("-√4", -2.0),
+            ("2 * √9 + |-3|", 9.0),
+            ("√|-16|", 4.0),
+            ("|√16 - 5|", 1.0),
+            ("||n| - 5|", 2.0),
+            ("||n||", 3.0),
+            ("|n| || 0", 1.0),
+            ("|(0 || 0)|", 0.0),
+            ("max(|n|, 1)", 3.0),
+            ("√max(4, 9)", 3.0),
+        ] {
+            assert_eq!(evaluator.evaluate_with_env(input, &vars), Ok(value), "{}", input);
+        }
+        let implicit = evaluator.clone().with_implicit_multiplication(true);
+        assert_eq!(implicit.evaluate_with_env("2√9 + |n|4", &vars), Ok(18.0));
+        // Where an operator is expected a `|` is one, even so.
+        assert!(implicit.evaluate_with_env("3|n|", &vars).is_err());
+
+        let error = |kind, position| Err(ExpressionError::Parse(ParseError { kind, position }));
+        assert_eq!(evaluator.evaluate_with_env("|x", &vars), error(ParseErrorKind::UnbalancedBar, 0));
+        assert_eq!(evaluator.evaluate_with_env("|x - |y|", &vars), error(ParseErrorKind::UnbalancedBar, 0));
+        assert_eq!(evaluator.evaluate_with_env("(|x)|", &vars), error(ParseErrorKind::UnbalancedBar, 1));
+        assert_eq!(evaluator.evaluate_with_env("|(x|)", &vars), error(ParseErrorKind::UnbalancedBar, 0));
+        assert_eq!(evaluator.evaluate("√"), error(ParseErrorKind::MissingOperand, 3));
+        assert_eq!(evaluator.evaluate("1 + √ * 2").unwrap_err().to_string(), "missing operand at position 8");
+        assert_eq!(
+            evaluator.evaluate("|1").unwrap_err().to_string(),
+            "unbalanced absolute value bar at position 0"
+        );
+
+        // Off, as by default, `√` is a stray character and `|` an operator.
+        let plain = Evaluator::default();
+        assert_eq!(plain.evaluate("√9"), error(ParseErrorKind::UnexpectedCharacter('√'), 0));
+        let leading = ParseErrorKind::LeadingOperator { span: Span { start: 0, end: 1 } };
+        assert_eq!(plain.evaluate("|1|"), error(leading, 0));
+        assert_eq!(evaluator.clone().with_math_notation(false).unwrap().evaluate("√9"), plain.evaluate("√9"));
+
+        // A `|` operator and bars can't both be on.
+        let or = |a: f64, b: f64| Ok(((a as i64) | (b as i64)) as f64);
+        let with_or = Evaluator::default().with_operator('|', 3, Associativity::Left, or).unwrap();
+        assert_eq!(with_or.evaluate("5 | 2"), Ok(7.0));
+        assert_eq!(with_or.clone().with_math_notation(true).err(), Some(ExpressionError::InvalidOperator('|')));
+        assert!(with_or.with_math_notation(false).is_ok());
+        let bars = evaluator.with_operator('|', 3, Associativity::Left, or);
+        assert_eq!(bars.err(), Some(ExpressionError::InvalidOperator('|')));
+    }
+
+    #[test]
+    fn test_superscripts() {
+        let evaluator = Evaluator::default().with_superscripts(true);
+        let vars = HashMap::from([("x".to_string(), 3.0), ("y".to_string(), 4.0)]);
+        for (input, value) in [
+            ("3²", 9.0),
+            ("x²", 9.0),
+            ("(1+1)³", 8.0),
+            ("2⁻¹", 0.5),
+            ("10³", 1000.0),
+            ("x² + y²", 25.0),
+            ("2¹⁰", 1024.0),
+            ("-3²", -9.0),
+            ("2^3²", 512.0),
+            ("max(2, 3)²", 9.0),
+            ("x⁰", 1.0),
+        ] {
+            assert_eq!(evaluator.evaluate_with_env(input, &vars), Ok(value), "{}", input);
+        }
+
+        // The `^` has no characters of its own, and each exponent has its digits.
+        let notation = Notation { superscripts: true, ..Notation::ASCII };
+        let spans = |input| -> Vec<(Token, Span)> {
+            let tokens = tokenize_infix_spanned_with(input, &OperatorTable::default(), false, notation).unwrap();
+            return tokens.into_iter().map(|t| (t.token, t.span)).collect();
+        };
+        assert_eq!(
+            spans("x²³ + 1"),
+            [
+                (Token::Ident("x".to_string()), Span { start: 0, end: 1 }),
+                (Token::Caret, Span { start: 1, end: 1 }),
+                (Token::Number(23), Span { start: 1, end: 5 }),
+                (Token::Plus, Span { start: 6, end: 7 }),
+                (Token::Number(1), Span { start: 8, end: 9 }),
+            ]
+        );
+        assert_eq!(
+            spans("2⁻¹"),
+            [
+                (Token::Number(2), Span { start: 0, end: 1 }),
+                (Token::Caret, Span { start: 1, end: 1 }),
+                (Token::UnaryMinus, Span { start: 1, end: 4 }),
+                (Token::Number(1), Span { start: 4, end: 6 }),
+            ]
+        );
+
+        let error = |kind, position| Err(ExpressionError::Parse(ParseError { kind, position }));
+        let leading = ParseErrorKind::LeadingOperator { span: Span { start: 0, end: 0 } };
+        assert_eq!(evaluator.evaluate("²"), error(leading, 0));
+        assert!(matches!(
+            evaluator.evaluate("1 + ²"),
+            Err(ExpressionError::Parse(ParseError { kind: ParseErrorKind::ConsecutiveOperators { .. }, .. }))
+        ));
+        assert_eq!(evaluator.evaluate("2⁻"), error(ParseErrorKind::MissingExponent, 1));
+        assert_eq!(evaluator.evaluate("2⁹⁹⁹⁹⁹⁹⁹⁹⁹⁹⁹⁹⁹⁹⁹⁹⁹⁹⁹⁹"), Err(ExpressionError::Overflow));
+
+        // Off, as by default, they are stray characters.
+        let plain = Evaluator::default();
+        assert_eq!(plain.evaluate("3²"), error(ParseErrorKind::UnexpectedCharacter('²'), 1));
+        assert_eq!(plain.evaluate("2⁻¹"), error(ParseErrorKind::UnexpectedCharacter('⁻'), 1));
+    }
+
+    /// Counts the allocations made on the thread that is `counting`, so
+    /// the other tests running alongside don't add theirs.
+    struct CountingAllocator;
+
+    std::thread_local! {
+        static ALLOCATIONS: core::cell::Cell<Option<usize>> = const { core::cell::Cell::new(None) };
+    }
+
+    fn count_allocation() {
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get().map(|n| n + 1)));
+    }
+
+    unsafe impl core::alloc::GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(self: &Self, layout: core::alloc::Layout) -> *mut u8 {
+            count_allocation();
+            return unsafe { std::alloc::System.alloc(layout) };
+        }
+
+        unsafe fn dealloc(self: &Self, ptr: *mut u8, layout: core::alloc::Layout) {
+            unsafe { std::alloc::System.dealloc(ptr, layout) };
+        }
+
+        unsafe fn realloc(self: &Self, ptr: *mut u8, layout: core::alloc::Layout, new_size: usize) -> *mut u8 {
+            count_allocation();
+            return unsafe { std::alloc::System.realloc(ptr, layout, new_size) };
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    /// What `f` gives, with how many allocations it took.
+    fn counting<T>(f: impl FnOnce() -> T) -> (T, usize) {
+        ALLOCATIONS.with(|count| count.set(Some(0)));
+        let result = f();
+        let Some(count) = ALLOCATIONS.with(|count| count.replace(None)) else { unreachable!() };
+        return (result, count);
+    }
+
+    #[test]
+    fn test_allocations_independent_of_length() {
+        let evaluator = Evaluator::default();
+        let mut counts = Vec::new();
+        for bytes in [1_000, 100_000, 1_000_000] {
+            let chunk = "(1 + 2) * 3 - 4.5 / 1.5 + ";
+            let repeats = bytes / chunk.len();
+            let input = chunk.repeat(repeats) + "0";
+            let expected = 6.0 * repeats as f64;
+
+            let (value, evaluating) = counting(|| evaluator.evaluate(&input));
+            assert_eq!(value, Ok(expected));
+            let (rpn, converting) = counting(|| evaluator.infix_to_rpn(&input));
+            let rpn = rpn.unwrap();
+            assert_eq!(rpn, infix_to_rpn_f64(input.clone()).unwrap());
+            assert!(rpn.starts_with("1 2 + 3 * 4.5 1.5 / - 1 2 + "));
+            let (value, evaluating_rpn) = counting(|| evaluator.evaluate_rpn(&rpn));
+            assert_eq!(value, Ok(expected));
+            counts.push((evaluating, converting, evaluating_rpn));
+        }
+        assert_eq!(counts[0], counts[1]);
+        assert_eq!(counts[0], counts[2]);
+        assert!(counts[0].0 <= 8 && counts[0].1 <= 8 && counts[0].2 <= 8, "{:?}", counts[0]);
+
+        // A batch sizes its 3 buffers for the first input, and grows them for
+        // the longer second one, unless the hint covers both.
+        let inputs = ["1 + 2", "(1 + 2) * (3 + 4) - 5", "1"];
+        let (values, unhinted) = counting(|| evaluator.evaluate_batch(inputs));
+        assert_eq!(values, [Ok(3.0), Ok(16.0), Ok(1.0)]);
+        let hinted = Evaluator::default().with_capacity_hint(64);
+        let (values, hinted) = counting(|| hinted.evaluate_batch(inputs));
+        assert_eq!(values, [Ok(3.0), Ok(16.0), Ok(1.0)]);
+        assert_eq!(unhinted, hinted + 3);
+    }
+
+    #[test]
+    fn test_missing_variable_policies() {
+        let vars = HashMap::from([("y".to_string(), 2.0)]);
+        let policy = |missing_variables| EvaluatorConfig { missing_variables, ..EvaluatorConfig::default() };
+        let error = policy(MissingVarPolicy::Error);
+        let zero = policy(MissingVarPolicy::Default(Value::Int(0)));
+        let null = policy(MissingVarPolicy::Propagate);
+
+        let input = "x * y + 1";
+        let undefined = Err(ExpressionError::UndefinedVariable("x".to_string()));
+        assert_eq!(evaluate_with_config_env(input, &vars, &error), undefined);
+        assert_eq!(evaluate_with_config_env(input, &vars, &zero), Ok(Value::Float(1.0)));
+        assert_eq!(evaluate_with_config_env(input, &vars, &null), Ok(Value::Null));
+        // Present variables are the same under any policy.
+        for config in [error, zero, null] {
+            assert_eq!(evaluate_with_config_env("y * 3", &vars, &config), Ok(Value::Float(6.0)));
+        }
+        assert_eq!(evaluate_with_config("x", &EvaluatorConfig::default()), undefined);
+
+        // Null goes through every operator, however deep.
+        for input in [
+            "((x + 1) * (y - 3)) / 2 ^ sqrt(4)",
+            "-x",
+            "!x",
+            "x % 2 + max(y, x)",
+            "x == x",
+            "x < 1",
+            "1 < x < 3",
+            "(x > 1) && false",
+            "true || x",
+        ] {
+            assert_eq!(evaluate_with_config_env(input, &vars, &null), Ok(Value::Null), "{}", input);
+        }
+
+        // Unless it is caught.
+        for (input, value) in [
+            ("coalesce(x, 0)", Value::Int(0)),
+            ("coalesce(x, 0) + y", Value::Float(2.0)),
+            ("coalesce(y, 0)", Value::Float(2.0)),
+            ("coalesce(x, coalesce(z, 5))", Value::Int(5)),
+            ("is_null(x)", Value::Bool(true)),
+            ("is_null(x * y)", Value::Bool(true)),
+            ("is_null(y)", Value::Bool(false)),
+            ("if(is_null(x), -1, x * 2)", Value::Int(-1)),
+            ("if(is_null(y), -1, y * 2)", Value::Float(4.0)),
+            ("if(x > 1, 1, 0)", Value::Null),
+            ("if(true, 1, x)", Value::Int(1)),
+        ] {
+            assert_eq!(evaluate_with_config_env(input, &vars, &null), Ok(value), "{}", input);
+        }
+        let condition = ExpressionError::TypeMismatch {
+            op: "if".to_string(),
+            operands: vec!["int".to_string(), "int".to_string(), "int".to_string()],
+        };
+        assert_eq!(evaluate_with_config("if(1, 2, 3)", &null), Err(condition));
+        assert_eq!(Value::Null.to_string(), "null");
+
+        // Numbers are never null, so in `f64` the guards give way.
+        assert_eq!(sy_evaluate_with_vars("coalesce(y, 0) + is_null(y)".to_string(), &vars), Ok(2.0));
+        assert_eq!(sy_evaluate_with_vars("if(y > 1, 10, 20)".to_string(), &vars), Ok(10.0));
     }
 }
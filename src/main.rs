@@ -0,0 +1,215 @@
+//! A command line calculator:
+//!
+//! ```text
+//! expresso "1 + 2 * (3 - 1)"    evaluates infix
+//! expresso --rpn "1 2 +"        evaluates postfix
+//! expresso --to-rpn "1 + 2"     converts infix to postfix
+//! expresso --to-ast "1 + 2"     prints the parse tree, a node to a line;
+//!                               `--format debug` prints it on one line
+//! expresso                      reads expressions from stdin, one per line
+//! ```
+//!
+//! Errors go to stderr, with a `^` under where the problem is, and make
+//! the exit status 1. Reading from stdin carries on after an error.
+#![allow(clippy::needless_arbitrary_self_type, clippy::needless_return)]
+
+use std::io::{self, BufRead, Write};
+use std::process::ExitCode;
+
+use expresso::{Evaluator, ExpressionError};
+
+const USAGE: &str = "usage: expresso [--rpn | --to-rpn | --to-ast [--format tree|debug]] [EXPRESSION]";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let code = run(&args, io::stdin().lock(), &mut io::stdout(), &mut io::stderr()).unwrap_or(1);
+    return ExitCode::from(code);
+}
+
+/// Runs the calculator with `args`, the arguments after the program name,
+/// reading `input` if there is no expression among them. Returns the exit
+/// status.
+fn run(args: &[String], input: impl BufRead, out: &mut dyn Write, err: &mut dyn Write) -> io::Result<u8> {
+    let evaluator = Evaluator::default();
+    let (mode, expression) = match args.first().map(String::as_str) {
+        None => return repl(input, out, err),
+        Some("-h" | "--help") => {
+            writeln!(out, "{}", USAGE)?;
+            return Ok(0);
+        },
+        Some(flag @ ("--rpn" | "--to-rpn")) => (flag, &args[1..]),
+        Some("--to-ast") => match args.get(1..3) {
+            Some([option, format]) if option == "--format" => match format.as_str() {
+                "tree" => ("--to-ast", &args[3..]),
+                "debug" => ("--to-ast-debug", &args[3..]),
+                _ => {
+                    writeln!(err, "unknown format: {}\n{}", format, USAGE)?;
+                    return Ok(2);
+                },
+            },
+            _ => ("--to-ast", &args[1..]),
+        },
+        Some(flag) if flag.starts_with("--") => {
+            writeln!(err, "unknown option: {}\n{}", flag, USAGE)?;
+            return Ok(2);
+        },
+        Some(_) => ("", args),
+    };
+    if expression.is_empty() {
+        writeln!(err, "{}", USAGE)?;
+        return Ok(2);
+    }
+
+    // An unquoted expression arrives in pieces, as in `expresso 1 + 2`.
+    let expression = expression.join(" ");
+    let result = match mode {
+        "--rpn" => evaluator.evaluate_rpn(&expression).map(|value| value.to_string()),
+        "--to-rpn" => evaluator.infix_to_rpn(&expression),
+        // The tree ends with a newline of its own.
+        "--to-ast" => expresso::parse(&expression).map(|expr| expr.to_debug_tree().trim_end().to_string()),
+        "--to-ast-debug" => expresso::parse(&expression).map(|expr| format!("{:?}", expr)),
+        _ => evaluator.evaluate(&expression).map(|value| value.to_string()),
+    };
+    match result {
+        Ok(text) => {
+            writeln!(out, "{}", text)?;
+            return Ok(0);
+        },
+        Err(error) => {
+            write_error(err, &expression, &error)?;
+            return Ok(1);
+        },
+    }
+}
+
+/// Evaluates `input` a line at a time. An expression can go on over several
+/// lines while it is unfinished, as in `Evaluator::feed`, and `ans` is the
+/// last result.
+fn repl(input: impl BufRead, out: &mut dyn Write, err: &mut dyn Write) -> io::Result<u8> {
+    let mut evaluator = Evaluator::default();
+    // The lines of the expression so far, for pointing into.
+    let mut expression = String::new();
+
+    for line in input.lines() {
+        let line = line?;
+        if !expression.is_empty() {
+            expression.push('\n');
+        }
+        expression.push_str(&line);
+
+        match evaluator.feed(&line) {
+            Ok(Some(value)) => writeln!(out, "{}", value)?,
+            Ok(None) => {},
+            Err(error) => write_error(err, &expression, &error)?,
+        }
+        if !evaluator.is_pending() {
+            expression.clear();
+        }
+    }
+
+    // The input ended part way through an expression.
+    match evaluator.finish() {
+        Ok(Some(value)) => writeln!(out, "{}", value)?,
+        Ok(None) => {},
+        Err(error) => write_error(err, &expression, &error)?,
+    }
+    return Ok(0);
+}
+
+/// Writes `error`, and for a syntax error the line of `input` it is in
+/// with a `^` under where it was found.
+fn write_error(err: &mut dyn Write, input: &str, error: &ExpressionError) -> io::Result<()> {
+    writeln!(err, "error: {}", error)?;
+    let ExpressionError::Parse(parse_error) = error else {
+        return Ok(());
+    };
+    let position = parse_error.position.min(input.len());
+    let line_start = input[..position].rfind('\n').map_or(0, |newline| newline + 1);
+    let line_end = input[position..].find('\n').map_or(input.len(), |newline| position + newline);
+    let column = input[line_start..position].chars().count();
+    writeln!(err, "  {}", &input[line_start..line_end])?;
+    writeln!(err, "  {}^", " ".repeat(column))?;
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs with `args` and `input`, returning stdout, stderr and the exit
+    /// status.
+    fn run_with(args: &[&str], input: &str) -> (String, String, u8) {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        let (mut out, mut err) = (Vec::new(), Vec::new());
+        let code = run(&args, input.as_bytes(), &mut out, &mut err).unwrap();
+        return (String::from_utf8(out).unwrap(), String::from_utf8(err).unwrap(), code);
+    }
+
+    fn ok(text: &str) -> (String, String, u8) {
+        return (format!("{}\n", text), String::new(), 0);
+    }
+
+    #[test]
+    fn test_one_shot() {
+        assert_eq!(run_with(&["1 + 2 * (3 - 1)"], ""), ok("5"));
+        assert_eq!(run_with(&["1", "+", "2.5"], ""), ok("3.5"));
+        assert_eq!(run_with(&["-3 + 1"], ""), ok("-2"));
+        assert_eq!(run_with(&["--rpn", "1 2 +"], ""), ok("3"));
+        assert_eq!(run_with(&["--to-rpn", "1 + 2"], ""), ok("1 2 +"));
+        assert_eq!(run_with(&["--help"], ""), ok(USAGE));
+    }
+
+    #[test]
+    fn test_to_ast() {
+        let tree = "BinaryOp Add\n  Int 1\n  UnaryMinus\n    Call sqrt\n      Var x";
+        assert_eq!(run_with(&["--to-ast", "1 + -sqrt(x)"], ""), ok(tree));
+        assert_eq!(run_with(&["--to-ast", "--format", "tree", "1 + -sqrt(x)"], ""), ok(tree));
+        assert_eq!(
+            run_with(&["--to-ast", "--format", "debug", "-x"], ""),
+            ok("UnaryMinus(Var(\"x\"))"),
+        );
+        assert_eq!(run_with(&["--to-ast", "--format", "json", "1"], "").2, 2);
+        assert_eq!(run_with(&["--to-ast", "1 +"], "").2, 1);
+    }
+
+    #[test]
+    fn test_one_shot_errors() {
+        let (out, err, code) = run_with(&["1 +"], "");
+        assert_eq!((out.as_str(), code), ("", 1));
+        assert_eq!(err, "error: trailing operator at position 2\n  1 +\n    ^\n");
+
+        let (_, err, code) = run_with(&["--rpn", "1 /"], "");
+        assert_eq!((err.as_str(), code), ("error: not enough operands\n", 1));
+        assert_eq!(run_with(&["1 / 0"], "").1, "error: division by zero\n");
+        assert_eq!(run_with(&["--to-rpn", "(1"], "").2, 1);
+
+        assert_eq!(run_with(&["--rpn"], ""), (String::new(), format!("{}\n", USAGE), 2));
+        assert_eq!(run_with(&["--nope", "1"], "").2, 2);
+    }
+
+    #[test]
+    fn test_repl() {
+        let (out, err, code) = run_with(&[], "1 + 2\n\nans * 2\n(1 +\n 2) * 3\n");
+        assert_eq!((out.as_str(), err.as_str(), code), ("3\n6\n9\n", "", 0));
+
+        // It carries on after an error, and points into the right line.
+        let (out, err, code) = run_with(&[], "2 $ 3\n(1 +\n 2 @)\n4\n");
+        assert_eq!(out, "4\n");
+        assert_eq!(
+            err,
+            "error: unexpected character: $ at position 2\n  2 $ 3\n    ^\n\
+             error: unexpected character: @ at position 8\n   2 @)\n     ^\n"
+        );
+        assert_eq!(code, 0);
+
+        // An expression left unfinished at the end of the input.
+        assert_eq!(run_with(&[], "1 +"), (String::new(), "error: trailing operator at position 2\n  1 +\n    ^\n".to_string(), 0));
+        assert_eq!(run_with(&[], "ans \\"), (String::new(), "error: undefined variable: ans\n".to_string(), 0));
+        assert_eq!(run_with(&[], "3 \\\n"), ("3\n".to_string(), String::new(), 0));
+
+        // After a `\` and a comment, the caret is still under what was typed.
+        let (out, err, _) = run_with(&[], "1 + \\ # more\n2 $ 3\n# done\n4 # four\n");
+        assert_eq!(out, "4\n");
+        assert_eq!(err, "error: unexpected character: $ at position 15\n  2 $ 3\n    ^\n");
+    }
+}
@@ -0,0 +1,47 @@
+//! Lazily built statics, for the built-in operator and function tables.
+//! With `std` this is `OnceLock`; without it, a minimal stand-in.
+
+#[cfg(feature = "std")]
+pub(crate) use std::sync::OnceLock as Once;
+
+#[cfg(not(feature = "std"))]
+pub(crate) use self::atomic::Once;
+
+#[cfg(not(feature = "std"))]
+mod atomic {
+    use alloc::boxed::Box;
+    use core::ptr;
+    use core::sync::atomic::{AtomicPtr, Ordering};
+
+    /// A value built on first use and never dropped. Threads that race to
+    /// build it each build one, and all but the first throw theirs away.
+    pub(crate) struct Once<T> {
+        value: AtomicPtr<T>,
+    }
+
+    impl<T: Sync> Once<T> {
+        pub(crate) const fn new() -> Self {
+            Once { value: AtomicPtr::new(ptr::null_mut()) }
+        }
+
+        pub(crate) fn get_or_init(self: &'static Self, init: fn() -> T) -> &'static T {
+            let current = self.value.load(Ordering::Acquire);
+            if !current.is_null() {
+                // SAFETY: set below from `Box::into_raw` and never freed.
+                return unsafe { &*current };
+            }
+
+            let built = Box::into_raw(Box::new(init()));
+            match self.value.compare_exchange(ptr::null_mut(), built, Ordering::AcqRel, Ordering::Acquire) {
+                // SAFETY: `built` was just leaked, and is now never freed.
+                Ok(_) => return unsafe { &*built },
+                Err(winner) => {
+                    // SAFETY: `built` lost the race, so nothing else has seen
+                    // it; `winner` is never freed.
+                    drop(unsafe { Box::from_raw(built) });
+                    return unsafe { &*winner };
+                },
+            }
+        }
+    }
+}
@@ -0,0 +1,148 @@
+use alloc::collections::BTreeMap;
+
+use crate::once::Once;
+use crate::{EvalError, ExpressionError, Token, NOT, UNARY_MINUS};
+
+/// Evaluates a custom binary operator in floating point.
+pub type OperatorFn = fn(f64, f64) -> Result<f64, EvalError>;
+
+/// Which way a chain of the same operator groups: `1 - 2 - 3` is
+/// `(1 - 2) - 3` because `-` is left associative, and `2 ^ 3 ^ 2` is
+/// `2 ^ (3 ^ 2)` because `^` is right associative.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Associativity {
+    Left,
+    Right,
+}
+
+/// How an operator parses, and for custom operators how it evaluates.
+#[derive(Debug, Clone, Copy)]
+pub struct OperatorInfo {
+    pub symbol: char,
+    pub precedence: u8,
+    pub associativity: Associativity,
+    /// Evaluates the operator. `None` means the built-in arithmetic for
+    /// `symbol`, which is exact in integer mode.
+    pub apply: Option<OperatorFn>,
+}
+
+/// The operators expressions can use, keyed by symbol.
+/// `OperatorTable::default()` holds the built-ins:
+///
+/// | operator                         | precedence | associativity |
+/// |----------------------------------|------------|---------------|
+/// | `\|\|`                           | 1          | left          |
+/// | `&&`                             | 2          | left          |
+/// | `\|`                             | 3          | left          |
+/// | `xor`                            | 4          | left          |
+/// | `&`                              | 5          | left          |
+/// | `<` `>` `<=` `>=` `==` `!=`      | 6          | left          |
+/// | `<<` `>>`                        | 7          | left          |
+/// | `+` `-`                          | 8          | left          |
+/// | `*` `/` `%`                      | 9          | left          |
+/// | unary `-` `!`                    | 10         | right         |
+/// | `^`                              | 11         | right         |
+///
+/// Unary minus binds tighter than `*` but looser than `^`, so `-2^2` is
+/// `-(2^2)`. Operators longer than one character are keyed by a stand-in:
+/// `≤`, `≥`, `=` and `≠` for the comparisons, `⊕` for `xor`, `«` and `»`
+/// for the shifts and `∧` and `∨` for `&&` and `||`.
+#[derive(Debug, Clone)]
+pub struct OperatorTable {
+    operators: BTreeMap<char, OperatorInfo>,
+}
+
+impl OperatorTable {
+    /// Functions bind tighter than any operator.
+    pub const FUNCTION_PRECEDENCE: u8 = u8::MAX;
+
+    /// Creates an empty table, without the built-ins.
+    pub fn new() -> Self {
+        OperatorTable {
+            operators: BTreeMap::new(),
+        }
+    }
+
+    /// Adds an operator, replacing any operator with the same symbol.
+    pub fn insert(self: &mut Self, info: OperatorInfo) {
+        self.operators.insert(info.symbol, info);
+    }
+
+    /// Adds an operator that uses the built-in arithmetic for `symbol`,
+    /// or changes how an existing one parses. Evaluating an operator with
+    /// no built-in arithmetic fails; use `insert` to give it an `apply`.
+    pub fn register(self: &mut Self, symbol: char, precedence: u8, associativity: Associativity) {
+        self.insert(OperatorInfo { symbol, precedence, associativity, apply: None });
+    }
+
+    pub fn get(self: &Self, symbol: char) -> Option<&OperatorInfo> {
+        self.operators.get(&symbol)
+    }
+
+    /// Get precedence score of the operator
+    pub fn get_precedence(self: &Self, op: char) -> Result<u8, ExpressionError> {
+        match self.get(op) {
+            Some(info) => Ok(info.precedence),
+            None => Err(ExpressionError::UnexpectedToken(op)),
+        }
+    }
+
+    pub fn get_associativity(self: &Self, op: char) -> Result<Associativity, ExpressionError> {
+        match self.get(op) {
+            Some(info) => Ok(info.associativity),
+            None => Err(ExpressionError::UnexpectedToken(op)),
+        }
+    }
+
+    /// Checks that `symbol` can be a binary operator: it mustn't be read
+    /// as part of something else, such as a number, a name or a call, and
+    /// mustn't be one of the prefix operators `~` and `!`. Built-in binary
+    /// operators such as `+` can be replaced.
+    pub(crate) fn check_symbol(symbol: char) -> Result<(), ExpressionError> {
+        let taken = symbol.is_alphanumeric()
+            || symbol.is_whitespace()
+            || matches!(symbol, '_' | '(' | ')' | '.' | ',' | '×' | '÷' | '−' | UNARY_MINUS | NOT);
+        if taken {
+            return Err(ExpressionError::InvalidOperator(symbol));
+        }
+        return Ok(());
+    }
+
+    /// Whether `c` is an operator only this table knows about, rather than
+    /// one the tokenizer always recognizes.
+    pub(crate) fn is_custom(self: &Self, c: char) -> bool {
+        return matches!(Token::from_operator_symbol(c), Token::Operator(_)) && self.operators.contains_key(&c);
+    }
+
+}
+
+impl Default for OperatorTable {
+    fn default() -> Self {
+        let mut table = OperatorTable::new();
+        table.register('∨', 1, Associativity::Left);
+        table.register('∧', 2, Associativity::Left);
+        table.register('|', 3, Associativity::Left);
+        table.register('⊕', 4, Associativity::Left);
+        table.register('&', 5, Associativity::Left);
+        for comparison in ['<', '>', '≤', '≥', '=', '≠'] {
+            table.register(comparison, 6, Associativity::Left);
+        }
+        table.register('«', 7, Associativity::Left);
+        table.register('»', 7, Associativity::Left);
+        table.register('+', 8, Associativity::Left);
+        table.register('-', 8, Associativity::Left);
+        table.register('*', 9, Associativity::Left);
+        table.register('/', 9, Associativity::Left);
+        table.register('%', 9, Associativity::Left);
+        table.register(UNARY_MINUS, 10, Associativity::Right);
+        table.register(NOT, 10, Associativity::Right);
+        table.register('^', 11, Associativity::Right);
+        return table;
+    }
+}
+
+/// The built-in table, built once for the functions that don't take one.
+pub(crate) fn default_operators() -> &'static OperatorTable {
+    static DEFAULT: Once<OperatorTable> = Once::new();
+    return DEFAULT.get_or_init(OperatorTable::default);
+}
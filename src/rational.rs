@@ -0,0 +1,178 @@
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use core::fmt;
+
+use crate::{compare, integer_result, ExpressionError, Function, Numeric, OperatorFn, Token};
+
+/// An exact fraction, the result of `evaluate_exact`. It is always in
+/// lowest terms with a positive denominator, so `2/-4` is `-1/2` and equal
+/// fractions are `==`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Rational {
+    num: i64,
+    den: i64,
+}
+
+impl Rational {
+    /// `num / den` in lowest terms. A zero `den` is `DivisionByZero`.
+    pub fn new(num: i64, den: i64) -> Result<Self, ExpressionError> {
+        return Rational::reduce(num as i128, den as i128);
+    }
+
+    pub fn numerator(self: &Self) -> i64 {
+        return self.num;
+    }
+
+    /// The denominator, which is always positive.
+    pub fn denominator(self: &Self) -> i64 {
+        return self.den;
+    }
+
+    pub fn is_integer(self: &Self) -> bool {
+        return self.den == 1;
+    }
+
+    /// The nearest float to the fraction.
+    pub fn as_f64(self: &Self) -> f64 {
+        return self.num as f64 / self.den as f64;
+    }
+
+    /// Products of two `i64`s fit in an `i128`, so arithmetic works there
+    /// and only overflows if the result in lowest terms doesn't fit back.
+    fn reduce(num: i128, den: i128) -> Result<Self, ExpressionError> {
+        if den == 0 {
+            return Err(ExpressionError::DivisionByZero);
+        }
+        let divisor = gcd(num, den) * den.signum();
+        let (Ok(num), Ok(den)) = (i64::try_from(num / divisor), i64::try_from(den / divisor)) else {
+            return Err(ExpressionError::Overflow);
+        };
+        return Ok(Rational { num, den });
+    }
+
+    /// The fraction rounded towards zero.
+    fn trunc(self: &Self) -> i64 {
+        return self.num / self.den;
+    }
+
+    /// Raises the fraction to a whole power. `n` may be negative, which
+    /// inverts it first.
+    fn pow(self: Self, n: i64) -> Result<Self, ExpressionError> {
+        let (num, den) = if n < 0 { (self.den, self.num) } else { (self.num, self.den) };
+        let Ok(exponent) = u32::try_from(n.unsigned_abs()) else {
+            return Err(ExpressionError::Overflow);
+        };
+        let num = num.checked_pow(exponent).ok_or(ExpressionError::Overflow)?;
+        let den = den.checked_pow(exponent).ok_or(ExpressionError::Overflow)?;
+        return Rational::new(num, den);
+    }
+}
+
+fn gcd(mut a: i128, mut b: i128) -> i128 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    return a.abs();
+}
+
+impl From<i64> for Rational {
+    fn from(n: i64) -> Self {
+        return Rational { num: n, den: 1 };
+    }
+}
+
+/// Prints `1/2`, or just the numerator for an integer.
+impl fmt::Display for Rational {
+    fn fmt(self: &Self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_integer() {
+            return write!(f, "{}", self.num);
+        }
+        write!(f, "{}/{}", self.num, self.den)
+    }
+}
+
+impl Ord for Rational {
+    fn cmp(self: &Self, other: &Self) -> Ordering {
+        // The denominators are positive, so cross-multiplying keeps the order.
+        return (self.num as i128 * other.den as i128).cmp(&(other.num as i128 * self.den as i128));
+    }
+}
+
+impl PartialOrd for Rational {
+    fn partial_cmp(self: &Self, other: &Self) -> Option<Ordering> {
+        return Some(self.cmp(other));
+    }
+}
+
+impl Numeric for Rational {
+    fn from_integer(n: i64) -> Result<Self, ExpressionError> {
+        Ok(Rational::from(n))
+    }
+
+    /// Float literals are rarely exact, so like integer mode this mode
+    /// has no `.`; write `1/10` instead of `0.1`.
+    fn from_float(f: f64) -> Result<Self, ExpressionError> {
+        Err(ExpressionError::FloatInIntegerMode(f))
+    }
+
+    fn negate(self: Self) -> Result<Self, ExpressionError> {
+        Rational::reduce(-(self.num as i128), self.den as i128)
+    }
+
+    fn not(self: Self) -> Result<Self, ExpressionError> {
+        Ok(Rational::from((self.num == 0) as i64))
+    }
+
+    /// The remainder takes the sign of `left`, as in integer mode, and a
+    /// power has to be a whole number. The bitwise operators only take
+    /// whole numbers.
+    fn apply(op: char, left: Self, right: Self) -> Result<Self, ExpressionError> {
+        let (a, b) = (left.num as i128, left.den as i128);
+        let (c, d) = (right.num as i128, right.den as i128);
+        match op {
+            '+' => return Rational::reduce(a * d + c * b, b * d),
+            '-' => return Rational::reduce(a * d - c * b, b * d),
+            '*' => return Rational::reduce(a * c, b * d),
+            '/' => return Rational::reduce(a * d, b * c),
+            '%' => {
+                if c == 0 {
+                    return Err(ExpressionError::DivisionByZero);
+                }
+                // The quotient fits, as it is no bigger than `left / right`.
+                let quotient = Rational::reduce(a * d, b * c)?.trunc();
+                let multiple = Rational::apply('*', right, Rational::from(quotient))?;
+                return Rational::apply('-', left, multiple);
+            },
+            '^' if right.is_integer() => return left.pow(right.num),
+            '^' => return Err(ExpressionError::NonIntegerResult),
+            '&' | '|' | '⊕' | '«' | '»' if left.is_integer() && right.is_integer() => {
+                return Ok(Rational::from(i64::apply(op, left.num, right.num)?));
+            },
+            '&' | '|' | '⊕' | '«' | '»' => {
+                return Err(ExpressionError::IntegerOnly(Token::from_operator_symbol(op).to_string()));
+            },
+            '∧' => return Ok(Rational::from((a != 0 && c != 0) as i64)),
+            '∨' => return Ok(Rational::from((a != 0 || c != 0) as i64)),
+            _ => match compare(op, left, right) {
+                Some(result) => return Ok(Rational::from(result as i64)),
+                None => return Err(ExpressionError::UnexpectedToken(op)),
+            },
+        }
+    }
+
+    /// Functions work in floating point, so only a whole result is exact
+    /// enough to keep.
+    fn call(_name: &str, function: Function, args: &[Self]) -> Result<Self, ExpressionError> {
+        let args: Vec<f64> = args.iter().map(Rational::as_f64).collect();
+        return Ok(Rational::from(integer_result(function.call(&args))?));
+    }
+
+    fn apply_custom(_op: char, apply: OperatorFn, left: Self, right: Self) -> Result<Self, ExpressionError> {
+        return Ok(Rational::from(integer_result(apply(left.as_f64(), right.as_f64())?)?));
+    }
+
+    fn as_number(self: &Self) -> Option<f64> {
+        return Some(self.as_f64());
+    }
+}
@@ -0,0 +1,198 @@
+//! A reference evaluator, as plain as one can be, for checking the others
+//! and passes that rewrite `Expr`s against.
+
+use alloc::vec::Vec;
+
+use crate::environment::{self, Environment};
+use crate::functions::default_functions;
+use crate::{call_function, EvalError, Expr, ExpressionError, Numeric, Value};
+
+/// Evaluates `expr` by the most direct recursion over the tree there is,
+/// with no folding, caching or reordering, so what it gives is what `expr`
+/// means. Operands are evaluated left to right and the first error wins.
+/// Variables from `vars` are floats, and functions are the built-ins.
+///
+/// The other evaluators agree with it: `evaluate` exactly, and those that
+/// work in `f64`, such as `Expr::eval` and `CompiledExpr::eval`, wherever
+/// it doesn't find a `TypeMismatch`. To check a pass of your own, evaluate
+/// trees before and after it:
+///
+/// ```
+/// use std::collections::HashMap;
+///
+/// use expresso::{parse, reference};
+///
+/// let vars = HashMap::from([("x".to_string(), 2.0)]);
+/// let expr = parse("x * 3 + 1").unwrap();
+/// let folded = expr.fold_constants();
+/// assert_eq!(reference::eval(&folded, &vars), reference::eval(&expr, &vars));
+/// ```
+///
+/// It recurses once per level, so a tree deeper than `parse` allows, such
+/// as one built with `ExprBuilder`, can run it out of stack.
+pub fn eval(expr: &Expr, vars: &dyn Environment) -> Result<Value, EvalError> {
+    match expr {
+        Expr::Int(n) => return Ok(Value::Int(*n)),
+        Expr::Num(n) => return Ok(Value::Float(*n)),
+        Expr::Bool(b) => return Ok(Value::Bool(*b)),
+        Expr::Var(name) => match environment::lookup(vars, name) {
+            Some(value) => return Ok(Value::Float(value)),
+            None => return Err(ExpressionError::UndefinedVariable(name.clone())),
+        },
+        Expr::BinOp { op, left, right } => {
+            let left = eval(left, vars)?;
+            let right = eval(right, vars)?;
+            return Value::apply(op.symbol(), left, right);
+        },
+        Expr::UnaryMinus(operand) => return eval(operand, vars)?.negate(),
+        Expr::Not(operand) => return eval(operand, vars)?.not(),
+        Expr::Call { name, args } => {
+            let mut values = Vec::new();
+            for arg in args {
+                values.push(eval(arg, vars)?);
+            }
+            return call_function(default_functions(), name, &values);
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::boxed::Box;
+    use alloc::format;
+    use alloc::string::{String, ToString};
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::{evaluate_value_with_env, parse, BinOpKind, Evaluator};
+
+    /// Random expressions, the same ones every run. Literals are floats
+    /// only with `floats`, and there are variables only with `vars`. With
+    /// `floats` comparisons and logic only go at the top, as arithmetic on
+    /// booleans is a `TypeMismatch` that `f64` can't be checked against.
+    fn corpus(floats: bool, vars: bool) -> Vec<String> {
+        // A small linear congruential generator, for reproducible trees.
+        let mut state: u64 = 0x9E3779B97F4A7C15;
+        let mut next = move |n: usize| {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            return (state >> 33) as usize % n;
+        };
+
+        fn tree(next: &mut dyn FnMut(usize) -> usize, depth: usize, floats: bool, vars: bool) -> Expr {
+            use BinOpKind::*;
+
+            let ops: &[BinOpKind] = match floats {
+                true => &[Add, Sub, Mul, Div, Rem, Pow],
+                false => &[Add, Sub, Mul, Div, Rem, Pow, Lt, Le, Eq, Ne, And, Or, BitAnd],
+            };
+            let literals = [0.0, 0.5, 1.5, 2.0, 3.25, -4.0];
+            if depth == 0 || next(4) == 0 {
+                return match next(if vars { 4 } else { 3 }) {
+                    0 if !floats => Expr::Int(next(12) as i64 - 3),
+                    1 if !floats && next(8) == 0 => Expr::Bool(next(2) == 0),
+                    3 => Expr::Var(["x", "y", "pi"][next(3)].to_string()),
+                    _ => Expr::Num(literals[next(literals.len())]),
+                };
+            }
+            let (kind, op) = (next(10), ops[next(ops.len())]);
+            let (name, arity) = [("sqrt", 1), ("abs", 1), ("floor", 1), ("max", 2), ("min", 2)][next(5)];
+            let mut operand = || Box::new(tree(next, depth - 1, floats, vars));
+            return match kind {
+                0 => Expr::UnaryMinus(operand()),
+                1 if !floats => Expr::Not(operand()),
+                2 => Expr::Call { name: name.to_string(), args: (0..arity).map(|_| *operand()).collect() },
+                _ => Expr::BinOp { op, left: operand(), right: operand() },
+            };
+        }
+
+        let mut expressions = Vec::new();
+        for i in 0..400 {
+            let mut expr = tree(&mut next, 4, floats, vars);
+            if floats && i % 3 == 0 {
+                let op = [BinOpKind::Lt, BinOpKind::Ge, BinOpKind::Eq, BinOpKind::Ne][next(4)];
+                expr = Expr::BinOp { op, left: Box::new(expr), right: Box::new(tree(&mut next, 2, floats, vars)) };
+            }
+            expressions.push(expr.to_string());
+        }
+        return expressions;
+    }
+
+    fn variables() -> HashMap<String, f64> {
+        return HashMap::from([("x".to_string(), 2.5), ("y".to_string(), -1.0)]);
+    }
+
+    /// Whether an `f64` result agrees with the reference's. Where the
+    /// reference finds a `TypeMismatch`, as in `(1 < 2) + 1`, `f64` has no
+    /// types to mismatch, so anything does.
+    fn agrees(reference: &Result<Value, EvalError>, result: &Result<f64, EvalError>) -> bool {
+        match reference {
+            Ok(value) => return *result == Ok(value.as_f64()),
+            Err(ExpressionError::TypeMismatch { .. }) => return true,
+            Err(error) => return result.as_ref().err() == Some(error),
+        }
+    }
+
+    /// The inputs of `corpus` that the `results` of evaluating them some
+    /// other way disagree with the reference on, with what each gave.
+    fn disagreements(corpus: &[String], results: Vec<Result<f64, EvalError>>) -> Vec<String> {
+        let vars = variables();
+        let mut found = Vec::new();
+        for (input, result) in corpus.iter().zip(results) {
+            let reference = eval(&parse(input).unwrap(), &vars);
+            if !agrees(&reference, &result) {
+                found.push(format!("{}: {:?}, not {:?}", input, result, reference));
+            }
+        }
+        return found;
+    }
+
+    #[test]
+    fn test_evaluate_agrees_exactly() {
+        let vars = variables();
+        for input in corpus(false, true) {
+            let reference = eval(&parse(&input).unwrap(), &vars);
+            assert_eq!(evaluate_value_with_env(&input, &vars), reference, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_f64_paths_agree() {
+        let floats = corpus(true, true);
+        let vars = variables();
+        let evaluator = Evaluator::default();
+
+        let tree = floats.iter().map(|input| parse(input)?.eval_with_vars(&vars)).collect();
+        assert_eq!(disagreements(&floats, tree), Vec::<String>::new());
+        let compiled = floats.iter().map(|input| evaluator.compile(input)?.eval(&vars)).collect();
+        assert_eq!(disagreements(&floats, compiled), Vec::<String>::new());
+
+        // A batch has no variables.
+        let closed = corpus(true, false);
+        let batch = evaluator.evaluate_batch(closed.iter().map(String::as_str));
+        assert_eq!(disagreements(&closed, batch), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_catches_a_wrong_pass() {
+        // Turns `a - b` into `b - a` at the top, as a pass that wrongly
+        // thought subtraction commutes might.
+        let wrong = |expr: &Expr| match expr {
+            Expr::BinOp { op: BinOpKind::Sub, left, right } => {
+                Expr::BinOp { op: BinOpKind::Sub, left: right.clone(), right: left.clone() }
+            },
+            _ => expr.clone(),
+        };
+        let vars = variables();
+        let floats = corpus(true, true);
+
+        let after = |pass: &dyn Fn(&Expr) -> Expr| -> Vec<Result<f64, EvalError>> {
+            let evaluated = floats.iter().map(|input| eval(&pass(&parse(input)?), &vars).map(|value| value.as_f64()));
+            return evaluated.collect();
+        };
+
+        let caught = disagreements(&floats, after(&wrong));
+        assert!(!caught.is_empty());
+        assert!(caught.iter().all(|disagreement| disagreement.contains(" - ")));
+        assert_eq!(disagreements(&floats, after(&Expr::clone)), Vec::<String>::new());
+    }
+}
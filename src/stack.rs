@@ -0,0 +1,191 @@
+//! A general purpose last-in, first-out stack.
+
+use alloc::vec::{self, Vec};
+use core::fmt;
+use core::slice;
+
+/// A last-in, first-out stack, as used by the evaluators for operands and
+/// operators. Popping an empty stack gives `None` rather than panicking.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stack<T> {
+    elements: Vec<T>
+}
+
+impl<T> Stack<T> {
+    pub fn new() -> Self {
+        Stack {
+            elements: Vec::new(),
+        }
+    }
+
+    /// Creates an empty stack with room for `capacity` elements.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Stack {
+            elements: Vec::with_capacity(capacity),
+        }
+    }
+
+    /// Makes room for at least `additional` more elements.
+    pub fn reserve(self: &mut Self, additional: usize) {
+        self.elements.reserve(additional);
+    }
+
+    pub fn len(self: &Self) -> usize {
+        self.elements.len()
+    }
+
+    pub fn is_empty(self: &Self) -> bool {
+        self.elements.is_empty()
+    }
+
+    pub fn push(self: &mut Self, element: T) {
+        self.elements.push(element);
+    }
+
+    /// Removes the top element, if there is one.
+    pub fn pop(self: &mut Self) -> Option<T> {
+        self.elements.pop()
+    }
+
+    #[deprecated(note = "`pop` returns an `Option` now")]
+    pub fn try_pop(self: &mut Self) -> Option<T> {
+        self.pop()
+    }
+
+    pub fn peek(self: &Self) -> Option<&T> {
+        self.elements.last()
+    }
+
+    pub fn peek_mut(self: &mut Self) -> Option<&mut T> {
+        self.elements.last_mut()
+    }
+
+    pub fn clear(self: &mut Self) {
+        self.elements.clear();
+    }
+
+    /// Iterates from the bottom of the stack to the top.
+    pub fn iter(self: &Self) -> slice::Iter<'_, T> {
+        self.elements.iter()
+    }
+}
+
+impl<T> Default for Stack<T> {
+    fn default() -> Self {
+        Stack::new()
+    }
+}
+
+/// The last element of the vector becomes the top of the stack.
+impl<T> From<Vec<T>> for Stack<T> {
+    fn from(elements: Vec<T>) -> Self {
+        Stack { elements }
+    }
+}
+
+/// The elements from bottom to top.
+impl<T> From<Stack<T>> for Vec<T> {
+    fn from(stack: Stack<T>) -> Self {
+        stack.elements
+    }
+}
+
+/// Pushes the elements in order, so the last one ends up on top.
+impl<T> FromIterator<T> for Stack<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Stack {
+            elements: iter.into_iter().collect(),
+        }
+    }
+}
+
+/// Yields the elements from bottom to top.
+impl<T> IntoIterator for Stack<T> {
+    type Item = T;
+    type IntoIter = vec::IntoIter<T>;
+
+    fn into_iter(self: Self) -> Self::IntoIter {
+        self.elements.into_iter()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a Stack<T> {
+    type Item = &'a T;
+    type IntoIter = slice::Iter<'a, T>;
+
+    fn into_iter(self: Self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Prints the elements from bottom to top, e.g. `[1, 2, 3]` after
+/// pushing 1, 2 and 3.
+impl<T: fmt::Debug> fmt::Display for Stack<T> {
+    fn fmt(self: &Self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.elements)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::{String, ToString};
+    use alloc::vec;
+
+    use super::*;
+
+    #[test]
+    fn test_push_pop_order() {
+        let mut stack = Stack::new();
+        assert!(stack.is_empty());
+        stack.push(1);
+        stack.push(2);
+        stack.push(3);
+        assert_eq!(stack.len(), 3);
+        assert_eq!(stack.peek(), Some(&3));
+
+        *stack.peek_mut().unwrap() = 30;
+        assert_eq!(stack.pop(), Some(30));
+        assert_eq!(stack.pop(), Some(2));
+        assert_eq!(stack.pop(), Some(1));
+        assert_eq!(stack.pop(), None);
+    }
+
+    #[test]
+    fn test_empty_pop() {
+        let mut stack: Stack<String> = Stack::with_capacity(4);
+        assert_eq!(stack.pop(), None);
+        assert_eq!(stack.peek(), None);
+        stack.push("a".to_string());
+        stack.clear();
+        assert!(stack.is_empty());
+        assert_eq!(stack.pop(), None);
+    }
+
+    #[test]
+    fn test_conversions_and_display() {
+        let mut stack: Stack<i32> = Stack::from(vec![1, 2, 3]);
+        assert_eq!(stack.iter().copied().collect::<Vec<i32>>(), vec![1, 2, 3]);
+        assert_eq!(stack.to_string(), "[1, 2, 3]");
+        assert_eq!(stack.clone(), stack);
+        assert_eq!(stack.pop(), Some(3));
+        assert_eq!(Vec::from(stack.clone()), vec![1, 2]);
+
+        stack.clear();
+        assert_eq!(stack, Stack::default());
+    }
+
+    #[test]
+    fn test_iteration_order() {
+        let mut stack: Stack<i32> = (1..=3).collect();
+        assert_eq!(stack.peek(), Some(&3));
+
+        let mut seen = Vec::new();
+        for n in &stack {
+            seen.push(*n);
+        }
+        assert_eq!(seen, vec![1, 2, 3]);
+
+        stack.push(4);
+        assert_eq!(stack.into_iter().rev().collect::<Vec<i32>>(), vec![4, 3, 2, 1]);
+    }
+}
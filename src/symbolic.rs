@@ -0,0 +1,422 @@
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+
+#[cfg(not(any(feature = "std", test)))]
+#[allow(unused_imports)]
+use crate::float::Float;
+
+use crate::functions::default_functions;
+use crate::{call_function, parse, BinOpKind, Expr, ExpressionError, Numeric};
+
+/// Parses `input`, simplifies it with `Expr::simplify` and prints it back,
+/// so `"2 * 3 + x * (4 - 4)"` becomes `"6"`.
+pub fn simplify(input: &str) -> Result<String, ExpressionError> {
+    return Ok(parse(input)?.simplify().to_string());
+}
+
+impl Expr {
+    /// The derivative with respect to `var`, by the usual rules: sum,
+    /// product, quotient, power and chain rule for the built-in functions.
+    /// Every other variable is a constant. The result isn't simplified, so
+    /// `x^2` gives `2 * x ^ (2 - 1) * 1`; see `simplify`.
+    ///
+    /// Comparisons, `!`, `%`, `floor` and `ceil` are differentiated piecewise,
+    /// ignoring the points where they jump. Functions that aren't built in
+    /// are an `UnknownFunction` error.
+    pub fn diff(self: &Self, var: &str) -> Result<Expr, ExpressionError> {
+        match self {
+            Expr::Int(_) | Expr::Num(_) | Expr::Bool(_) | Expr::Not(_) => return Ok(num(0.0)),
+            Expr::Var(name) if name == var => return Ok(num(1.0)),
+            Expr::Var(_) => return Ok(num(0.0)),
+            Expr::UnaryMinus(operand) => return Ok(neg(operand.diff(var)?)),
+            Expr::BinOp { op, left, right } => return diff_binop(*op, left, right, var),
+            Expr::Call { name, args } => return diff_call(name, args, var),
+        }
+    }
+
+    /// Folds constants and removes operations that do nothing, such as
+    /// `0 + e`, `e * 1` and `e ^ 1`. `e * 0` and `e ^ 0` drop `e`.
+    /// Operations on constants that fail, like `1 / 0`, are left as they
+    /// are, and so is anything they would be dropped from.
+    pub fn simplify(self: &Self) -> Expr {
+        match self {
+            Expr::Int(_) | Expr::Num(_) | Expr::Bool(_) | Expr::Var(_) => return self.clone(),
+            Expr::UnaryMinus(operand) => match operand.simplify() {
+                Expr::Num(n) => return Expr::Num(-n),
+                Expr::Int(n) => return negate_int(n),
+                Expr::UnaryMinus(ref mut inner) => return inner.take(),
+                operand => return neg(operand),
+            },
+            Expr::Not(operand) => return not(operand.simplify()),
+            Expr::BinOp { op, left, right } => return simplify_binop(*op, left.simplify(), right.simplify()),
+            Expr::Call { name, args } => {
+                let args: Vec<Expr> = args.iter().map(|arg| arg.simplify()).collect();
+                return fold_call(name, &args).unwrap_or_else(|| Expr::Call { name: name.clone(), args });
+            },
+        }
+    }
+
+    /// Evaluates every operation and built-in function call whose operands
+    /// are all constants, bottom up, so `2 * 3 + x` becomes `6 + x`. Unlike
+    /// `simplify`, that is all it does. Operations on constants that fail,
+    /// like `1 / 0`, are left as they are.
+    pub fn fold_constants(self: &Self) -> Expr {
+        match self {
+            Expr::Int(_) | Expr::Num(_) | Expr::Bool(_) | Expr::Var(_) => return self.clone(),
+            Expr::UnaryMinus(operand) => match operand.fold_constants() {
+                Expr::Num(n) => return Expr::Num(-n),
+                Expr::Int(n) => return negate_int(n),
+                operand => return neg(operand),
+            },
+            Expr::Not(operand) => return not(operand.fold_constants()),
+            Expr::BinOp { op, left, right } => {
+                let (left, right) = (left.fold_constants(), right.fold_constants());
+                return fold_binop(*op, &left, &right).unwrap_or_else(|| binop(*op, left, right));
+            },
+            Expr::Call { name, args } => {
+                let args: Vec<Expr> = args.iter().map(|arg| arg.fold_constants()).collect();
+                return fold_call(name, &args).unwrap_or_else(|| Expr::Call { name: name.clone(), args });
+            },
+        }
+    }
+
+    /// Whether `var` appears anywhere in the expression.
+    pub fn contains_var(self: &Self, var: &str) -> bool {
+        match self {
+            Expr::Int(_) | Expr::Num(_) | Expr::Bool(_) => return false,
+            Expr::Var(name) => return name == var,
+            Expr::UnaryMinus(operand) | Expr::Not(operand) => return operand.contains_var(var),
+            Expr::BinOp { left, right, .. } => return left.contains_var(var) || right.contains_var(var),
+            Expr::Call { args, .. } => return args.iter().any(|arg| arg.contains_var(var)),
+        }
+    }
+
+    fn as_num(self: &Self) -> Option<f64> {
+        match self {
+            Expr::Num(n) => return Some(*n),
+            Expr::Int(n) => return Some(*n as f64),
+            Expr::Bool(b) => return Some(*b as u8 as f64),
+            _ => return None,
+        }
+    }
+}
+
+/// The constant `n`, as an `Int` if it's integral, so it prints as `6`
+/// rather than `6.0`. `eval` reads the two the same.
+fn num(n: f64) -> Expr {
+    if n.fract() == 0.0 && n.abs() < 9007199254740992.0 {
+        return Expr::Int(n as i64);
+    }
+    return Expr::Num(n);
+}
+
+fn neg(operand: Expr) -> Expr {
+    return Expr::UnaryMinus(Box::new(operand));
+}
+
+/// `-n`, as a float if it doesn't fit an `i64`.
+fn negate_int(n: i64) -> Expr {
+    return n.checked_neg().map_or(num(-(n as f64)), Expr::Int);
+}
+
+/// `!operand`, or its value if `operand` is a constant.
+fn not(operand: Expr) -> Expr {
+    match operand.as_num() {
+        Some(n) => return Expr::Bool(n == 0.0),
+        None => return Expr::Not(Box::new(operand)),
+    }
+}
+
+fn binop(op: BinOpKind, left: Expr, right: Expr) -> Expr {
+    return Expr::BinOp { op, left: Box::new(left), right: Box::new(right) };
+}
+
+fn call(name: &str, args: Vec<Expr>) -> Expr {
+    return Expr::Call { name: name.to_string(), args };
+}
+
+fn diff_binop(op: BinOpKind, u: &Expr, v: &Expr, var: &str) -> Result<Expr, ExpressionError> {
+    use BinOpKind::*;
+
+    let du = u.diff(var)?;
+    let dv = v.diff(var)?;
+    let (u, v) = (u.clone(), v.clone());
+
+    match op {
+        Add | Sub => return Ok(binop(op, du, dv)),
+        Mul => return Ok(binop(Add, binop(Mul, du, v), binop(Mul, u, dv))),
+        Div => {
+            let numerator = binop(Sub, binop(Mul, du, v.clone()), binop(Mul, u, dv));
+            return Ok(binop(Div, numerator, binop(Pow, v, num(2.0))));
+        },
+        // u % v is u - v * floor(u / v), and floor is flat where it's
+        // differentiable.
+        Rem => return Ok(binop(Sub, du, binop(Mul, call("floor", vec![binop(Div, u, v.clone())]), dv))),
+        Pow if !v.contains_var(var) => {
+            let power = binop(Pow, u, binop(Sub, v.clone(), num(1.0)));
+            return Ok(binop(Mul, binop(Mul, v, power), du));
+        },
+        Pow if !u.contains_var(var) => {
+            let ln_u = call("ln", vec![u.clone()]);
+            return Ok(binop(Mul, binop(Mul, binop(Pow, u, v), ln_u), dv));
+        },
+        // d(u^v) = u^v * (v' * ln(u) + v * u' / u)
+        Pow => {
+            let ln_u = call("ln", vec![u.clone()]);
+            let inner = binop(Add, binop(Mul, dv, ln_u), binop(Div, binop(Mul, v.clone(), du), u.clone()));
+            return Ok(binop(Mul, binop(Pow, u, v), inner));
+        },
+        Lt | Gt | Le | Ge | Eq | Ne | And | Or => return Ok(num(0.0)),
+        BitAnd | BitOr | BitXor | Shl | Shr => return Err(ExpressionError::IntegerOnly(op.to_string())),
+    }
+}
+
+fn diff_call(name: &str, args: &[Expr], var: &str) -> Result<Expr, ExpressionError> {
+    use BinOpKind::*;
+
+    let derivatives = args.iter().map(|arg| arg.diff(var)).collect::<Result<Vec<Expr>, ExpressionError>>()?;
+
+    match (name, args, derivatives.as_slice()) {
+        ("sin", [u], [du]) => return Ok(binop(Mul, call("cos", vec![u.clone()]), du.clone())),
+        ("cos", [u], [du]) => return Ok(binop(Mul, neg(call("sin", vec![u.clone()])), du.clone())),
+        ("tan", [u], [du]) => return Ok(binop(Div, du.clone(), binop(Pow, call("cos", vec![u.clone()]), num(2.0)))),
+        ("sqrt", [u], [du]) => return Ok(binop(Div, du.clone(), binop(Mul, num(2.0), call("sqrt", vec![u.clone()])))),
+        ("abs", [u], [du]) => return Ok(binop(Div, binop(Mul, u.clone(), du.clone()), call("abs", vec![u.clone()]))),
+        ("ln", [u], [du]) => return Ok(binop(Div, du.clone(), u.clone())),
+        ("floor" | "ceil", [_], [_]) => return Ok(num(0.0)),
+        ("pow", [u, v], _) => return diff_binop(Pow, u, v, var),
+        // log(b, u) is ln(u) / ln(b).
+        ("log", [b, u], _) => {
+            let quotient = binop(Div, call("ln", vec![u.clone()]), call("ln", vec![b.clone()]));
+            return quotient.diff(var);
+        },
+        ("hypot", [u, v], [du, dv]) => {
+            let numerator = binop(Add, binop(Mul, u.clone(), du.clone()), binop(Mul, v.clone(), dv.clone()));
+            return Ok(binop(Div, numerator, call("hypot", vec![u.clone(), v.clone()])));
+        },
+        // Whichever argument is selected, with ties going to the first.
+        ("max" | "min", [u, v], [du, dv]) => {
+            let first = if name == "max" { Ge } else { Le };
+            let second = if name == "max" { Lt } else { Gt };
+            let pick_u = binop(Mul, binop(first, u.clone(), v.clone()), du.clone());
+            let pick_v = binop(Mul, binop(second, u.clone(), v.clone()), dv.clone());
+            return Ok(binop(Add, pick_u, pick_v));
+        },
+        _ => return Err(ExpressionError::UnknownFunction(name.to_string())),
+    }
+}
+
+/// The value of `left op right` if both are constants and it has one.
+fn fold_binop(op: BinOpKind, left: &Expr, right: &Expr) -> Option<Expr> {
+    let (Some(l), Some(r)) = (left.as_num(), right.as_num()) else { return None; };
+    return f64::apply(op.symbol(), l, r).ok().map(num);
+}
+
+/// The value of calling the built-in `name` if all of `args` are constants
+/// and it has one.
+fn fold_call(name: &str, args: &[Expr]) -> Option<Expr> {
+    let values = args.iter().map(Expr::as_num).collect::<Option<Vec<f64>>>()?;
+    return call_function(default_functions(), name, &values).ok().map(num);
+}
+
+fn simplify_binop(op: BinOpKind, left: Expr, right: Expr) -> Expr {
+    use BinOpKind::*;
+
+    if let Some(folded) = fold_binop(op, &left, &right) {
+        return folded;
+    }
+
+    match (op, left.as_num(), right.as_num()) {
+        (Add, Some(0.0), _) => return right,
+        (Add | Sub, _, Some(0.0)) => return left,
+        (Sub, Some(0.0), _) => return neg(right).simplify(),
+        (Mul, Some(0.0), _) if !fails(&right) => return num(0.0),
+        (Mul, _, Some(0.0)) if !fails(&left) => return num(0.0),
+        (Mul, Some(1.0), _) => return right,
+        (Mul | Div | Pow, _, Some(1.0)) => return left,
+        (Pow, _, Some(0.0)) if !fails(&left) => return num(1.0),
+        _ => return binop(op, left, right),
+    }
+}
+
+/// Whether a simplified expression always fails to evaluate: it divides by
+/// a constant zero, or has an operation on constants that couldn't be
+/// folded.
+fn fails(expr: &Expr) -> bool {
+    match expr {
+        Expr::Int(_) | Expr::Num(_) | Expr::Bool(_) | Expr::Var(_) => return false,
+        Expr::UnaryMinus(operand) | Expr::Not(operand) => return fails(operand),
+        Expr::BinOp { op: BinOpKind::Div | BinOpKind::Rem, right, .. } if right.as_num() == Some(0.0) => return true,
+        Expr::BinOp { left, right, .. } => {
+            return (left.as_num().is_some() && right.as_num().is_some()) || fails(left) || fails(right);
+        },
+        Expr::Call { args, .. } => {
+            return args.iter().all(|arg| arg.as_num().is_some()) || args.iter().any(fails);
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn derivative(input: &str) -> String {
+        return parse(input).unwrap().diff("x").unwrap().simplify().to_string();
+    }
+
+    /// Checks `diff` against a central difference at `x`.
+    fn assert_slope(input: &str, x: f64) {
+        let expr = parse(input).unwrap();
+        let at = |x: f64| expr.eval_with_vars(&HashMap::from([("x".to_string(), x)])).unwrap();
+        let h = 1e-6;
+        let numeric = (at(x + h) - at(x - h)) / (2.0 * h);
+
+        let derivative = expr.diff("x").unwrap();
+        let symbolic = derivative.eval_with_vars(&HashMap::from([("x".to_string(), x)])).unwrap();
+        assert!((numeric - symbolic).abs() < 1e-4, "{}: {} vs {} at {}", input, numeric, symbolic, x);
+    }
+
+    #[test]
+    fn test_diff_rules() {
+        assert_eq!(derivative("3"), "0");
+        assert_eq!(derivative("y"), "0");
+        assert_eq!(derivative("x"), "1");
+        assert_eq!(derivative("x + y"), "1");
+        assert_eq!(derivative("x ^ 2"), "2 * x");
+        assert_eq!(derivative("x ^ 3 - 4 * x"), "3 * x ^ 2 - 4");
+        assert_eq!(derivative("sin(x)"), "cos(x)");
+        assert_eq!(derivative("sin(2 * x)"), "cos(2 * x) * 2");
+        assert_eq!(derivative("y * x"), "y");
+        assert_eq!(derivative("-x"), "-1");
+
+        // Not simplified.
+        assert_eq!(parse("x ^ 2").unwrap().diff("x").unwrap().to_string(), "2 * x ^ (2 - 1) * 1");
+    }
+
+    #[test]
+    fn test_diff_numerically() {
+        let expressions = [
+            "x * x * x",
+            "(x + 1) / (x - 3)",
+            "2 ^ x",
+            "x ^ x",
+            "sqrt(x) + ln(x) - cos(x)",
+            "tan(x / 2)",
+            "abs(x - 2) * x",
+            "log(2, x)",
+            "hypot(x, 3)",
+            "max(x, 2) + min(x * x, 1)",
+            "pow(x, 3) % 2",
+            "-(x ^ 2) ^ -1",
+        ];
+        for input in expressions {
+            for x in [0.7, 1.3, 2.9] {
+                assert_slope(input, x);
+            }
+        }
+    }
+
+    #[test]
+    fn test_diff_unknown_function() {
+        assert_eq!(
+            parse("f(x)").unwrap().diff("x"),
+            Err(crate::ExpressionError::UnknownFunction("f".to_string())),
+        );
+    }
+
+    #[test]
+    fn test_simplify() {
+        let simplified = |input: &str| parse(input).unwrap().simplify().to_string();
+        assert_eq!(simplified("0 + x"), "x");
+        assert_eq!(simplified("x * 1 - 0"), "x");
+        assert_eq!(simplified("0 - x"), "-x");
+        assert_eq!(simplified("(2 + 3) * x ^ (4 - 3)"), "5 * x");
+        assert_eq!(simplified("y ^ 0 + sqrt(16)"), "5");
+        assert_eq!(simplified("!(1 < 2) || !(x * 1)"), "false || !x");
+        assert_eq!(simplified("!(2 - 2) + true"), "2");
+        assert_eq!(simplified("0 * sin(x)"), "0");
+        assert_eq!(simplified("--x"), "x");
+        assert_eq!(simplified("1 / 0 + x"), "1 / 0 + x");
+    }
+
+    #[test]
+    fn test_fold_constants() {
+        let folded = |input: &str| parse(input).unwrap().fold_constants();
+        assert_eq!(folded("2 * (3 + 4)"), Expr::Int(14));
+        assert_eq!(folded("2 * 0.25 + x").to_string(), "0.5 + x");
+        assert_eq!(folded("2 * 3 + x").to_string(), "6 + x");
+        assert_eq!(folded("-(1 + 2) * y").to_string(), "-3 * y");
+        assert_eq!(folded("max(1, 2 ^ 3) - sqrt(x)").to_string(), "8 - sqrt(x)");
+        assert_eq!(folded("(1 < 2) + x").to_string(), "1 + x");
+
+        // Identities are `simplify`'s job, and failures stay.
+        assert_eq!(folded("x * 1 + 0 * y").to_string(), "x * 1 + 0 * y");
+        assert_eq!(folded("--x").to_string(), "--x");
+        assert_eq!(folded("x / (1 - 1)").to_string(), "x / 0");
+        assert_eq!(folded("sqrt(-1) + f(2 + 2)").to_string(), "sqrt(-1) + f(4)");
+
+        let original = parse("x ^ (2 * 2) + max(3, 4) * -(2 - 7)").unwrap();
+        let once = original.fold_constants();
+        assert_eq!(once.to_string(), "x ^ 4 + 20");
+        assert_eq!(once.fold_constants(), once);
+        assert_eq!(original.to_string(), "x ^ (2 * 2) + max(3, 4) * -(2 - 7)");
+    }
+
+    #[test]
+    fn test_simplify_input() {
+        assert_eq!(simplify("2 * 3 + x * (4 - 4)").unwrap(), "6");
+        assert_eq!(simplify("x + 0").unwrap(), "x");
+        assert_eq!(simplify("1 * x / 1").unwrap(), "x");
+        assert_eq!(simplify("(x - 0) ^ 1").unwrap(), "x");
+        assert_eq!(simplify("x ^ 0 * y").unwrap(), "y");
+
+        // Failures stay where they are.
+        assert_eq!(simplify("x / 0").unwrap(), "x / 0");
+        assert_eq!(simplify("(1 / 0) * 0").unwrap(), "1 / 0 * 0");
+        assert_eq!(simplify("0 * (x % 0)").unwrap(), "0 * (x % 0)");
+        assert_eq!(simplify("sqrt(-1) ^ 0").unwrap(), "sqrt(-1) ^ 0");
+
+        assert_eq!(
+            simplify("1 +"),
+            Err(crate::ExpressionError::Parse(crate::ParseError {
+                kind: crate::ParseErrorKind::TrailingOperator { span: crate::Span { start: 2, end: 3 } },
+                position: 2,
+            })),
+        );
+    }
+
+    #[test]
+    fn test_simplify_preserves_values() {
+        let expressions = [
+            "2 * 3 + x * (4 - 4)",
+            "(x + 0) * (1 * y) - 0",
+            "x ^ 1 + y ^ 0 - (2 ^ 3) / 4",
+            "0 - x * 1 + sqrt(16) * y",
+            "max(x, 2 + 1) / 1 + 0 * y",
+            "-(-(x)) + (3 > 2) * y",
+            "x / (2 - 2) + y",
+            "0 * (y / (1 - 1))",
+        ];
+        let bindings = [(0.0, 1.0), (2.5, -3.0), (-4.0, 0.5), (10.0, 7.0)];
+        for input in expressions {
+            let original = parse(input).unwrap();
+            let simplified = original.simplify();
+            for (x, y) in bindings {
+                let variables = HashMap::from([("x".to_string(), x), ("y".to_string(), y)]);
+                assert_eq!(
+                    simplified.eval_with_vars(&variables),
+                    original.eval_with_vars(&variables),
+                    "{} -> {} at x = {}, y = {}",
+                    input,
+                    simplified,
+                    x,
+                    y,
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,249 @@
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::{Add, Div, Mul, Rem, Sub};
+
+#[cfg(not(any(feature = "std", test)))]
+#[allow(unused_imports)]
+use crate::float::Float;
+
+use crate::{check_float, compare, ExpressionError, Function, Numeric, OperatorFn, Token};
+
+/// The result of `evaluate`, which keeps integers, floats and booleans
+/// apart instead of treating them all as numbers.
+///
+/// Integers stay exact until they meet a float, and the comparisons give
+/// booleans, which only the logical operators and `==` and `!=` accept.
+/// The arithmetic operators follow the same rules, so `Value::Int(7) /
+/// Value::Int(2)` is `Ok(Value::Int(3))`.
+///
+/// `Null` is a missing variable under `MissingVarPolicy::Propagate`.
+/// Anything it touches is null: every operator with a null operand,
+/// comparisons and `&&` and `||` included, so `x == x` is null too, and
+/// every function with a null argument. The exceptions are the functions
+/// for handling it: `coalesce(a, b)` is `a` unless that is null and `b`
+/// otherwise, `is_null(a)` is whether `a` is, and `if(c, a, b)` is only
+/// null if the one of `a` and `b` that `c` picks is, or `c` is.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Value {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Null,
+}
+
+impl Value {
+    /// The name of the value's type, as used in `TypeMismatch` errors.
+    pub fn type_name(self: &Self) -> &'static str {
+        match self {
+            Value::Int(_) => return "int",
+            Value::Float(_) => return "float",
+            Value::Bool(_) => return "bool",
+            Value::Null => return "null",
+        }
+    }
+
+    /// The value as a float. Booleans are 1 and 0, and null is NaN.
+    pub fn as_f64(self: &Self) -> f64 {
+        match self {
+            Value::Int(n) => return *n as f64,
+            Value::Float(f) => return *f,
+            Value::Bool(b) => return *b as u8 as f64,
+            Value::Null => return f64::NAN,
+        }
+    }
+
+    pub fn is_null(self: &Self) -> bool {
+        return *self == Value::Null;
+    }
+
+    /// The value if it is an integer.
+    pub fn as_i64(self: &Self) -> Option<i64> {
+        match self {
+            Value::Int(n) => return Some(*n),
+            _ => return None,
+        }
+    }
+
+    /// The value as a number, or `None` for a boolean or null.
+    fn number(self: &Self) -> Option<f64> {
+        match self {
+            Value::Int(n) => return Some(*n as f64),
+            Value::Float(f) => return Some(*f),
+            Value::Bool(_) | Value::Null => return None,
+        }
+    }
+}
+
+/// Prints floats with a decimal point, so `Float(3.0)` prints as `3.0`
+/// and `Int(3)` as `3`.
+impl fmt::Display for Value {
+    fn fmt(self: &Self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Int(n) => write!(f, "{}", n),
+            Value::Float(x) if x.fract() == 0.0 => write!(f, "{:.1}", x),
+            Value::Float(x) => write!(f, "{}", x),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Null => write!(f, "null"),
+        }
+    }
+}
+
+/// The error for applying `op` to operands it doesn't take.
+fn mismatch(op: &str, operands: &[Value]) -> ExpressionError {
+    return ExpressionError::TypeMismatch {
+        op: op.to_string(),
+        operands: operands.iter().map(|value| value.type_name().to_string()).collect(),
+    };
+}
+
+fn operator_mismatch(op: char, operands: &[Value]) -> ExpressionError {
+    return mismatch(&Token::from_operator_symbol(op).to_string(), operands);
+}
+
+impl Numeric for Value {
+    fn from_integer(n: i64) -> Result<Self, ExpressionError> {
+        Ok(Value::Int(n))
+    }
+
+    fn from_float(f: f64) -> Result<Self, ExpressionError> {
+        Ok(Value::Float(f))
+    }
+
+    fn from_bool(b: bool) -> Result<Self, ExpressionError> {
+        Ok(Value::Bool(b))
+    }
+
+    fn negate(self: Self) -> Result<Self, ExpressionError> {
+        match self {
+            Value::Int(n) => return Ok(Value::Int(n.negate()?)),
+            Value::Float(f) => return Ok(Value::Float(-f)),
+            Value::Bool(_) => return Err(mismatch("-", &[self])),
+            Value::Null => return Ok(Value::Null),
+        }
+    }
+
+    fn not(self: Self) -> Result<Self, ExpressionError> {
+        match self {
+            Value::Bool(b) => return Ok(Value::Bool(!b)),
+            Value::Null => return Ok(Value::Null),
+            _ => return Err(operator_mismatch(crate::NOT, &[self])),
+        }
+    }
+
+    /// Integers only become floats when the other operand is one, so
+    /// `7 / 2` is `3` but `7 / 2.0` is `3.5`.
+    fn apply(op: char, left: Self, right: Self) -> Result<Self, ExpressionError> {
+        match (left, right) {
+            (Value::Null, _) | (_, Value::Null) => return Ok(Value::Null),
+            (Value::Bool(l), Value::Bool(r)) => match op {
+                '∧' => return Ok(Value::Bool(l && r)),
+                '∨' => return Ok(Value::Bool(l || r)),
+                '=' => return Ok(Value::Bool(l == r)),
+                '≠' => return Ok(Value::Bool(l != r)),
+                _ => return Err(operator_mismatch(op, &[left, right])),
+            },
+            _ if matches!(op, '∧' | '∨') => {
+                return Err(operator_mismatch(op, &[left, right]));
+            },
+            (Value::Int(l), Value::Int(r)) => {
+                if let Some(result) = compare(op, l, r) {
+                    return Ok(Value::Bool(result));
+                }
+                return Ok(Value::Int(i64::apply(op, l, r)?));
+            },
+            _ => {
+                let (Some(l), Some(r)) = (left.number(), right.number()) else {
+                    return Err(operator_mismatch(op, &[left, right]));
+                };
+                if let Some(result) = compare(op, l, r) {
+                    return Ok(Value::Bool(result));
+                }
+                return Ok(Value::Float(f64::apply(op, l, r)?));
+            },
+        }
+    }
+
+    /// Functions take numbers and give floats, but for `coalesce`,
+    /// `is_null` and `if`, which take anything. `if` takes a boolean to
+    /// choose by.
+    fn call(name: &str, function: Function, args: &[Self]) -> Result<Self, ExpressionError> {
+        match (name, args) {
+            ("coalesce", [Value::Null, other]) => return Ok(*other),
+            ("coalesce", [value, _]) => return Ok(*value),
+            ("is_null", [value]) => return Ok(Value::Bool(value.is_null())),
+            ("if", [Value::Bool(condition), then, otherwise]) => return Ok(if *condition { *then } else { *otherwise }),
+            ("if", [Value::Null, _, _]) => return Ok(Value::Null),
+            ("if", _) => return Err(mismatch(name, args)),
+            _ if args.iter().any(Value::is_null) => return Ok(Value::Null),
+            _ => {},
+        }
+        let Some(numbers) = args.iter().map(Value::number).collect::<Option<Vec<f64>>>() else {
+            return Err(mismatch(name, args));
+        };
+        return Ok(Value::Float(check_float(function.call(&numbers))?));
+    }
+
+    fn apply_custom(op: char, apply: OperatorFn, left: Self, right: Self) -> Result<Self, ExpressionError> {
+        if left.is_null() || right.is_null() {
+            return Ok(Value::Null);
+        }
+        let (Some(l), Some(r)) = (left.number(), right.number()) else {
+            return Err(operator_mismatch(op, &[left, right]));
+        };
+        return Ok(Value::Float(check_float(apply(l, r)?)?));
+    }
+
+    fn as_number(self: &Self) -> Option<f64> {
+        return self.number();
+    }
+
+    fn as_integer(self: &Self) -> Option<i64> {
+        return self.as_i64();
+    }
+
+    fn from_value(value: Value) -> Result<Self, ExpressionError> {
+        return Ok(value);
+    }
+}
+
+impl Add for Value {
+    type Output = Result<Value, ExpressionError>;
+
+    fn add(self: Self, other: Self) -> Self::Output {
+        return Value::apply('+', self, other);
+    }
+}
+
+impl Sub for Value {
+    type Output = Result<Value, ExpressionError>;
+
+    fn sub(self: Self, other: Self) -> Self::Output {
+        return Value::apply('-', self, other);
+    }
+}
+
+impl Mul for Value {
+    type Output = Result<Value, ExpressionError>;
+
+    fn mul(self: Self, other: Self) -> Self::Output {
+        return Value::apply('*', self, other);
+    }
+}
+
+impl Div for Value {
+    type Output = Result<Value, ExpressionError>;
+
+    fn div(self: Self, other: Self) -> Self::Output {
+        return Value::apply('/', self, other);
+    }
+}
+
+impl Rem for Value {
+    type Output = Result<Value, ExpressionError>;
+
+    fn rem(self: Self, other: Self) -> Self::Output {
+        return Value::apply('%', self, other);
+    }
+}
@@ -0,0 +1,24 @@
+//! JavaScript bindings, built with `wasm-pack build --features wasm`.
+//! Errors become JavaScript strings holding their message.
+
+use alloc::string::{String, ToString};
+
+use wasm_bindgen::prelude::*;
+
+use crate::{infix_to_rpn_f64, sy_evaluate_f64, ExpressionError};
+
+fn to_js(error: ExpressionError) -> JsValue {
+    return JsValue::from_str(&error.to_string());
+}
+
+/// `sy_evaluate_f64` for JavaScript, as `evaluate`.
+#[wasm_bindgen(js_name = evaluate)]
+pub fn js_evaluate(input: &str) -> Result<f64, JsValue> {
+    return sy_evaluate_f64(input.to_string()).map_err(to_js);
+}
+
+/// `infix_to_rpn_f64` for JavaScript, as `infixToRpn`.
+#[wasm_bindgen(js_name = infixToRpn)]
+pub fn js_infix_to_rpn(input: &str) -> Result<String, JsValue> {
+    return infix_to_rpn_f64(input.to_string()).map_err(to_js);
+}